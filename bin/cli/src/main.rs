@@ -1,4 +1,10 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use db::types::{
+    Cursor, Exclusivity, GraphFormat, GraphOptions, Image, ImageId, ListOptions, RuleId, RuleKind,
+    SortBy, TagId, TagNode, TagValue,
+};
+use rusqlite::backup::Progress;
+use std::path::Path;
 
 /// CLI to send commands to the sqlite database
 #[derive(Parser, Debug)]
@@ -23,6 +29,158 @@ enum Commands {
         #[command(subcommand)]
         t: Target,
     },
+    /// Show the history of changes, optionally filtered
+    Log {
+        /// Only show changes to the image with this path
+        #[arg(long)]
+        image: Option<String>,
+        /// Only show changes to the tag with this name
+        #[arg(long)]
+        tag: Option<String>,
+        /// Only show changes from this date on (YYYY-MM-DD [HH:MM:SS], UTC)
+        #[arg(long)]
+        since: Option<String>,
+        /// Only show changes before this date (YYYY-MM-DD [HH:MM:SS], UTC)
+        #[arg(long)]
+        until: Option<String>,
+    },
+    /// Run a saved search, or an image query which can get saved
+    Search {
+        /// Image query, the same as for `query images`
+        #[arg(required_unless_present_any = ["saved", "list"])]
+        query: Option<String>,
+        /// Run the saved search with this name
+        #[arg(long, conflicts_with = "query")]
+        saved: Option<String>,
+        /// Save the query under this name before running it
+        #[arg(long, requires = "query")]
+        save: Option<String>,
+        /// List the saved searches
+        #[arg(long)]
+        list: bool,
+        /// Sort by id, path, usage, added, updated or tagged, saved with the query
+        #[arg(long, default_value = "id")]
+        sort: SortBy,
+        /// Sort in descending order, saved with the query
+        #[arg(long)]
+        desc: bool,
+        /// Maximum number of images to show
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Continue after the cursor printed by a previous search
+        #[arg(long)]
+        after: Option<Cursor>,
+    },
+    /// Merge tags into a target tag, moving their images, parents, children and namespace
+    Merge {
+        /// Name of the tag which stays
+        target: String,
+        /// Names of the tags which get merged into the target
+        #[arg(required = true)]
+        sources: Vec<String>,
+        /// Keep the names of the sources as aliases of the target
+        #[arg(long)]
+        alias: bool,
+    },
+    /// Rename every tag matching a regex, `$1` in the replacement inserts the first group
+    Rename {
+        pattern: String,
+        replacement: String,
+        /// Only rename tags in the namespace with this name
+        #[arg(long)]
+        namespace: Option<String>,
+        /// Merge tags which end up with the name of another tag into it
+        #[arg(long)]
+        merge: bool,
+        /// Only print the renames
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Manage rules which imply or exclude tags
+    Rule {
+        #[command(subcommand)]
+        r: RuleCommand,
+    },
+    /// Allow at most one tag of a namespace per image, printing images which already have more
+    Exclusive {
+        namespace: String,
+        /// `replace` swaps out the old tag, `refuse` keeps it, without a mode the namespace
+        /// stops being exclusive
+        mode: Option<Exclusivity>,
+    },
+    /// Create a smart group holding the images matching a query
+    SmartGroup {
+        name: String,
+        /// Image query, the same as for `query images`
+        query: String,
+        /// Store the members, they only change with `refresh`
+        #[arg(long)]
+        materialized: bool,
+    },
+    /// Store the current members of materialized smart groups
+    Refresh {
+        /// Only refresh the group with this name
+        group: Option<String>,
+    },
+    /// Print the tag hierarchy indented, with the number of images of every tag
+    Tree {
+        /// Only print this tag and its children
+        root: Option<String>,
+    },
+    /// Print the tag hierarchy, edges point from parent to child
+    Graph {
+        /// dot or mermaid
+        #[arg(long, default_value = "dot")]
+        format: GraphFormat,
+        /// Only print this tag and its children
+        #[arg(long)]
+        root: Option<String>,
+        /// Color tags by namespace
+        #[arg(long)]
+        namespaces: bool,
+        /// Draw tags on more images larger
+        #[arg(long)]
+        usage: bool,
+    },
+    /// Copy the database to a file, it can stay in use meanwhile
+    Backup {
+        path: String,
+        /// Treat the path as a directory of snapshots and keep only the newest N
+        #[arg(long)]
+        keep: Option<usize>,
+    },
+    /// Replace the database with a backup
+    Restore { path: String },
+    /// Print everything wrong in the database
+    Doctor {
+        /// Fix what can be fixed, then print what is left
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Make this database and another one hold the same tags and images, then print what
+    /// changed in each
+    Sync {
+        remote: String,
+        /// Only print what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Store the hash of image files on this computer, so sync can match them with images
+    /// at other paths
+    Hash,
+    /// Print every image with its tags, tag values and properties
+    Export {
+        /// tsv: path, tags as `tag` or `tag=value` and properties as `@key=value`, separated
+        /// by tabs. json: one object per line
+        #[arg(long, value_enum, default_value = "tsv")]
+        format: Format,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    Tsv,
+    Json,
 }
 
 #[derive(Subcommand, Debug)]
@@ -30,7 +188,47 @@ enum Query {
     /// Get tags of an image
     Tags { path: String },
     /// Get images with matching tags
-    Images { filter: String },
+    Images {
+        filter: String,
+        /// Sort by id, path, usage, added, updated or tagged
+        #[arg(long, default_value = "id")]
+        sort: SortBy,
+        /// Sort in descending order
+        #[arg(long)]
+        desc: bool,
+        /// Maximum number of images to show
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Continue after the cursor printed by a previous query
+        #[arg(long)]
+        after: Option<Cursor>,
+    },
+}
+#[derive(Subcommand, Debug)]
+enum RuleCommand {
+    /// Add a rule, images with all tags of --if get the --implies tag or can't have the
+    /// --excludes tag
+    Add {
+        /// Comma separated tag names
+        #[arg(long = "if", required = true, value_delimiter = ',')]
+        conditions: Vec<String>,
+        #[arg(
+            long,
+            required_unless_present = "excludes",
+            conflicts_with = "excludes"
+        )]
+        implies: Option<String>,
+        #[arg(long)]
+        excludes: Option<String>,
+    },
+    /// List the rules with their ids
+    List,
+    /// Remove the rule with the id
+    Remove { id: i64 },
+    /// Add implied tags to all images
+    Apply,
+    /// List the images breaking a rule
+    Check,
 }
 #[derive(Subcommand, Debug)]
 enum Target {
@@ -41,11 +239,22 @@ fn main() {
     let args = Args::parse();
     let mut conn = rusqlite::Connection::open(&args.path).unwrap();
     db::methods::init::init_tables(&conn).unwrap();
-    db::methods::tags::add_tags(
-        vec!["a", "b", "c", "d", "test", "abc", "alphabet", "xenomorph"],
-        &mut conn,
-    );
-    db::methods::images::add_image("test.jpg", &conn);
+    db::methods::history::set_actor(Some("cli"), &conn).unwrap();
+    // example rows, kept out of the database being checked, backed up, restored or synced
+    if !matches!(
+        args.cmd,
+        Commands::Doctor { .. }
+            | Commands::Backup { .. }
+            | Commands::Restore { .. }
+            | Commands::Sync { .. }
+            | Commands::Hash
+    ) {
+        db::methods::tags::add_tags(
+            vec!["a", "b", "c", "d", "test", "abc", "alphabet", "xenomorph"],
+            &mut conn,
+        );
+        db::methods::images::add_image("test.jpg", &conn);
+    }
     //db::tags::add_tag_to_img("a", 1, false, &conn);
 
     match &args.cmd {
@@ -53,13 +262,31 @@ fn main() {
             Query::Tags { path } => {
                 let id = db::methods::utils::get_id("images", &format!("path='{}'", path), &conn)
                     .unwrap();
-                let tags = db::methods::images::get_tags_of_img(id, &conn);
+                let tags = db::methods::images::get_tags_of_img(ImageId(id), &conn);
                 for tag in tags {
                     println!("{}", tag.1);
                 }
             }
-            Query::Images { .. } => {
-                todo!()
+            Query::Images {
+                filter,
+                sort,
+                desc,
+                limit,
+                after,
+            } => {
+                let options = ListOptions {
+                    limit: *limit,
+                    ..Default::default()
+                }
+                .sorted(*sort, *desc)
+                .after(after.clone());
+                let page = db::methods::images::query_sql_page(filter, &options, &conn).unwrap();
+                for image in page.items {
+                    println!("{}", image.1);
+                }
+                if let Some(next) = page.next {
+                    eprintln!("more results: --after {}", next);
+                }
             }
         },
         Commands::Delete { t } => match &t {
@@ -67,5 +294,395 @@ fn main() {
                 db::methods::init::recreate_db(std::path::PathBuf::from(args.path));
             }
         },
+        Commands::Log {
+            image,
+            tag,
+            since,
+            until,
+        } => {
+            let from = since.as_deref().map_or(0, |date| {
+                db::methods::utils::parse_date(date).expect("Invalid --since date")
+            });
+            let to = until.as_deref().map_or(i64::MAX, |date| {
+                db::methods::utils::parse_date(date).expect("Invalid --until date")
+            });
+            let entries = if let Some(path) = image {
+                let id = db::methods::images::get_image_by_path(path, &conn)
+                    .expect("Image doesn't exist");
+                db::methods::history::get_history_of_image(id, &conn)
+            } else if let Some(name) = tag {
+                let id = db::methods::tags::resolve(name, &conn).expect("Tag doesn't exist");
+                db::methods::history::get_history_of_tag(id, &conn)
+            } else {
+                db::methods::history::get_history_between(from, to, &conn)
+            }
+            .unwrap();
+            for entry in entries {
+                if entry.timestamp >= from && entry.timestamp < to {
+                    println!("{}", entry);
+                }
+            }
+        }
+        Commands::Search {
+            query,
+            saved,
+            save,
+            list,
+            sort,
+            desc,
+            limit,
+            after,
+        } => {
+            if *list {
+                for search in db::methods::searches::get_searches(&conn).unwrap() {
+                    let order = if search.descending { " desc" } else { "" };
+                    println!(
+                        "{}\t{}\t{}{}",
+                        search.name, search.query, search.sort, order
+                    );
+                }
+                return;
+            }
+            let options = ListOptions {
+                limit: *limit,
+                ..Default::default()
+            }
+            .after(after.clone());
+            let page = if let Some(name) = saved {
+                let search = db::methods::searches::get_search_by_name(name, &conn)
+                    .unwrap()
+                    .expect("Saved search doesn't exist");
+                db::methods::searches::run_search_page(search.id, &options, &conn)
+                    .unwrap()
+                    .unwrap()
+            } else {
+                let query = query.as_deref().unwrap();
+                if let Some(name) = save {
+                    db::methods::searches::add_search(name, query, *sort, *desc, &conn)
+                        .expect("A saved search with this name already exists");
+                }
+                let options = options.sorted(*sort, *desc);
+                db::methods::images::query_sql_page(query, &options, &conn).unwrap()
+            };
+            for image in page.items {
+                println!("{}", image.1);
+            }
+            if let Some(next) = page.next {
+                eprintln!("more results: --after {}", next);
+            }
+        }
+        Commands::Merge {
+            target,
+            sources,
+            alias,
+        } => {
+            let resolve = |name: &str| {
+                db::methods::tags::resolve(name, &conn)
+                    .unwrap_or_else(|| panic!("Tag {} doesn't exist", name))
+            };
+            let target = resolve(target);
+            let sources: Vec<TagId> = sources.iter().map(|name| resolve(name)).collect();
+            db::methods::tags::merge_tags(&sources, target, *alias, &mut conn).unwrap();
+        }
+        Commands::Rename {
+            pattern,
+            replacement,
+            namespace,
+            merge,
+            dry_run,
+        } => {
+            let namespace = namespace.as_ref().map(|name| {
+                db::methods::namespaces::get_namespace_by_name(name, &conn)
+                    .expect("Namespace doesn't exist")
+            });
+            let renames = if *dry_run {
+                db::methods::tags::preview_rename(pattern, replacement, namespace, &conn)
+            } else {
+                db::methods::tags::bulk_rename(pattern, replacement, namespace, *merge, &mut conn)
+            };
+            let renames = match renames {
+                Ok(renames) => renames,
+                Err(err) => {
+                    eprintln!("Nothing was renamed: {}", err);
+                    if !*merge {
+                        eprintln!("Use --dry-run to see collisions and --merge to merge them");
+                    }
+                    std::process::exit(1);
+                }
+            };
+            for rename in renames {
+                let note = match rename.collision {
+                    Some(_) if *merge => " (merged)",
+                    Some(_) => " (collides)",
+                    None => "",
+                };
+                println!("{} -> {}{}", rename.old, rename.new, note);
+            }
+        }
+        Commands::Rule { r } => {
+            let tag = |name: &str| {
+                db::methods::tags::resolve(name, &conn)
+                    .unwrap_or_else(|| panic!("Tag {} doesn't exist", name))
+            };
+            let name =
+                |tag_id: TagId| db::methods::tags::get_name(tag_id, &conn).unwrap_or_default();
+            match r {
+                RuleCommand::Add {
+                    conditions,
+                    implies,
+                    excludes,
+                } => {
+                    let conditions: Vec<TagId> = conditions.iter().map(|name| tag(name)).collect();
+                    let (kind, target) = match (implies, excludes) {
+                        (Some(target), _) => (RuleKind::Implies, tag(target)),
+                        (None, target) => (RuleKind::Excludes, tag(target.as_deref().unwrap())),
+                    };
+                    let id = db::methods::rules::add_rule(kind, &conditions, target, &mut conn)
+                        .expect("Rules need conditions which don't include the target");
+                    println!("{}", id);
+                }
+                RuleCommand::List => {
+                    for rule in db::methods::rules::get_rules(&conn).unwrap() {
+                        let conditions: Vec<String> =
+                            rule.conditions.into_iter().map(name).collect();
+                        println!(
+                            "{}\t{} {} {}",
+                            rule.id,
+                            conditions.join(" AND "),
+                            rule.kind,
+                            name(rule.target)
+                        );
+                    }
+                }
+                RuleCommand::Remove { id } => {
+                    db::methods::rules::remove_rule(RuleId(*id), &conn).unwrap();
+                }
+                RuleCommand::Apply => {
+                    let added = db::methods::rules::apply_rules_to_all(&mut conn).unwrap();
+                    println!("added {} tags", added);
+                }
+                RuleCommand::Check => {
+                    for violation in db::methods::rules::get_violations(&conn).unwrap() {
+                        let path = db::methods::images::get_path(violation.img_id, &conn);
+                        println!("{}\t{}", violation.rule_id, path.unwrap_or_default());
+                    }
+                }
+            }
+        }
+        Commands::Exclusive { namespace, mode } => {
+            let id = db::methods::namespaces::get_namespace_by_name(namespace, &conn)
+                .expect("Namespace doesn't exist");
+            db::methods::namespaces::set_exclusive(id, *mode, &conn).unwrap();
+            for (namespace_id, img_id) in
+                db::methods::namespaces::get_exclusive_violations(&conn).unwrap()
+            {
+                if namespace_id == id {
+                    let path = db::methods::images::get_path(img_id, &conn);
+                    println!("{}", path.unwrap_or_default());
+                }
+            }
+        }
+        Commands::SmartGroup {
+            name,
+            query,
+            materialized,
+        } => {
+            let id = db::methods::groups::add_smart_group(name, query, *materialized, &conn)
+                .expect("A group with this name already exists");
+            if *materialized {
+                db::methods::groups::refresh_group(id, &mut conn).unwrap();
+            }
+        }
+        Commands::Refresh { group } => match group {
+            Some(name) => {
+                let id = db::methods::groups::get_group_by_name(name, &conn)
+                    .expect("Group doesn't exist");
+                db::methods::groups::refresh_group(id, &mut conn).unwrap();
+            }
+            None => db::methods::groups::refresh_groups(&mut conn).unwrap(),
+        },
+        Commands::Tree { root } => {
+            let trees = match root {
+                Some(name) => {
+                    let id = db::methods::tags::resolve(name, &conn)
+                        .unwrap_or_else(|| panic!("Tag {} doesn't exist", name));
+                    vec![db::methods::subtags::get_tree(id, &conn).unwrap()]
+                }
+                None => db::methods::subtags::get_forest(&conn).unwrap(),
+            };
+            for tree in &trees {
+                print_tree(tree, 0);
+            }
+        }
+        Commands::Graph {
+            format,
+            root,
+            namespaces,
+            usage,
+        } => {
+            let options = GraphOptions {
+                root: root.as_ref().map(|name| {
+                    db::methods::tags::resolve(name, &conn)
+                        .unwrap_or_else(|| panic!("Tag {} doesn't exist", name))
+                }),
+                color_namespaces: *namespaces,
+                size_by_usage: *usage,
+            };
+            let graph = db::methods::graph::export_hierarchy(*format, &options, &conn).unwrap();
+            print!("{}", graph);
+        }
+        Commands::Backup { path, keep } => {
+            let res = match keep {
+                Some(keep) => db::methods::backup::backup_rotating(
+                    Path::new(path),
+                    *keep,
+                    print_progress,
+                    &conn,
+                ),
+                None => db::methods::backup::backup(Path::new(path), print_progress, &conn)
+                    .map(|_| path.into()),
+            };
+            match res {
+                Ok(path) => println!("{}", path.display()),
+                Err(err) => {
+                    eprintln!("Backup failed: {}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Restore { path } => {
+            if let Err(err) =
+                db::methods::backup::restore(Path::new(path), print_progress, &mut conn)
+            {
+                eprintln!("Nothing was restored: {}", err);
+                std::process::exit(1);
+            }
+        }
+        Commands::Doctor { repair } => {
+            let problems = db::methods::integrity::check(&conn).unwrap();
+            let problems = if *repair && !problems.is_empty() {
+                let left = db::methods::integrity::repair(&mut conn).unwrap();
+                println!(
+                    "fixed {} problems",
+                    problems.len().saturating_sub(left.len())
+                );
+                left
+            } else {
+                problems
+            };
+            for problem in &problems {
+                println!("{}", problem);
+            }
+            if !problems.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Commands::Sync { remote, dry_run } => {
+            let mut remote = match db::methods::sync::open_remote(Path::new(remote)) {
+                Ok(remote) => remote,
+                Err(err) => {
+                    eprintln!("Can't open {}: {}", remote, err);
+                    std::process::exit(1);
+                }
+            };
+            let report = db::methods::sync::sync(&mut conn, &mut remote, *dry_run).unwrap();
+            for change in &report.local {
+                println!("local: {}", change);
+            }
+            for change in &report.remote {
+                println!("remote: {}", change);
+            }
+            for conflict in &report.conflicts {
+                println!(
+                    "conflict ({}): {}: {}",
+                    conflict.side, conflict.skipped, conflict.kind
+                );
+            }
+        }
+        Commands::Hash => {
+            let hashed = db::methods::sync::hash_images(&conn).unwrap();
+            println!("hashed {} images", hashed);
+        }
+        Commands::Export { format } => {
+            db::methods::images::for_each_image(&conn, |image| {
+                println!("{}", export_line(image, *format, &conn));
+            })
+            .unwrap();
+        }
+    }
+}
+
+/// Prints how much of a backup is done over the previous progress
+fn print_progress(progress: Progress) {
+    let done = progress.pagecount - progress.remaining;
+    eprint!("\r{}/{} pages", done, progress.pagecount);
+    if progress.remaining == 0 {
+        eprintln!();
+    }
+}
+
+/// Prints the node and its children, indented by their depth
+fn print_tree(node: &TagNode, depth: usize) {
+    println!("{}{} ({})", "  ".repeat(depth), node.name, node.image_count);
+    for child in &node.children {
+        print_tree(child, depth + 1);
+    }
+}
+
+/// Escapes tabs, newlines and backslashes in a tsv field
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+/// Formats an image with its tags, tag values and properties as one line
+fn export_line(image: Image, format: Format, conn: &rusqlite::Connection) -> String {
+    let mut tags: Vec<String> = vec![];
+    db::methods::images::for_each_tag_of_img(image.id, &ListOptions::default(), conn, |_, tag| {
+        tags.push(tag)
+    })
+    .unwrap();
+    let values = db::methods::values::get_values_of_img(image.id, conn).unwrap();
+    let properties = db::methods::properties::get_properties(image.id, conn).unwrap();
+    match format {
+        Format::Tsv => {
+            let mut fields = vec![escape(&image.path)];
+            for tag in tags {
+                match values.iter().find(|value| value.1 == tag) {
+                    Some((_, _, value)) => fields.push(escape(&format!("{}={}", tag, value))),
+                    None => fields.push(escape(&tag)),
+                }
+            }
+            for (key, value) in properties {
+                fields.push(escape(&format!("@{}={}", key, value)));
+            }
+            fields.join("\t")
+        }
+        Format::Json => {
+            let values: serde_json::Map<String, serde_json::Value> = values
+                .into_iter()
+                .map(|(_, tag, value)| {
+                    let value = match value {
+                        TagValue::Int(value) => value.into(),
+                        TagValue::Float(value) => value.into(),
+                        value => value.to_string().into(),
+                    };
+                    (tag, value)
+                })
+                .collect();
+            let properties: serde_json::Map<String, serde_json::Value> = properties
+                .into_iter()
+                .map(|(key, value)| (key, value.into()))
+                .collect();
+            serde_json::json!({
+                "path": image.path,
+                "tags": tags,
+                "values": values,
+                "properties": properties,
+            })
+            .to_string()
+        }
     }
 }
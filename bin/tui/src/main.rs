@@ -1,3 +1,4 @@
+use db::types::{ImageId, ListOptions, NamespaceId, SearchId, TagId};
 use ratatui::{
     crossterm::{
         event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
@@ -14,6 +15,9 @@ use std::{
 };
 use tui_input::{backend::crossterm::EventHandler, Input};
 
+/// Maximum number of search results and recommendations shown
+const RESULT_LIMIT: usize = 200;
+
 enum InputMode {
     View,
     Insert,
@@ -48,6 +52,8 @@ struct App {
     db_conn: Connection,
 
     user_input: Option<UserInput>,
+    /// Outcome of the last action, shown below the editing tab
+    status: Option<String>,
 }
 struct UserInput {
     operation: EditOption,
@@ -73,14 +79,22 @@ enum ResultType {
     Namespace,
     Image,
     Group,
+    SavedSearch,
 }
 
 enum EditOption {
     Rename,
+    Describe,
     Delete,
     AddTag,
     RemoveTag,
-    Confirm { confirm: bool },
+    /// Regex rename of all tags, merge is set once colliding tags should get merged
+    BulkRename {
+        merge: bool,
+    },
+    Confirm {
+        confirm: bool,
+    },
 }
 
 impl Default for Result {
@@ -119,6 +133,7 @@ fn main() -> io::Result<()> {
         },
         db_conn: Connection::open("base.db").unwrap(),
         user_input: None,
+        status: None,
     };
     let res = run_app(&mut terminal, app);
 
@@ -186,12 +201,67 @@ fn run_app(
                                 }
                                 _ => {}
                             },
+                            KeyCode::Char('s') => match app.currently_focused {
+                                FocusedWidget::SearchBar => {
+                                    app.currently_viewing = CurrentlyViewing::Search {
+                                        search_type: ResultType::SavedSearch,
+                                    };
+                                    app.search_results.items =
+                                        db::methods::searches::get_searches(&app.db_conn)
+                                            .unwrap_or_default()
+                                            .into_iter()
+                                            .map(|search| Result {
+                                                name: search.name,
+                                                id: search.id.into(),
+                                                rtype: ResultType::SavedSearch,
+                                            })
+                                            .collect();
+                                }
+                                _ => {}
+                            },
+                            KeyCode::Enter => match app.currently_focused {
+                                // runs the selected saved search
+                                FocusedWidget::Results
+                                    if matches!(search_type, ResultType::SavedSearch) =>
+                                {
+                                    let selected = app.search_results.state.selected().unwrap_or(0);
+                                    if let Some(search) = app.search_results.items.get(selected) {
+                                        app.search_results.items =
+                                            db::methods::searches::run_search_page(
+                                                SearchId(search.id),
+                                                &ListOptions::limit(RESULT_LIMIT),
+                                                &app.db_conn,
+                                            )
+                                            .ok()
+                                            .flatten()
+                                            .map(|page| page.items)
+                                            .unwrap_or_default()
+                                            .iter()
+                                            .map(|image| Result {
+                                                id: image.0.into(),
+                                                name: image.1.clone(),
+                                                rtype: ResultType::Image,
+                                            })
+                                            .collect();
+                                        app.search_results.state.select(Some(0));
+                                        app.currently_viewing = CurrentlyViewing::Search {
+                                            search_type: ResultType::Image,
+                                        };
+                                    }
+                                }
+                                _ => {}
+                            },
                             KeyCode::Char('e') => match app.currently_focused {
                                 FocusedWidget::Results => {
                                     let (table, options) = match search_type {
                                         ResultType::Tag => (
                                             "tags".to_string(),
-                                            vec![EditOption::Rename, EditOption::Delete],
+                                            vec![
+                                                EditOption::Rename,
+                                                EditOption::Describe,
+                                                EditOption::Delete,
+                                                EditOption::BulkRename { merge: false },
+                                            ],
                                         ),
                                         ResultType::Image => (
                                             "images".to_string(),
@@ -211,13 +281,19 @@ fn run_app(
                                                 EditOption::RemoveTag,
                                             ],
                                         ),
+                                        ResultType::SavedSearch => (
+                                            "saved_searches".to_string(),
+                                            vec![EditOption::Rename, EditOption::Delete],
+                                        ),
                                         ResultType::Namespace => (
                                             "namespaces".to_string(),
                                             vec![
                                                 EditOption::Rename,
+                                                EditOption::Describe,
                                                 EditOption::Delete,
                                                 EditOption::AddTag,
                                                 EditOption::RemoveTag,
+                                                EditOption::BulkRename { merge: false },
                                             ],
                                         ),
                                     };
@@ -264,17 +340,21 @@ fn run_app(
                             KeyCode::Enter => {
                                 match search_type {
                                     ResultType::Image => {
-                                        app.search_results.items = db::methods::images::query_sql(
-                                            app.search.value(),
-                                            &app.db_conn,
-                                        )
-                                        .iter()
-                                        .map(|image| Result {
-                                            id: image.0,
-                                            name: image.1.clone(),
-                                            rtype: ResultType::Image,
-                                        })
-                                        .collect();
+                                        app.search_results.items =
+                                            db::methods::images::query_sql_page(
+                                                app.search.value(),
+                                                &ListOptions::limit(RESULT_LIMIT),
+                                                &app.db_conn,
+                                            )
+                                            .map(|page| page.items)
+                                            .unwrap_or_default()
+                                            .iter()
+                                            .map(|image| Result {
+                                                id: image.0.into(),
+                                                name: image.1.clone(),
+                                                rtype: ResultType::Image,
+                                            })
+                                            .collect();
                                     }
                                     _ => {}
                                 }
@@ -306,14 +386,17 @@ fn run_app(
                                         let last_word =
                                             app.search.value().split(' ').last().unwrap_or("");
                                         app.search_results.items =
-                                            db::methods::tags::get_tags_with(
+                                            db::methods::tags::get_tags_with_page(
                                                 last_word,
+                                                &ListOptions::limit(RESULT_LIMIT),
                                                 &app.db_conn,
                                             )
+                                            .map(|page| page.items)
+                                            .unwrap_or_default()
                                             .iter()
                                             .map(|tag| Result {
                                                 name: tag.1.clone(),
-                                                id: tag.0,
+                                                id: tag.0.into(),
                                                 rtype: search_type.clone(),
                                             })
                                             .collect();
@@ -349,36 +432,63 @@ fn run_app(
                     }
                     KeyCode::Enter => {
                         if let Some(ref input) = app.user_input {
+                            // stays open to confirm merging colliding tags
+                            let mut confirm_merge = false;
                             match input.operation {
                                 EditOption::Rename => match &table[..] {
                                     "tags" => db::methods::tags::rename_tag(
-                                        input.target_id,
+                                        TagId(input.target_id),
                                         input.data.value(),
                                         &app.db_conn,
                                     )
                                     .unwrap(),
                                     "images" => db::methods::images::update_path(
-                                        input.target_id,
+                                        ImageId(input.target_id),
                                         input.data.value(),
                                         &app.db_conn,
                                     )
                                     .unwrap(),
                                     "namespaces" => db::methods::namespaces::rename_namespace(
-                                        input.target_id,
+                                        NamespaceId(input.target_id),
                                         input.data.value(),
                                         &app.db_conn,
                                     )
                                     .unwrap(),
                                     "groups" => todo!(),
+                                    "saved_searches" => db::methods::searches::rename_search(
+                                        SearchId(input.target_id),
+                                        input.data.value(),
+                                        &app.db_conn,
+                                    )
+                                    .unwrap(),
                                     _ => {}
                                 },
+                                EditOption::Describe => {
+                                    let description = Some(input.data.value())
+                                        .filter(|description| !description.is_empty());
+                                    match &table[..] {
+                                        "tags" => db::methods::tags::set_description(
+                                            TagId(input.target_id),
+                                            description,
+                                            &app.db_conn,
+                                        )
+                                        .unwrap(),
+                                        "namespaces" => db::methods::namespaces::set_description(
+                                            NamespaceId(input.target_id),
+                                            description,
+                                            &app.db_conn,
+                                        )
+                                        .unwrap(),
+                                        _ => {}
+                                    }
+                                }
                                 EditOption::AddTag => {
                                     let res = match &table[..] {
                                         "images" => {
                                             db::methods::tags::add_tag_to_img(
-                                                input.data.value(), // tag
-                                                input.target_id,    // img
-                                                false,              // don't create tags
+                                                input.data.value(),       // tag
+                                                ImageId(input.target_id), // img
+                                                false,                    // don't create tags
                                                 &app.db_conn,
                                             )
                                             //if let None = res {
@@ -398,9 +508,52 @@ fn run_app(
                                         println!("Error somewhere");
                                     }
                                 }
+                                EditOption::BulkRename { merge } => {
+                                    if let Some((pattern, replacement)) =
+                                        input.data.value().rsplit_once('/')
+                                    {
+                                        let namespace =
+                                            bulk_rename_namespace(table, input.target_id);
+                                        let collides = db::methods::tags::preview_rename(
+                                            pattern,
+                                            replacement,
+                                            namespace,
+                                            &app.db_conn,
+                                        )
+                                        .is_ok_and(|renames| {
+                                            renames.iter().any(|rename| rename.collision.is_some())
+                                        });
+                                        if collides && !merge {
+                                            confirm_merge = true;
+                                        } else {
+                                            app.status = Some(
+                                                match db::methods::tags::bulk_rename(
+                                                    pattern,
+                                                    replacement,
+                                                    namespace,
+                                                    merge,
+                                                    &mut app.db_conn,
+                                                ) {
+                                                    Ok(renames) => {
+                                                        format!("Renamed {} tags", renames.len())
+                                                    }
+                                                    Err(err) => {
+                                                        format!("Bulk rename failed: {}", err)
+                                                    }
+                                                },
+                                            );
+                                        }
+                                    }
+                                }
                                 _ => {}
                             }
-                            app.user_input = None;
+                            if confirm_merge {
+                                if let Some(ref mut input) = app.user_input {
+                                    input.operation = EditOption::BulkRename { merge: true };
+                                }
+                            } else {
+                                app.user_input = None;
+                            }
                         } else {
                             match options.items[options.state.selected().unwrap()] {
                                 EditOption::Delete => {
@@ -415,15 +568,46 @@ fn run_app(
                                         recommendations: None,
                                     });
                                 }
+                                EditOption::Describe => {
+                                    let description = match &table[..] {
+                                        "tags" => db::methods::tags::get_description(
+                                            TagId(entry.id),
+                                            &app.db_conn,
+                                        ),
+                                        _ => db::methods::namespaces::get_description(
+                                            NamespaceId(entry.id),
+                                            &app.db_conn,
+                                        ),
+                                    };
+                                    app.user_input = Some(UserInput {
+                                        operation: EditOption::Describe,
+                                        target_id: entry.id,
+                                        data: Input::new(description.unwrap_or_default()),
+                                        recommendations: None,
+                                    });
+                                }
                                 EditOption::AddTag => {
                                     app.user_input = Some(UserInput {
                                         operation: EditOption::AddTag,
                                         target_id: entry.id,
                                         data: Input::default(),
-                                        recommendations: Some(vec![]),
+                                        recommendations: Some(tag_recommendations(
+                                            table,
+                                            entry.id,
+                                            "",
+                                            &app.db_conn,
+                                        )),
                                     });
                                 }
                                 EditOption::RemoveTag => {}
+                                EditOption::BulkRename { .. } => {
+                                    app.user_input = Some(UserInput {
+                                        operation: EditOption::BulkRename { merge: false },
+                                        target_id: entry.id,
+                                        data: Input::default(),
+                                        recommendations: Some(vec![]),
+                                    });
+                                }
                                 _ => {
                                     unimplemented! {}
                                 }
@@ -433,20 +617,50 @@ fn run_app(
                     _ => {
                         if let Some(ref mut input) = app.user_input {
                             input.data.handle_event(&Event::Key(key));
+                            if let EditOption::BulkRename { .. } = input.operation {
+                                // changing the pattern needs a new confirmation
+                                input.operation = EditOption::BulkRename { merge: false };
+                            }
                             if let Some(ref mut _recom) = input.recommendations {
                                 input.recommendations = match input.operation {
-                                    EditOption::AddTag => Some(
-                                        db::methods::tags::get_tags_with(
-                                            input.data.value(),
-                                            &app.db_conn,
-                                        )
-                                        .iter()
-                                        .map(|tag| Result {
-                                            name: tag.1.clone(),
-                                            id: tag.0,
-                                            rtype: ResultType::Tag,
-                                        })
-                                        .collect(),
+                                    EditOption::AddTag => Some(tag_recommendations(
+                                        table,
+                                        input.target_id,
+                                        input.data.value(),
+                                        &app.db_conn,
+                                    )),
+                                    EditOption::BulkRename { .. } => Some(
+                                        input
+                                            .data
+                                            .value()
+                                            .rsplit_once('/')
+                                            .and_then(|(pattern, replacement)| {
+                                                db::methods::tags::preview_rename(
+                                                    pattern,
+                                                    replacement,
+                                                    bulk_rename_namespace(table, input.target_id),
+                                                    &app.db_conn,
+                                                )
+                                                .ok()
+                                            })
+                                            .unwrap_or_default()
+                                            .into_iter()
+                                            .take(RESULT_LIMIT)
+                                            .map(|rename| Result {
+                                                name: format!(
+                                                    "{} -> {}{}",
+                                                    rename.old,
+                                                    rename.new,
+                                                    if rename.collision.is_some() {
+                                                        " (collides)"
+                                                    } else {
+                                                        ""
+                                                    }
+                                                ),
+                                                id: rename.tag_id.into(),
+                                                rtype: ResultType::Tag,
+                                            })
+                                            .collect(),
                                     ),
                                     _ => None,
                                 }
@@ -458,6 +672,50 @@ fn run_app(
         }
     }
 }
+/// Tags to add matching the typed text, on images the ones suggested from the tags it already
+/// has come first with their confidence
+fn tag_recommendations(table: &str, target_id: i64, typed: &str, conn: &Connection) -> Vec<Result> {
+    let mut recommendations: Vec<Result> = Vec::new();
+    if table == "images" {
+        for suggestion in
+            db::methods::suggestions::suggest_tags(ImageId(target_id), RESULT_LIMIT, conn)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|suggestion| suggestion.name.contains(typed))
+        {
+            recommendations.push(Result {
+                name: format!(
+                    "{} ({:.0}%)",
+                    suggestion.name,
+                    suggestion.confidence * 100.0
+                ),
+                id: suggestion.tag_id.into(),
+                rtype: ResultType::Tag,
+            });
+        }
+    }
+    if typed.is_empty() {
+        return recommendations;
+    }
+    for tag in db::methods::tags::get_tags_with_page(typed, &ListOptions::limit(RESULT_LIMIT), conn)
+        .map(|page| page.items)
+        .unwrap_or_default()
+    {
+        let id: i64 = tag.0.into();
+        if !recommendations.iter().any(|result| result.id == id) {
+            recommendations.push(Result {
+                name: tag.1,
+                id,
+                rtype: ResultType::Tag,
+            });
+        }
+    }
+    recommendations
+}
+/// Bulk renames started from a namespace only rename the tags in it
+fn bulk_rename_namespace(table: &str, target_id: i64) -> Option<NamespaceId> {
+    (table == "namespaces").then_some(NamespaceId(target_id))
+}
 fn ui(f: &mut Frame, app: &mut App) {
     match &app.currently_viewing {
         CurrentlyViewing::Search { .. } => tabs::render_search(f, app),
@@ -567,7 +825,14 @@ mod tabs {
         };
         let outer_layout = Layout::default()
             .direction(Direction::Vertical)
-            .constraints([Constraint::Length(1), Constraint::Min(3)].as_ref())
+            .constraints(
+                [
+                    Constraint::Length(1),
+                    Constraint::Min(3),
+                    Constraint::Length(1),
+                ]
+                .as_ref(),
+            )
             .split(f.size());
         #[allow(unused)]
         let inner_layout = Layout::default()
@@ -582,28 +847,61 @@ mod tabs {
         f.render_widget(
             Paragraph::new(match entry.rtype {
                 ResultType::Tag => {
-                    format!("Name: {}\nId: {}", entry.name, entry.id)
+                    format!(
+                        "Name: {}\nId: {}\nDescription:\n{}",
+                        entry.name,
+                        entry.id,
+                        db::methods::tags::get_description(TagId(entry.id), &app.db_conn)
+                            .unwrap_or_default()
+                    )
                 }
                 ResultType::Image => {
                     let mut res = String::new();
                     res.push_str(&format!("Path: {}\nId: {}\nTags:\n", entry.name, entry.id));
-                    for tag in db::methods::images::get_tags_of_img(entry.id, &app.db_conn) {
+                    for tag in db::methods::images::get_tags_of_img(ImageId(entry.id), &app.db_conn)
+                    {
                         res.push_str(&tag.1);
                     }
                     res
                 }
-                ResultType::Namespace => "".to_string(),
+                ResultType::Namespace => format!(
+                    "Name: {}\nId: {}\nDescription:\n{}",
+                    entry.name,
+                    entry.id,
+                    db::methods::namespaces::get_description(NamespaceId(entry.id), &app.db_conn)
+                        .unwrap_or_default()
+                ),
                 ResultType::Group => "".to_string(),
+                ResultType::SavedSearch => {
+                    match db::methods::searches::get_search(SearchId(entry.id), &app.db_conn) {
+                        Ok(Some(search)) => format!(
+                            "Name: {}\nId: {}\nQuery: {}\nSort: {}{}",
+                            search.name,
+                            entry.id,
+                            search.query,
+                            search.sort,
+                            if search.descending {
+                                " (descending)"
+                            } else {
+                                ""
+                            }
+                        ),
+                        _ => "".to_string(),
+                    }
+                }
             })
+            .wrap(Wrap { trim: false })
             .block(Block::default().borders(Borders::ALL).title("Data")),
             inner_layout[0],
         );
         f.render_stateful_widget(
             List::new(options.items.iter().map(|opt| match opt {
                 EditOption::Rename => "Rename",
+                EditOption::Describe => "Edit Description",
                 EditOption::Delete => "Delete",
                 EditOption::AddTag => "Add Tag",
                 EditOption::RemoveTag => "Remove Tag",
+                EditOption::BulkRename { .. } => "Bulk Rename",
                 _ => {
                     unimplemented! {}
                 }
@@ -613,10 +911,18 @@ mod tabs {
             inner_layout[1],
             &mut options.state,
         );
+        if let Some(status) = &app.status {
+            f.render_widget(Paragraph::new(status.as_str()), outer_layout[2]);
+        }
 
         if let Some(input) = &app.user_input {
+            let title = match input.operation {
+                EditOption::BulkRename { merge: false } => "Input (regex/replacement)",
+                EditOption::BulkRename { merge: true } => "Enter again to merge colliding tags",
+                _ => "Input",
+            };
             let text = Paragraph::new(input.data.value())
-                .block(Block::default().borders(Borders::ALL).title("Input"));
+                .block(Block::default().borders(Borders::ALL).title(title));
             let full_area = centered_rect(60, 20, f.size());
             f.render_widget(Clear, full_area);
 
@@ -0,0 +1,540 @@
+//! Async facade over [`Database`], enabled with the `async` feature.
+//!
+//! Every method runs the matching sync method on tokio's blocking pool. Dropping a returned
+//! future before it finishes (e.g. through `tokio::time::timeout` or `tokio::select!`)
+//! interrupts the SQLite statement it is running.
+use crate::{
+    methods::{
+        history::HistoryEntry,
+        tags::{BatchSummary, Rename},
+    },
+    pool::{self, InterruptSlot},
+    types::{
+        Exclusivity, GraphFormat, GraphOptions, Group, GroupId, Image, ImageId, ListOptions,
+        Namespace, NamespaceId, Page, Problem, Rule, RuleId, RuleKind, SavedSearch, SearchId,
+        SortBy, Suggestion, SyncReport, Tag, TagId, TagNode, TagValue, Violation,
+    },
+    wrapper::Database,
+};
+use rusqlite::backup::Progress;
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+/// Generates async versions of sync Database methods, arguments are owned so they can be moved
+/// to the blocking pool
+macro_rules! mirror {
+    ($(
+        $(#[$doc:meta])*
+        fn $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty = |$db:ident| $call:expr;
+    )*) => {
+        $(
+            $(#[$doc])*
+            pub async fn $name(&self, $($arg: $ty),*) -> $ret {
+                self.run(move |$db| $call).await
+            }
+        )*
+    };
+}
+
+/// Borrows owned strings for methods taking string slices
+fn strs(strings: &[String]) -> Vec<&str> {
+    strings.iter().map(String::as_str).collect()
+}
+
+/// Interrupts the watched connection when dropped before being disarmed
+struct InterruptOnDrop {
+    slot: InterruptSlot,
+    armed: bool,
+}
+impl Drop for InterruptOnDrop {
+    fn drop(&mut self) {
+        if self.armed {
+            let slot = self
+                .slot
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Some(handle) = slot.as_ref() {
+                handle.interrupt();
+            }
+        }
+    }
+}
+
+/// Async handle to the tag database, cheap to clone
+#[derive(Clone, Default)]
+pub struct AsyncDatabase {
+    db: Database,
+}
+
+impl From<Database> for AsyncDatabase {
+    fn from(db: Database) -> Self {
+        AsyncDatabase { db }
+    }
+}
+
+impl AsyncDatabase {
+    /// Create a database with a connection to the given path, missing tables get created
+    pub async fn open(path: &str) -> Self {
+        let path = path.to_owned();
+        Self::spawn(move || Database::open(&path)).await.into()
+    }
+    /// Create a database with a connection to the given path, also recreate the database fully
+    pub async fn create_db(path: &str) -> Self {
+        let path = path.to_owned();
+        Self::spawn(move || Database::create_db(&path)).await.into()
+    }
+    /// Gets the sync database this wraps
+    pub fn sync(&self) -> &Database {
+        &self.db
+    }
+    /// Runs f with the sync database on the blocking pool. If the returned future gets dropped
+    /// before f finishes, the query f is running gets interrupted
+    pub async fn run<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&Database) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let db = self.db.clone();
+        let slot: InterruptSlot = Arc::new(Mutex::new(None));
+        let mut guard = InterruptOnDrop {
+            slot: slot.clone(),
+            armed: true,
+        };
+        let res = Self::spawn(move || pool::watch_interrupts(slot, || f(&db))).await;
+        guard.armed = false;
+        res
+    }
+    async fn spawn<T, F>(f: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        match tokio::task::spawn_blocking(f).await {
+            Ok(res) => res,
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        }
+    }
+
+    // tags
+    mirror! {
+        /// Create tags with the given names
+        fn create_tags(tags: Vec<String>) -> () = |db| db.create_tags(strs(&tags));
+        /// Delete all tags via id
+        fn delete_tags(tags: Vec<TagId>) -> Option<()> = |db| db.delete_tags(tags);
+        /// Get the name of the tag with the given id
+        fn get_tag_name(tag_id: TagId) -> Option<String> = |db| db.get_tag_name(tag_id);
+        /// Get the tag with the given id
+        fn get_tag(tag_id: TagId) -> Option<Tag> = |db| db.get_tag(tag_id);
+        /// Rename the tag via id
+        fn rename_tag(tag_id: TagId, new_name: String) -> Option<()> =
+            |db| db.rename_tag(tag_id, &new_name);
+        /// Merges the source tags into the target, with keep_aliases their names resolve to it
+        fn merge_tags(sources: Vec<TagId>, target: TagId, keep_aliases: bool) -> Option<()> =
+            |db| db.merge_tags(&sources, target, keep_aliases);
+        /// Gets the renames replacing the regex in tag names, optionally only in a namespace
+        fn preview_tag_rename(
+            pattern: String,
+            replacement: String,
+            namespace: Option<NamespaceId>
+        ) -> Option<Vec<Rename>> =
+            |db| db.preview_tag_rename(&pattern, &replacement, namespace);
+        /// Replaces the regex in tag names at once, colliding tags get merged if merge is true
+        fn rename_tags(
+            pattern: String,
+            replacement: String,
+            namespace: Option<NamespaceId>,
+            merge: bool
+        ) -> Option<Vec<Rename>> =
+            |db| db.rename_tags(&pattern, &replacement, namespace, merge);
+        /// Gets the tag with the given name or alias
+        fn resolve_tag(name: String) -> Option<TagId> = |db| db.resolve_tag(&name);
+        /// Lets the name resolve to the tag
+        fn add_tag_alias(tag_id: TagId, name: String) -> Option<()> =
+            |db| db.add_tag_alias(tag_id, &name);
+        /// Removes an alias, the tag stays
+        fn remove_tag_alias(name: String) -> Option<()> = |db| db.remove_tag_alias(&name);
+        /// Gets the aliases of a tag
+        fn get_tag_aliases(tag_id: TagId) -> Option<Vec<String>> = |db| db.get_tag_aliases(tag_id);
+        /// Get all tag ids and names which don't connect to any images
+        fn get_tag_orphans() -> Vec<Tag> = |db| db.get_tag_orphans();
+        /// Get a page of the tags which don't connect to any images
+        fn get_tag_orphans_page(options: ListOptions) -> Option<Page<Tag>> =
+            |db| db.get_tag_orphans_page(&options);
+        /// Get all tags which match the given search term
+        fn get_tags_with(search_term: String) -> Vec<Tag> =
+            |db| db.get_tags_with(&search_term);
+        /// Get a page of the tags which match the given search term
+        fn get_tags_with_page(search_term: String, options: ListOptions) -> Option<Page<Tag>> =
+            |db| db.get_tags_with_page(&search_term, &options);
+        /// Sets the description of a tag, None removes it
+        fn set_tag_description(tag_id: TagId, description: Option<String>) -> Option<()> =
+            |db| db.set_tag_description(tag_id, description.as_deref());
+        /// Gets all tags whose description matches the full text query
+        fn search_tag_descriptions(query: String) -> Option<Vec<Tag>> =
+            |db| db.search_tag_descriptions(&query);
+        /// Gets a page of the tags whose description matches the full text query
+        fn search_tag_descriptions_page(query: String, options: ListOptions) -> Option<Page<Tag>> =
+            |db| db.search_tag_descriptions_page(&query, &options);
+        /// Get the namespace of the tag, return None if it fails
+        fn get_namespace_of_tag(tag_id: TagId) -> Option<Namespace> =
+            |db| db.get_namespace_of_tag(tag_id);
+    }
+
+    // images
+    mirror! {
+        /// Add a tag to the image, create decides if the tag should get created if it doesn't
+        /// exist
+        fn add_tag_to_img(tag: String, img_id: ImageId, create: bool) -> Option<()> =
+            |db| db.add_tag_to_img(&tag, img_id, create);
+        /// Suggests tags for the image which often appear together with its tags, best first
+        fn suggest_tags(img_id: ImageId, limit: usize) -> Option<Vec<Suggestion>> =
+            |db| db.suggest_tags(img_id, limit);
+        /// Removes and adds the tags on all images in one transaction
+        fn batch_tag(
+            imgs: Vec<ImageId>,
+            add: Vec<String>,
+            remove: Vec<String>,
+            create: bool
+        ) -> Option<Vec<BatchSummary>> =
+            |db| db.batch_tag(&imgs, &strs(&add), &strs(&remove), create);
+        /// Removes the tag matching the tag_id from the image matching the img_id
+        fn remove_tag_from_img(tag_id: TagId, img_id: ImageId) -> Option<()> =
+            |db| db.remove_tag_from_img(tag_id, img_id);
+        /// Creates an image entry with the given path and returns its id
+        fn create_image(path: String) -> Option<ImageId> = |db| db.create_image(&path);
+        /// Deletes the image with the path given
+        fn delete_image(path: String) -> Option<()> = |db| db.delete_image(&path);
+        /// Gets all images with a given tag
+        fn get_images_with_tag(tag_id: TagId) -> Option<Vec<Image>> =
+            |db| db.get_images_with_tag(tag_id);
+        /// Gets a page of the images with a given tag
+        fn get_images_with_tag_page(tag_id: TagId, options: ListOptions) -> Option<Page<Image>> =
+            |db| db.get_images_with_tag_page(tag_id, &options);
+        /// Gets all tags of a given image
+        fn get_tags_of_image(img_id: ImageId) -> Vec<Tag> =
+            |db| db.get_tags_of_image(img_id);
+        /// Gets a page of the tags of a given image
+        fn get_tags_of_image_page(img_id: ImageId, options: ListOptions) -> Option<Page<Tag>> =
+            |db| db.get_tags_of_image_page(img_id, &options);
+        /// Gets the path of an image via id
+        fn get_image_path(img_id: ImageId) -> Option<String> = |db| db.get_image_path(img_id);
+        /// Gets the image with the given id
+        fn get_image(img_id: ImageId) -> Option<Image> = |db| db.get_image(img_id);
+        /// Replaces the path of an image via id
+        fn replace_image_path(img_id: ImageId, path: String) -> Option<()> =
+            |db| db.replace_image_path(img_id, &path);
+        /// Gets all images without tags associated to them
+        fn get_image_orphans() -> Vec<Image> = |db| db.get_image_orphans();
+        /// Gets a page of the images without tags associated to them
+        fn get_image_orphans_page(options: ListOptions) -> Option<Page<Image>> =
+            |db| db.get_image_orphans_page(&options);
+        /// Gets a page of the images which have all known tags of the space separated query
+        fn query_images_page(query: String, options: ListOptions) -> Option<Page<Image>> =
+            |db| db.query_images_page(&query, &options);
+    }
+
+    // subtags
+    mirror! {
+        /// Gets all children and the children's children of a tag
+        fn get_tag_children(parent_id: TagId) -> Option<Vec<Tag>> =
+            |db| db.get_tag_children(parent_id);
+        /// Gets all parents and parent's parents of a tag
+        fn get_tag_parents(child_id: TagId) -> Option<Vec<Tag>> =
+            |db| db.get_tag_parents(child_id);
+        /// Gets the tag with everything below it as a tree
+        fn get_tag_tree(root: TagId) -> Option<TagNode> = |db| db.get_tag_tree(root);
+        /// Gets a tree for every tag without parents, sorted by name
+        fn get_tag_forest() -> Option<Vec<TagNode>> = |db| db.get_tag_forest();
+        /// Gets the tags without parents
+        fn get_root_tags() -> Option<Vec<Tag>> = |db| db.get_root_tags();
+        /// Gets the tags without children
+        fn get_leaf_tags() -> Option<Vec<Tag>> = |db| db.get_leaf_tags();
+        /// Gets the length of the longest chain of parents above every tag
+        fn get_tag_depths() -> Option<Vec<(TagId, usize)>> = |db| db.get_tag_depths();
+        /// Adds a parent to a given tag, parent and tag need to exist
+        fn add_tag_parent(parent_id: TagId, tag_id: TagId) -> Option<()> =
+            |db| db.add_tag_parent(parent_id, tag_id);
+        /// Removes the connection (parent-child / child-parent) from two tags
+        fn remove_connection(tag1: TagId, tag2: TagId) -> Option<()> =
+            |db| db.remove_connection(tag1, tag2);
+        /// Renders the tag hierarchy as DOT or Mermaid text
+        fn export_tag_hierarchy(format: GraphFormat, options: GraphOptions) -> Option<String> =
+            |db| db.export_tag_hierarchy(format, &options);
+    }
+
+    // namespaces
+    mirror! {
+        /// Creates a namespace and returns its id
+        fn create_namespace(name: String) -> Option<NamespaceId> = |db| db.create_namespace(&name);
+        /// Gets the namespace with the given id
+        fn get_namespace(namespace_id: NamespaceId) -> Option<Namespace> =
+            |db| db.get_namespace(namespace_id);
+        /// Deletes a namespace via id
+        fn delete_namespace(namespace_id: NamespaceId) -> Option<()> =
+            |db| db.delete_namespace(namespace_id);
+        /// Renames a namespace via id
+        fn rename_namespace(namespace_id: NamespaceId, name: String) -> Option<()> =
+            |db| db.rename_namespace(namespace_id, &name);
+        /// Adds a namespace to a tag, each tag can only have one namespace
+        fn add_namespace_to_tag(namespace_id: NamespaceId, tag_id: TagId) -> Option<()> =
+            |db| db.add_namespace_to_tag(namespace_id, tag_id);
+        /// Removes the namespace from a tag
+        fn remove_namespace_from_tag(tag_id: TagId) -> Option<()> =
+            |db| db.remove_namespace_from_tag(tag_id);
+        /// Gets tags which have the given namespace
+        fn get_tags_with_namespace(namespace_id: NamespaceId) -> Vec<Tag> =
+            |db| db.get_tags_with_namespace(namespace_id);
+        /// Gets a page of the tags which have the given namespace
+        fn get_tags_with_namespace_page(
+            namespace_id: NamespaceId,
+            options: ListOptions
+        ) -> Option<Page<Tag>> =
+            |db| db.get_tags_with_namespace_page(namespace_id, &options);
+        /// Gets namespaces which don't link to any tags
+        fn get_namespace_orphans() -> Vec<Namespace> = |db| db.get_namespace_orphans();
+        /// Gets a page of the namespaces which don't link to any tags
+        fn get_namespace_orphans_page(options: ListOptions) -> Option<Page<Namespace>> =
+            |db| db.get_namespace_orphans_page(&options);
+        /// Makes images able to only have one tag of the namespace, None allows any number
+        fn set_namespace_exclusive(
+            namespace_id: NamespaceId,
+            exclusive: Option<Exclusivity>
+        ) -> Option<()> = |db| db.set_namespace_exclusive(namespace_id, exclusive);
+        /// Gets the (namespace, image) pairs where an image has more than one tag of an
+        /// exclusive namespace
+        fn get_exclusive_violations() -> Option<Vec<(NamespaceId, ImageId)>> =
+            |db| db.get_exclusive_violations();
+        /// Sets the description of a namespace, None removes it
+        fn set_namespace_description(
+            namespace_id: NamespaceId,
+            description: Option<String>
+        ) -> Option<()> = |db| db.set_namespace_description(namespace_id, description.as_deref());
+        /// Gets all namespaces whose description matches the full text query
+        fn search_namespace_descriptions(query: String) -> Option<Vec<Namespace>> =
+            |db| db.search_namespace_descriptions(&query);
+        /// Gets a page of the namespaces whose description matches the full text query
+        fn search_namespace_descriptions_page(
+            query: String,
+            options: ListOptions
+        ) -> Option<Page<Namespace>> =
+            |db| db.search_namespace_descriptions_page(&query, &options);
+    }
+
+    // history
+    mirror! {
+        /// Sets who or what is making the following changes
+        fn set_actor(actor: Option<String>) -> Option<()> = |db| db.set_actor(actor.as_deref());
+        /// Gets all changes which affected the image
+        fn get_image_history(img_id: ImageId) -> Option<Vec<HistoryEntry>> =
+            |db| db.get_image_history(img_id);
+        /// Gets all changes which affected the tag
+        fn get_tag_history(tag_id: TagId) -> Option<Vec<HistoryEntry>> =
+            |db| db.get_tag_history(tag_id);
+        /// Gets all changes which affected the group
+        fn get_group_history(group_id: GroupId) -> Option<Vec<HistoryEntry>> =
+            |db| db.get_group_history(group_id);
+        /// Gets all changes which affected the saved search
+        fn get_search_history(search_id: SearchId) -> Option<Vec<HistoryEntry>> =
+            |db| db.get_search_history(search_id);
+        /// Gets all changes between the two unix timestamps, `to` is exclusive
+        fn get_history_between(from: i64, to: i64) -> Option<Vec<HistoryEntry>> =
+            |db| db.get_history_between(from, to);
+    }
+
+    // groups
+    mirror! {
+        /// Creates a group and returns its id
+        fn create_group(name: String) -> Option<GroupId> = |db| db.create_group(&name);
+        /// Creates a smart group holding the images matching the query and returns its id
+        fn create_smart_group(name: String, query: String, materialized: bool) -> Option<GroupId> =
+            |db| db.create_smart_group(&name, &query, materialized);
+        /// Changes the query of a group, None turns it into a normal group
+        fn set_group_query(group_id: GroupId, query: Option<String>, materialized: bool) -> Option<()> =
+            |db| db.set_group_query(group_id, query.as_deref(), materialized);
+        /// Stores the current images matching the query of a smart group
+        fn refresh_group(group_id: GroupId) -> Option<()> = |db| db.refresh_group(group_id);
+        /// Refreshes all materialized smart groups
+        fn refresh_groups() -> Option<()> = |db| db.refresh_groups();
+        /// Deletes a group via id, the images stay
+        fn delete_group(group_id: GroupId) -> Option<()> = |db| db.delete_group(group_id);
+        /// Renames a group via id
+        fn rename_group(group_id: GroupId, name: String) -> Option<()> =
+            |db| db.rename_group(group_id, &name);
+        /// Adds an image to a group
+        fn add_image_to_group(group_id: GroupId, img_id: ImageId) -> Option<()> =
+            |db| db.add_image_to_group(group_id, img_id);
+        /// Removes an image from a group
+        fn remove_image_from_group(group_id: GroupId, img_id: ImageId) -> Option<()> =
+            |db| db.remove_image_from_group(group_id, img_id);
+        /// Gets the group with the given id
+        fn get_group(group_id: GroupId) -> Option<Group> = |db| db.get_group(group_id);
+        /// Gets all groups
+        fn get_groups() -> Vec<Group> = |db| db.get_groups();
+        /// Gets a page of all groups
+        fn get_groups_page(options: ListOptions) -> Option<Page<Group>> =
+            |db| db.get_groups_page(&options);
+        /// Gets all images in a group
+        fn get_images_of_group(group_id: GroupId) -> Option<Vec<Image>> =
+            |db| db.get_images_of_group(group_id);
+        /// Gets a page of the images in a group
+        fn get_images_of_group_page(group_id: GroupId, options: ListOptions) -> Option<Page<Image>> =
+            |db| db.get_images_of_group_page(group_id, &options);
+        /// Gets all groups an image is in
+        fn get_groups_of_image(img_id: ImageId) -> Vec<Group> = |db| db.get_groups_of_image(img_id);
+        /// Gets a page of the groups an image is in
+        fn get_groups_of_image_page(img_id: ImageId, options: ListOptions) -> Option<Page<Group>> =
+            |db| db.get_groups_of_image_page(img_id, &options);
+    }
+
+    // properties
+    mirror! {
+        /// Sets a property of the image, replacing the old value of the key
+        fn set_property(img_id: ImageId, key: String, value: String) -> Option<()> =
+            |db| db.set_property(img_id, &key, &value);
+        /// Gets the value of a property of the image
+        fn get_property(img_id: ImageId, key: String) -> Option<String> =
+            |db| db.get_property(img_id, &key);
+        /// Removes a property from the image
+        fn remove_property(img_id: ImageId, key: String) -> Option<()> =
+            |db| db.remove_property(img_id, &key);
+        /// Gets all (key, value) properties of the image sorted by key
+        fn get_properties(img_id: ImageId) -> Option<Vec<(String, String)>> =
+            |db| db.get_properties(img_id);
+        /// Gets all images where the property has exactly the value
+        fn get_images_with_property(key: String, value: String) -> Option<Vec<Image>> =
+            |db| db.get_images_with_property(&key, &value);
+        /// Gets a page of the images where the property has exactly the value
+        fn get_images_with_property_page(
+            key: String,
+            value: String,
+            options: ListOptions
+        ) -> Option<Page<Image>> =
+            |db| db.get_images_with_property_page(&key, &value, &options);
+        /// Gets all images where the value of the property contains the search term
+        fn search_property(key: String, search_term: String) -> Option<Vec<Image>> =
+            |db| db.search_property(&key, &search_term);
+        /// Gets a page of the images where the value of the property contains the search term
+        fn search_property_page(
+            key: String,
+            search_term: String,
+            options: ListOptions
+        ) -> Option<Page<Image>> =
+            |db| db.search_property_page(&key, &search_term, &options);
+    }
+
+    // saved searches
+    mirror! {
+        /// Saves an image query under a unique name and returns its id
+        fn create_search(
+            name: String,
+            query: String,
+            sort: SortBy,
+            descending: bool
+        ) -> Option<SearchId> = |db| db.create_search(&name, &query, sort, descending);
+        /// Renames a saved search
+        fn rename_search(search_id: SearchId, new_name: String) -> Option<()> =
+            |db| db.rename_search(search_id, &new_name);
+        /// Changes the query and order of a saved search
+        fn update_search(
+            search_id: SearchId,
+            query: String,
+            sort: SortBy,
+            descending: bool
+        ) -> Option<()> = |db| db.update_search(search_id, &query, sort, descending);
+        /// Deletes a saved search
+        fn delete_search(search_id: SearchId) -> Option<()> = |db| db.delete_search(search_id);
+        /// Gets the saved search with the given id
+        fn get_search(search_id: SearchId) -> Option<SavedSearch> = |db| db.get_search(search_id);
+        /// Gets the saved search with the given name
+        fn get_search_by_name(name: String) -> Option<SavedSearch> =
+            |db| db.get_search_by_name(&name);
+        /// Gets all saved searches sorted by name
+        fn get_searches() -> Option<Vec<SavedSearch>> = |db| db.get_searches();
+        /// Gets all images matched by a saved search, in the saved order
+        fn run_search(search_id: SearchId) -> Option<Vec<Image>> = |db| db.run_search(search_id);
+        /// Gets a page of the images matched by a saved search
+        fn run_search_page(search_id: SearchId, options: ListOptions) -> Option<Page<Image>> =
+            |db| db.run_search_page(search_id, &options);
+    }
+
+    // values
+    mirror! {
+        /// Sets the value of a tag on an image, None removes it
+        fn set_tag_value(tag_id: TagId, img_id: ImageId, value: Option<TagValue>) -> Option<()> =
+            |db| db.set_tag_value(tag_id, img_id, value);
+        /// Gets the value of a tag on an image
+        fn get_tag_value(tag_id: TagId, img_id: ImageId) -> Option<TagValue> =
+            |db| db.get_tag_value(tag_id, img_id);
+        /// Gets the tags of an image which have a value, with their names and values
+        fn get_tag_values(img_id: ImageId) -> Option<Vec<(TagId, String, TagValue)>> =
+            |db| db.get_tag_values(img_id);
+    }
+
+    // backup, generic over the progress callback so they can't use mirror!
+    /// Copies the database to a file while it stays usable, progress gets called after every
+    /// step
+    pub async fn backup(
+        &self,
+        path: String,
+        progress: impl FnMut(Progress) + Send + 'static,
+    ) -> Option<()> {
+        self.run(move |db| db.backup(&path, progress)).await
+    }
+    /// Copies the database to a new snapshot in the directory and keeps only the newest keep
+    /// snapshots, returns the path of the new one
+    pub async fn backup_rotating(
+        &self,
+        dir: String,
+        keep: usize,
+        progress: impl FnMut(Progress) + Send + 'static,
+    ) -> Option<PathBuf> {
+        self.run(move |db| db.backup_rotating(&dir, keep, progress))
+            .await
+    }
+    /// Replaces the content of the database with a backup, nothing changes if the backup isn't
+    /// a tag database of this or an older version
+    pub async fn restore(
+        &self,
+        path: String,
+        progress: impl FnMut(Progress) + Send + 'static,
+    ) -> Option<()> {
+        self.run(move |db| db.restore(&path, progress)).await
+    }
+
+    // integrity
+    mirror! {
+        /// Finds corruption, rows referring to deleted rows, loops between tags, missing parents
+        /// and images breaking rules or exclusive namespaces
+        fn check_integrity() -> Option<Vec<Problem>> = |db| db.check_integrity();
+        /// Fixes the problems which can be fixed, returns the ones which are left
+        fn repair() -> Option<Vec<Problem>> = |db| db.repair();
+    }
+
+    // sync
+    mirror! {
+        /// Makes this database and the one at the path hold the same tags, images and
+        /// connections between them, with dry_run nothing changes
+        fn sync_with(remote_path: String, dry_run: bool) -> Option<SyncReport> =
+            |db| db.sync_with(&remote_path, dry_run);
+        /// Stores the hash of the files of images which don't have one yet, returns how many
+        /// got hashed
+        fn hash_images() -> Option<usize> = |db| db.hash_images();
+    }
+
+    // rules
+    mirror! {
+        /// Adds a rule whose conditions imply or exclude the target
+        fn create_rule(kind: RuleKind, conditions: Vec<TagId>, target: TagId) -> Option<RuleId> =
+            |db| db.create_rule(kind, &conditions, target);
+        /// Deletes a rule, tags it added stay
+        fn delete_rule(rule_id: RuleId) -> Option<()> = |db| db.delete_rule(rule_id);
+        /// Gets all rules
+        fn get_rules() -> Option<Vec<Rule>> = |db| db.get_rules();
+        /// Adds the tags implied by rules to every image, returns how many got added
+        fn apply_rules() -> Option<usize> = |db| db.apply_rules();
+        /// Gets the images breaking a rule
+        fn get_rule_violations() -> Option<Vec<Violation>> = |db| db.get_rule_violations();
+    }
+}
@@ -4,5 +4,11 @@
 mod tests;
 
 pub mod methods;
+mod pool;
+pub mod store;
+pub mod types;
 
 pub mod wrapper;
+
+#[cfg(feature = "async")]
+pub mod async_wrapper;
@@ -0,0 +1,160 @@
+use crate::{
+    methods::init::{self, SCHEMA_VERSION},
+    pool::BUSY_TIMEOUT,
+};
+use rusqlite::{
+    backup::{Backup, Progress, StepResult},
+    ffi, Connection, OpenFlags, Result,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+/// Pages copied before the source gets unlocked again, so writers aren't blocked for long
+const PAGES_PER_STEP: i32 = 100;
+/// How long to wait when the source or destination is locked by another connection
+const BUSY_PAUSE: Duration = Duration::from_millis(50);
+/// Copies the database to a file while it stays usable, progress gets called after every step.
+/// The copy is written next to the path first, so an existing file at the path is only replaced
+/// by a complete copy
+pub fn backup(path: &Path, mut progress: impl FnMut(Progress), conn: &Connection) -> Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    if partial.exists() {
+        fs::remove_file(&partial).map_err(io_error)?;
+    }
+    {
+        let mut dst = Connection::open(&partial)?;
+        run(&Backup::new(conn, &mut dst)?, &mut progress)?;
+    }
+    fs::rename(&partial, path).map_err(io_error)
+}
+/// Copies the database to a new `snapshot-<unix millis>.db` in the directory and deletes the
+/// oldest snapshots in it until only keep of them are left, the new one always stays. Returns
+/// the path of the new snapshot
+pub fn backup_rotating(
+    dir: &Path,
+    keep: usize,
+    progress: impl FnMut(Progress),
+    conn: &Connection,
+) -> Result<PathBuf> {
+    fs::create_dir_all(dir).map_err(io_error)?;
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let path = dir.join(format!("snapshot-{}.db", millis));
+    backup(&path, progress, conn)?;
+    let mut snapshots: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(io_error)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("snapshot-") && name.ends_with(".db"))
+        })
+        .collect();
+    // same number of digits until the year 2286, so the names sort by age
+    snapshots.sort();
+    let old = snapshots.len().saturating_sub(keep.max(1));
+    for snapshot in &snapshots[..old] {
+        fs::remove_file(snapshot).map_err(io_error)?;
+    }
+    Ok(path)
+}
+/// Replaces the content of the database with a backup, progress gets called after every step.
+/// Fails without changing anything if the file isn't a tag database, is damaged or was written
+/// by a newer version. Backups of older versions get migrated, the backup itself is never written to
+pub fn restore(
+    path: &Path,
+    mut progress: impl FnMut(Progress),
+    conn: &mut Connection,
+) -> Result<()> {
+    // read only, validating mustn't change the backup
+    let src = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    validate(&src)?;
+    run(&Backup::new(&src, conn)?, &mut progress)?;
+    init::init_tables(conn)?;
+    rebuild_broken_indexes(conn)
+}
+/// Checks that the database has the tables of a tag database in a version this can read
+fn validate(conn: &Connection) -> Result<()> {
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > SCHEMA_VERSION {
+        return Err(not_a_backup(format!(
+            "Backup has schema version {}, this version only knows up to {}",
+            version, SCHEMA_VERSION
+        )));
+    }
+    let tables: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master
+        WHERE type='table' AND name IN ('tags', 'images', 'tag_map')",
+        [],
+        |row| row.get(0),
+    )?;
+    if tables < 3 {
+        return Err(not_a_backup("Backup isn't a tag database".to_string()));
+    }
+    // checking the full text indexes needs to write, they get checked after restoring
+    let tables: Vec<String> = conn
+        .prepare(
+            "SELECT name FROM sqlite_master
+            WHERE type='table' AND sql NOT LIKE 'CREATE VIRTUAL TABLE%'",
+        )?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+    for table in tables {
+        let check: String =
+            conn.query_row(&format!("PRAGMA quick_check(\"{}\")", table), [], |row| {
+                row.get(0)
+            })?;
+        if check != "ok" {
+            return Err(not_a_backup(format!("Backup is damaged: {}", check)));
+        }
+    }
+    Ok(())
+}
+/// Rebuilds the full text indexes of descriptions which don't match the descriptions
+fn rebuild_broken_indexes(conn: &Connection) -> Result<()> {
+    for table in ["tags_fts", "namespaces_fts"] {
+        let check = format!("INSERT INTO {table}({table}) VALUES ('integrity-check')");
+        if conn.execute(&check, []).is_err() {
+            conn.execute(
+                &format!("INSERT INTO {table}({table}) VALUES ('rebuild')"),
+                [],
+            )?;
+        }
+    }
+    Ok(())
+}
+/// Steps through the backup until it is done, waiting while the databases are locked
+fn run(backup: &Backup, progress: &mut impl FnMut(Progress)) -> Result<()> {
+    let mut waited = Duration::ZERO;
+    loop {
+        let step = backup.step(PAGES_PER_STEP)?;
+        progress(backup.progress());
+        match step {
+            StepResult::Done => return Ok(()),
+            StepResult::More => waited = Duration::ZERO,
+            _ if waited >= BUSY_TIMEOUT => {
+                return Err(rusqlite::Error::SqliteFailure(
+                    ffi::Error::new(ffi::SQLITE_BUSY),
+                    Some("Database stayed locked during the backup".to_string()),
+                ))
+            }
+            _ => {
+                thread::sleep(BUSY_PAUSE);
+                waited += BUSY_PAUSE;
+            }
+        }
+    }
+}
+fn not_a_backup(msg: String) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_NOTADB), Some(msg))
+}
+fn io_error(err: std::io::Error) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_IOERR), Some(err.to_string()))
+}
@@ -0,0 +1,167 @@
+use crate::{
+    methods::{subtags, tags},
+    types::{GraphFormat, GraphOptions, NamespaceId, Tag, TagId},
+};
+use rusqlite::{Connection, Result};
+use std::collections::{BTreeMap, BTreeSet};
+/// Fill colors of namespaces, picked by the namespace id
+const PALETTE: [&str; 8] = [
+    "#8dd3c7", "#ffffb3", "#bebada", "#fb8072", "#80b1d3", "#fdb462", "#b3de69", "#fccde5",
+];
+/// Font size of tags when they aren't sized by usage and of the least used ones
+const FONT_SIZE: f64 = 14.0;
+/// Renders the parent-child connections of the tags as text in the given format, edges point
+/// from parent to child. Tags without parents or children are included on their own
+pub fn export_hierarchy(
+    format: GraphFormat,
+    options: &GraphOptions,
+    conn: &Connection,
+) -> Result<String> {
+    let tags = match options.root {
+        Some(root) => {
+            let mut ids = subtags::get_descendants(root, conn)?;
+            ids.push(root);
+            ids.sort();
+            ids.dedup();
+            tags::load_tags(&ids, conn)?
+        }
+        None => {
+            let mut tags = Vec::new();
+            tags::for_each_tag(conn, |tag| tags.push(tag))?;
+            tags
+        }
+    };
+    let graph = Graph::new(tags, options);
+    Ok(match format {
+        GraphFormat::Dot => graph.dot(),
+        GraphFormat::Mermaid => graph.mermaid(),
+    })
+}
+/// Tags and the connections between them, ready to be written out
+struct Graph<'a> {
+    tags: Vec<Tag>,
+    edges: BTreeSet<(TagId, TagId)>,
+    /// Namespaces of the tags, for the legend
+    namespaces: BTreeMap<NamespaceId, String>,
+    max_usage: i64,
+    options: &'a GraphOptions,
+}
+impl<'a> Graph<'a> {
+    fn new(tags: Vec<Tag>, options: &'a GraphOptions) -> Self {
+        let ids: BTreeSet<TagId> = tags.iter().map(|tag| tag.id).collect();
+        let edges = tags
+            .iter()
+            .flat_map(|tag| tag.parents.iter().map(move |parent| (*parent, tag.id)))
+            .filter(|(parent, _)| ids.contains(parent))
+            .collect();
+        let namespaces = tags
+            .iter()
+            .filter_map(|tag| Some((tag.namespace_id?, tag.namespace.clone()?)))
+            .collect();
+        let max_usage = tags.iter().map(|tag| tag.image_count).max().unwrap_or(0);
+        Graph {
+            tags,
+            edges,
+            namespaces,
+            max_usage,
+            options,
+        }
+    }
+    fn color(&self, namespace: Option<NamespaceId>) -> Option<&'static str> {
+        let namespace = namespace.filter(|_| self.options.color_namespaces)?;
+        Some(PALETTE[namespace.0.unsigned_abs() as usize % PALETTE.len()])
+    }
+    /// Font size of the tag, from one to three times the normal size by its usage
+    fn font_size(&self, tag: &Tag) -> Option<u32> {
+        if !self.options.size_by_usage || self.max_usage == 0 {
+            return None;
+        }
+        let share = tag.image_count as f64 / self.max_usage as f64;
+        Some((FONT_SIZE * (1.0 + 2.0 * share)).round() as u32)
+    }
+    fn dot(&self) -> String {
+        let mut lines = vec![
+            "digraph tags {".to_string(),
+            "    rankdir=LR;".to_string(),
+            "    node [shape=box, style=\"rounded,filled\", fillcolor=white];".to_string(),
+        ];
+        for tag in &self.tags {
+            let mut attributes = vec![format!("label=\"{}\"", dot_escape(&tag.name))];
+            if let Some(color) = self.color(tag.namespace_id) {
+                attributes.push(format!("fillcolor=\"{}\"", color));
+            }
+            if let Some(size) = self.font_size(tag) {
+                attributes.push(format!("fontsize={}", size));
+            }
+            lines.push(format!("    t{} [{}];", tag.id, attributes.join(", ")));
+        }
+        for (parent, child) in &self.edges {
+            lines.push(format!("    t{} -> t{};", parent, child));
+        }
+        if self.options.color_namespaces && !self.namespaces.is_empty() {
+            lines.push("    subgraph cluster_namespaces {".to_string());
+            lines.push("        label=\"namespaces\";".to_string());
+            for (id, name) in &self.namespaces {
+                lines.push(format!(
+                    "        n{} [label=\"{}\", fillcolor=\"{}\"];",
+                    id,
+                    dot_escape(name),
+                    self.color(Some(*id)).unwrap_or("white")
+                ));
+            }
+            lines.push("    }".to_string());
+        }
+        lines.push("}\n".to_string());
+        lines.join("\n")
+    }
+    fn mermaid(&self) -> String {
+        let mut lines = vec!["flowchart LR".to_string()];
+        for tag in &self.tags {
+            lines.push(format!(
+                "    t{}[\"{}\"]",
+                tag.id,
+                mermaid_escape(&tag.name)
+            ));
+        }
+        for (parent, child) in &self.edges {
+            lines.push(format!("    t{} --> t{}", parent, child));
+        }
+        if self.options.color_namespaces && !self.namespaces.is_empty() {
+            lines.push("    subgraph namespaces".to_string());
+            for (id, name) in &self.namespaces {
+                lines.push(format!("        n{}[\"{}\"]", id, mermaid_escape(name)));
+            }
+            lines.push("    end".to_string());
+            for id in self.namespaces.keys() {
+                if let Some(color) = self.color(Some(*id)) {
+                    lines.push(format!("    style n{} fill:{}", id, color));
+                }
+            }
+        }
+        for tag in &self.tags {
+            let mut style = Vec::new();
+            if let Some(color) = self.color(tag.namespace_id) {
+                style.push(format!("fill:{}", color));
+            }
+            if let Some(size) = self.font_size(tag) {
+                style.push(format!("font-size:{}px", size));
+            }
+            if !style.is_empty() {
+                lines.push(format!("    style t{} {}", tag.id, style.join(",")));
+            }
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+}
+/// Escapes a label inside double quotes in DOT
+fn dot_escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+/// Escapes a label inside double quotes in Mermaid, which uses HTML entity codes
+fn mermaid_escape(label: &str) -> String {
+    label.replace('"', "#quot;").replace('\n', " ")
+}
@@ -1 +1,302 @@
-
+use crate::{
+    methods::{
+        history::{self, Change, Operation},
+        images,
+        listing::{self, Table},
+        utils::{
+            self,
+            macros::{handle_unique, insert_ret_id},
+        },
+    },
+    types::{Group, GroupId, ImageId, ListOptions, Page},
+};
+use rusqlite::{types::Value, Connection, OptionalExtension, Result};
+/// Adds a group into the groups table, returns id if successful
+pub fn add_group(name: &str, conn: &Connection) -> Option<GroupId> {
+    utils::savepoint(conn, || {
+        let id = GroupId(insert_ret_id!(
+            conn.execute("INSERT INTO groups(name) VALUES (?1)", [name]),
+            &conn
+        )?);
+        record_group(Operation::AddGroup, id, None, None, conn).ok()?;
+        Some(id)
+    })
+}
+fn record_group(
+    operation: Operation,
+    group_id: GroupId,
+    img_id: Option<ImageId>,
+    previous: Option<String>,
+    conn: &Connection,
+) -> Result<()> {
+    let change = Change {
+        group_id: Some(group_id),
+        img_id,
+        previous,
+        ..Default::default()
+    };
+    history::record(operation, change, conn)
+}
+/// Removes the group, the images stay
+pub fn remove_group(group_id: GroupId, conn: &Connection) -> Result<()> {
+    if get_group_name(group_id, conn).is_some() {
+        record_group(Operation::RemoveGroup, group_id, None, None, conn)?;
+    }
+    conn.execute("DELETE FROM groups WHERE id=?1", [group_id])?;
+    Ok(())
+}
+/// Renames the group
+pub fn rename_group(group_id: GroupId, new_name: &str, conn: &Connection) -> Result<()> {
+    let previous = get_group_name(group_id, conn);
+    let changed = conn.execute(
+        "UPDATE groups SET name=?1 WHERE id=?2",
+        (new_name, group_id),
+    )?;
+    if changed > 0 {
+        record_group(Operation::RenameGroup, group_id, None, previous, conn)?;
+    }
+    Ok(())
+}
+/// Gets the name of the group
+pub fn get_group_name(group_id: GroupId, conn: &Connection) -> Option<String> {
+    conn.query_row("SELECT name FROM groups WHERE id=?1", [group_id], |row| {
+        row.get(0)
+    })
+    .ok()
+}
+/// Gets the id of the group with the given name
+pub fn get_group_by_name(name: &str, conn: &Connection) -> Option<GroupId> {
+    conn.query_row("SELECT id FROM groups WHERE name=?1", [name], |row| {
+        row.get(0)
+    })
+    .ok()
+}
+/// Adds the image to the group, adding it twice does nothing
+pub fn add_image_to_group(group_id: GroupId, img_id: ImageId, conn: &Connection) -> Result<()> {
+    if handle_unique!(conn.execute(
+        "INSERT INTO group_map(group_id, img_id) VALUES (?1, ?2)",
+        (group_id, img_id),
+    ))? == 1
+    {
+        record_group(Operation::GroupImage, group_id, Some(img_id), None, conn)?;
+    }
+    Ok(())
+}
+/// Removes the image from the group
+pub fn remove_image_from_group(
+    group_id: GroupId,
+    img_id: ImageId,
+    conn: &Connection,
+) -> Result<()> {
+    if conn.execute(
+        "DELETE FROM group_map WHERE group_id=?1 AND img_id=?2",
+        (group_id, img_id),
+    )? > 0
+    {
+        record_group(Operation::UngroupImage, group_id, Some(img_id), None, conn)?;
+    }
+    Ok(())
+}
+/// Gets the ids and paths of all images in the group
+pub fn get_images_of_group(group_id: GroupId, conn: &Connection) -> Result<Vec<(ImageId, String)>> {
+    Ok(get_images_of_group_page(group_id, &ListOptions::default(), conn)?.items)
+}
+/// Gets a page of the images in the group
+pub fn get_images_of_group_page(
+    group_id: GroupId,
+    options: &ListOptions,
+    conn: &Connection,
+) -> Result<Page<(ImageId, String)>> {
+    let (filter, params) = members_filter(group_id, conn)?;
+    listing::list(Table::Images, &filter, params, options, conn)
+}
+/// Gets the ids and names of all groups the image is in
+pub fn get_groups_of_image(img_id: ImageId, conn: &Connection) -> Result<Vec<(GroupId, String)>> {
+    Ok(get_groups_of_image_page(img_id, &ListOptions::default(), conn)?.items)
+}
+/// Gets a page of the groups the image is in
+pub fn get_groups_of_image_page(
+    img_id: ImageId,
+    options: &ListOptions,
+    conn: &Connection,
+) -> Result<Page<(GroupId, String)>> {
+    // smart groups which aren't materialized get checked one by one
+    let mut smart: Vec<String> = Vec::new();
+    for (group_id, query) in live_groups(conn)? {
+        let (filter, mut params) = images::smart_group_filter(group_id, &query, conn);
+        params.push(img_id.0.into());
+        let sql = format!(
+            "SELECT id FROM images t WHERE t.id=?{} AND {}",
+            params.len(),
+            filter
+        );
+        if conn
+            .prepare(&sql)?
+            .exists(rusqlite::params_from_iter(params))?
+        {
+            smart.push(group_id.0.to_string());
+        }
+    }
+    listing::list(
+        Table::Groups,
+        &format!(
+            "((t.query IS NULL OR t.materialized) AND EXISTS (SELECT img_id FROM group_map
+                WHERE group_map.group_id=t.id AND group_map.img_id=?1))
+            OR t.id IN ({})",
+            smart.join(", ")
+        ),
+        vec![img_id.0.into()],
+        options,
+        conn,
+    )
+}
+/// Gets the ids and names of all groups
+pub fn get_groups(conn: &Connection) -> Result<Vec<(GroupId, String)>> {
+    Ok(get_groups_page(&ListOptions::default(), conn)?.items)
+}
+/// Gets a page of all groups
+pub fn get_groups_page(
+    options: &ListOptions,
+    conn: &Connection,
+) -> Result<Page<(GroupId, String)>> {
+    listing::list(Table::Groups, "1", vec![], options, conn)
+}
+/// Gets the full groups for the given ids, missing ones get skipped
+pub fn load_groups(ids: &[GroupId], conn: &Connection) -> Result<Vec<Group>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name,
+            (SELECT COUNT(*) FROM group_map WHERE group_map.group_id=groups.id),
+            (SELECT MAX(timestamp) FROM history
+                WHERE operation='add_group' AND history.group_id=groups.id),
+            query, materialized
+        FROM groups WHERE id=?1",
+    )?;
+    let mut res: Vec<Group> = Vec::new();
+    for id in ids {
+        let mut rows = stmt.query_map([id], |row| {
+            Ok(Group {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                image_count: row.get(2)?,
+                created: row.get(3)?,
+                query: row.get(4)?,
+                materialized: row.get(5)?,
+            })
+        })?;
+        if let Some(group) = rows.next() {
+            let mut group = group?;
+            if let (Some(query), false) = (&group.query, group.materialized) {
+                let (filter, params) = images::smart_group_filter(group.id, query, conn);
+                group.image_count = conn.query_row(
+                    &format!("SELECT COUNT(*) FROM images t WHERE {}", filter),
+                    rusqlite::params_from_iter(params),
+                    |row| row.get(0),
+                )?;
+            }
+            res.push(group);
+        }
+    }
+    Ok(res)
+}
+/// Adds a smart group whose members are the images matching the query, returns id if
+/// successful. Materialized groups only change when they get refreshed
+pub fn add_smart_group(
+    name: &str,
+    query: &str,
+    materialized: bool,
+    conn: &Connection,
+) -> Option<GroupId> {
+    utils::savepoint(conn, || {
+        let id = GroupId(insert_ret_id!(
+            conn.execute(
+                "INSERT INTO groups(name, query, materialized) VALUES (?1, ?2, ?3)",
+                (name, query, materialized),
+            ),
+            &conn
+        )?);
+        record_group(Operation::AddGroup, id, None, None, conn).ok()?;
+        Some(id)
+    })
+}
+/// Changes the query of a group, None turns it into a static group keeping the images it had
+/// when it was last refreshed
+pub fn set_group_query(
+    group_id: GroupId,
+    query: Option<&str>,
+    materialized: bool,
+    conn: &Connection,
+) -> Result<()> {
+    let previous: Option<String> = conn
+        .query_row("SELECT query FROM groups WHERE id=?1", [group_id], |row| {
+            row.get(0)
+        })
+        .optional()?
+        .flatten();
+    let changed = conn.execute(
+        "UPDATE groups SET query=?1, materialized=?2 WHERE id=?3",
+        (query, materialized, group_id),
+    )?;
+    if changed > 0 {
+        record_group(Operation::SetGroupQuery, group_id, None, previous, conn)?;
+    }
+    Ok(())
+}
+/// Stores the current members of a smart group, static groups stay the same
+pub fn refresh_group(group_id: GroupId, conn: &mut Connection) -> Result<()> {
+    let tx = conn.transaction()?;
+    let query: Option<String> =
+        tx.query_row("SELECT query FROM groups WHERE id=?1", [group_id], |row| {
+            row.get(0)
+        })?;
+    if let Some(query) = query {
+        let (filter, mut params) = images::smart_group_filter(group_id, &query, &tx);
+        tx.execute("DELETE FROM group_map WHERE group_id=?1", [group_id])?;
+        params.push(group_id.0.into());
+        tx.execute(
+            &format!(
+                "INSERT INTO group_map(group_id, img_id) SELECT ?{}, t.id FROM images t WHERE {}",
+                params.len(),
+                filter
+            ),
+            rusqlite::params_from_iter(params),
+        )?;
+    }
+    tx.commit()
+}
+/// Refreshes all materialized smart groups
+pub fn refresh_groups(conn: &mut Connection) -> Result<()> {
+    let ids: Vec<GroupId> = conn
+        .prepare("SELECT id FROM groups WHERE query IS NOT NULL AND materialized")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+    for id in ids {
+        refresh_group(id, conn)?;
+    }
+    Ok(())
+}
+/// Filter for the images of a group, smart groups which aren't materialized use their query
+fn members_filter(group_id: GroupId, conn: &Connection) -> Result<(String, Vec<Value>)> {
+    let group: Option<(Option<String>, bool)> = conn
+        .query_row(
+            "SELECT query, materialized FROM groups WHERE id=?1",
+            [group_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    Ok(match group {
+        Some((Some(query), false)) => images::smart_group_filter(group_id, &query, conn),
+        _ => (
+            "EXISTS (SELECT img_id FROM group_map
+                WHERE group_map.img_id=t.id AND group_map.group_id=?1)"
+                .to_owned(),
+            vec![group_id.0.into()],
+        ),
+    })
+}
+/// Gets the ids and queries of the smart groups which aren't materialized
+fn live_groups(conn: &Connection) -> Result<Vec<(GroupId, String)>> {
+    let mut stmt =
+        conn.prepare("SELECT id, query FROM groups WHERE query IS NOT NULL AND NOT materialized")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
@@ -0,0 +1,326 @@
+use crate::{
+    methods::utils,
+    types::{GroupId, ImageId, NamespaceId, SearchId, TagId},
+};
+use rusqlite::{Connection, OptionalExtension, Result, Row};
+use std::{fmt, str::FromStr};
+
+/// The kind of mutation a history entry describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Operation {
+    AddTag,
+    RemoveTag,
+    RenameTag,
+    AddImage,
+    RemoveImage,
+    UpdatePath,
+    TagImage,
+    UntagImage,
+    AddNamespace,
+    RemoveNamespace,
+    RenameNamespace,
+    AddNamespaceToTag,
+    RemoveNamespaceFromTag,
+    AddParent,
+    RemoveConnection,
+    AddGroup,
+    RemoveGroup,
+    RenameGroup,
+    GroupImage,
+    UngroupImage,
+    SetValue,
+    SetProperty,
+    RemoveProperty,
+    SetDescription,
+    MergeTag,
+    AddSearch,
+    RenameSearch,
+    UpdateSearch,
+    RemoveSearch,
+    SetGroupQuery,
+    AddAlias,
+    RemoveAlias,
+    AddRule,
+    RemoveRule,
+    SetExclusive,
+}
+impl Operation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::AddTag => "add_tag",
+            Operation::RemoveTag => "remove_tag",
+            Operation::RenameTag => "rename_tag",
+            Operation::AddImage => "add_image",
+            Operation::RemoveImage => "remove_image",
+            Operation::UpdatePath => "update_path",
+            Operation::TagImage => "tag_image",
+            Operation::UntagImage => "untag_image",
+            Operation::AddNamespace => "add_namespace",
+            Operation::RemoveNamespace => "remove_namespace",
+            Operation::RenameNamespace => "rename_namespace",
+            Operation::AddNamespaceToTag => "add_namespace_to_tag",
+            Operation::RemoveNamespaceFromTag => "remove_namespace_from_tag",
+            Operation::AddParent => "add_parent",
+            Operation::RemoveConnection => "remove_connection",
+            Operation::AddGroup => "add_group",
+            Operation::RemoveGroup => "remove_group",
+            Operation::RenameGroup => "rename_group",
+            Operation::GroupImage => "group_image",
+            Operation::UngroupImage => "ungroup_image",
+            Operation::SetValue => "set_value",
+            Operation::SetProperty => "set_property",
+            Operation::RemoveProperty => "remove_property",
+            Operation::SetDescription => "set_description",
+            Operation::MergeTag => "merge_tag",
+            Operation::AddSearch => "add_search",
+            Operation::RenameSearch => "rename_search",
+            Operation::UpdateSearch => "update_search",
+            Operation::RemoveSearch => "remove_search",
+            Operation::SetGroupQuery => "set_group_query",
+            Operation::AddAlias => "add_alias",
+            Operation::RemoveAlias => "remove_alias",
+            Operation::AddRule => "add_rule",
+            Operation::RemoveRule => "remove_rule",
+            Operation::SetExclusive => "set_exclusive",
+        }
+    }
+}
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+impl FromStr for Operation {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "add_tag" => Operation::AddTag,
+            "remove_tag" => Operation::RemoveTag,
+            "rename_tag" => Operation::RenameTag,
+            "add_image" => Operation::AddImage,
+            "remove_image" => Operation::RemoveImage,
+            "update_path" => Operation::UpdatePath,
+            "tag_image" => Operation::TagImage,
+            "untag_image" => Operation::UntagImage,
+            "add_namespace" => Operation::AddNamespace,
+            "remove_namespace" => Operation::RemoveNamespace,
+            "rename_namespace" => Operation::RenameNamespace,
+            "add_namespace_to_tag" => Operation::AddNamespaceToTag,
+            "remove_namespace_from_tag" => Operation::RemoveNamespaceFromTag,
+            "add_parent" => Operation::AddParent,
+            "remove_connection" => Operation::RemoveConnection,
+            "add_group" => Operation::AddGroup,
+            "remove_group" => Operation::RemoveGroup,
+            "rename_group" => Operation::RenameGroup,
+            "group_image" => Operation::GroupImage,
+            "ungroup_image" => Operation::UngroupImage,
+            "set_value" => Operation::SetValue,
+            "set_property" => Operation::SetProperty,
+            "remove_property" => Operation::RemoveProperty,
+            "set_description" => Operation::SetDescription,
+            "merge_tag" => Operation::MergeTag,
+            "add_search" => Operation::AddSearch,
+            "rename_search" => Operation::RenameSearch,
+            "update_search" => Operation::UpdateSearch,
+            "remove_search" => Operation::RemoveSearch,
+            "set_group_query" => Operation::SetGroupQuery,
+            "add_alias" => Operation::AddAlias,
+            "remove_alias" => Operation::RemoveAlias,
+            "add_rule" => Operation::AddRule,
+            "remove_rule" => Operation::RemoveRule,
+            "set_exclusive" => Operation::SetExclusive,
+            _ => return Err(format!("Unknown history operation {}", s)),
+        })
+    }
+}
+
+/// Ids affected by a mutation, names are looked up when the change gets recorded
+#[derive(Debug, Default, Clone)]
+pub struct Change {
+    pub img_id: Option<ImageId>,
+    pub tag_id: Option<TagId>,
+    pub namespace_id: Option<NamespaceId>,
+    pub parent_id: Option<TagId>,
+    pub group_id: Option<GroupId>,
+    pub search_id: Option<SearchId>,
+    /// Old name or path for renames, old value or description when setting one, key or old
+    /// `key=value` of properties, name of the tag merged into another one, old query of a saved
+    /// search or group, the name of an alias, kind and conditions of a rule,
+    /// old exclusivity of a namespace
+    pub previous: Option<String>,
+}
+
+/// A row of the history table
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HistoryEntry {
+    pub id: i64,
+    /// Unix timestamp (seconds, UTC)
+    pub timestamp: i64,
+    pub operation: Operation,
+    pub img_id: Option<ImageId>,
+    pub img_path: Option<String>,
+    pub tag_id: Option<TagId>,
+    pub tag_name: Option<String>,
+    pub namespace_id: Option<NamespaceId>,
+    pub namespace_name: Option<String>,
+    pub parent_id: Option<TagId>,
+    pub parent_name: Option<String>,
+    pub previous: Option<String>,
+    pub actor: Option<String>,
+    pub group_id: Option<GroupId>,
+    pub group_name: Option<String>,
+    pub search_id: Option<SearchId>,
+    pub search_name: Option<String>,
+}
+impl HistoryEntry {
+    fn from_row(row: &Row) -> Result<Self> {
+        let operation: String = row.get(2)?;
+        Ok(HistoryEntry {
+            id: row.get(0)?,
+            timestamp: row.get(1)?,
+            operation: operation.parse().map_err(|_| {
+                rusqlite::Error::InvalidColumnType(
+                    2,
+                    "operation".to_owned(),
+                    rusqlite::types::Type::Text,
+                )
+            })?,
+            img_id: row.get(3)?,
+            img_path: row.get(4)?,
+            tag_id: row.get(5)?,
+            tag_name: row.get(6)?,
+            namespace_id: row.get(7)?,
+            namespace_name: row.get(8)?,
+            parent_id: row.get(9)?,
+            parent_name: row.get(10)?,
+            previous: row.get(11)?,
+            actor: row.get(12)?,
+            group_id: row.get(13)?,
+            group_name: row.get(14)?,
+            search_id: row.get(15)?,
+            search_name: row.get(16)?,
+        })
+    }
+}
+impl fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            utils::format_timestamp(self.timestamp),
+            self.operation
+        )?;
+        if let Some(path) = &self.img_path {
+            write!(f, " image '{}'", path)?;
+        }
+        if let Some(name) = &self.tag_name {
+            write!(f, " tag '{}'", name)?;
+        }
+        if let Some(name) = &self.namespace_name {
+            write!(f, " namespace '{}'", name)?;
+        }
+        if let Some(name) = &self.parent_name {
+            write!(f, " parent '{}'", name)?;
+        }
+        if let Some(name) = &self.group_name {
+            write!(f, " group '{}'", name)?;
+        }
+        if let Some(name) = &self.search_name {
+            write!(f, " search '{}'", name)?;
+        }
+        if let Some(previous) = &self.previous {
+            write!(f, " (was '{}')", previous)?;
+        }
+        if let Some(actor) = &self.actor {
+            write!(f, " by {}", actor)?;
+        }
+        Ok(())
+    }
+}
+
+const SELECT_ENTRIES: &str = "SELECT id, timestamp, operation, img_id, img_path, tag_id, tag_name,
+    namespace_id, namespace_name, parent_id, parent_name, previous, actor, group_id, group_name,
+    search_id, search_name
+    FROM history";
+
+/// Sets the actor which gets stored with every change made through this connection,
+/// None clears it
+pub fn set_actor(actor: Option<&str>, conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TEMP TABLE IF NOT EXISTS history_actor (actor TEXT)",
+        (),
+    )?;
+    conn.execute("DELETE FROM temp.history_actor", ())?;
+    if let Some(actor) = actor {
+        conn.execute("INSERT INTO temp.history_actor(actor) VALUES (?1)", [actor])?;
+    }
+    Ok(())
+}
+/// Gets the actor set for this connection
+pub fn get_actor(conn: &Connection) -> Option<String> {
+    conn.query_row("SELECT actor FROM temp.history_actor", (), |row| row.get(0))
+        .optional()
+        .unwrap_or(None)
+}
+/// Stores a change in the history table, names of the given ids get resolved at the time of
+/// recording so they need to be recorded before deleting the rows
+pub fn record(operation: Operation, change: Change, conn: &Connection) -> Result<()> {
+    conn.execute(
+        "INSERT INTO history(timestamp, operation, img_id, img_path, tag_id, tag_name,
+            namespace_id, namespace_name, parent_id, parent_name, previous, actor,
+            group_id, group_name, search_id, search_name)
+        VALUES (
+            CAST(strftime('%s', 'now') AS INTEGER), ?1,
+            ?2, (SELECT path FROM images WHERE id=?2),
+            ?3, (SELECT name FROM tags WHERE id=?3),
+            ?4, (SELECT name FROM namespaces WHERE id=?4),
+            ?5, (SELECT name FROM tags WHERE id=?5),
+            ?6, ?7,
+            ?8, (SELECT name FROM groups WHERE id=?8),
+            ?9, (SELECT name FROM saved_searches WHERE id=?9))",
+        rusqlite::params![
+            operation.as_str(),
+            change.img_id,
+            change.tag_id,
+            change.namespace_id,
+            change.parent_id,
+            change.previous,
+            get_actor(conn),
+            change.group_id,
+            change.search_id,
+        ],
+    )?;
+    Ok(())
+}
+fn get_entries(
+    filter: &str,
+    params: impl rusqlite::Params,
+    conn: &Connection,
+) -> Result<Vec<HistoryEntry>> {
+    let mut stmt = conn.prepare(&format!("{} WHERE {} ORDER BY id", SELECT_ENTRIES, filter))?;
+    let rows = stmt.query_map(params, HistoryEntry::from_row)?;
+    rows.collect()
+}
+/// Gets all changes which affected the given image
+pub fn get_history_of_image(img_id: ImageId, conn: &Connection) -> Result<Vec<HistoryEntry>> {
+    get_entries("img_id=?1", [img_id], conn)
+}
+/// Gets all changes which affected the given tag, either directly or as a parent
+pub fn get_history_of_tag(tag_id: TagId, conn: &Connection) -> Result<Vec<HistoryEntry>> {
+    get_entries("tag_id=?1 OR parent_id=?1", [tag_id], conn)
+}
+/// Gets all changes which affected the given group
+pub fn get_history_of_group(group_id: GroupId, conn: &Connection) -> Result<Vec<HistoryEntry>> {
+    get_entries("group_id=?1", [group_id], conn)
+}
+/// Gets all changes which affected the given saved search
+pub fn get_history_of_search(search_id: SearchId, conn: &Connection) -> Result<Vec<HistoryEntry>> {
+    get_entries("search_id=?1", [search_id], conn)
+}
+/// Gets all changes made from `from` (inclusive) until `to` (exclusive), both unix timestamps
+pub fn get_history_between(from: i64, to: i64, conn: &Connection) -> Result<Vec<HistoryEntry>> {
+    get_entries("timestamp>=?1 AND timestamp<?2", [from, to], conn)
+}
@@ -1,130 +1,355 @@
-use crate::methods::utils::{
-    self,
-    macros::{handle_unique, insert_ret_id},
+use crate::{
+    methods::{
+        history::{self, Change, Operation},
+        listing::{self, Table},
+        tags,
+        utils::{
+            self,
+            macros::{handle_unique, insert_ret_id},
+        },
+        values,
+    },
+    types::{Comparison, GroupId, Image, ImageId, ListOptions, Page, TagId, TagValue},
 };
-use rusqlite::{Connection, Result};
+use rusqlite::{types::Value, Connection, Result, Row};
 /// Adds an image into the images table, returns id if successful
-pub fn add_image(path: &str, conn: &Connection) -> Option<i64> {
-    insert_ret_id!(
-        conn.execute("INSERT INTO images(path) VALUES (?1)", [path]),
-        &conn
-    )
+pub fn add_image(path: &str, conn: &Connection) -> Option<ImageId> {
+    utils::savepoint(conn, || {
+        let id = ImageId(insert_ret_id!(
+            conn.execute("INSERT INTO images(path) VALUES (?1)", [path]),
+            &conn
+        )?);
+        let change = Change {
+            img_id: Some(id),
+            ..Default::default()
+        };
+        history::record(Operation::AddImage, change, conn).ok()?;
+        Some(id)
+    })
 }
 /// Removes an image from the images table via path, delete cascades
 pub fn remove_image_path(path: &str, conn: &Connection) -> Result<()> {
+    if let Some(id) = get_image_by_path(path, conn) {
+        let change = Change {
+            img_id: Some(id),
+            ..Default::default()
+        };
+        history::record(Operation::RemoveImage, change, conn)?;
+    }
     conn.execute("DELETE FROM images WHERE path=?1", [path])?;
     Ok(())
 }
+/// Filters images on having the tag ?1
+const WITH_TAG: &str =
+    "EXISTS (SELECT id FROM tag_map WHERE tag_map.img_id=t.id AND tag_map.tag_id=?1)";
+/// Filters tags on being linked with the image ?1
+const TAGS_OF_IMG: &str =
+    "EXISTS (SELECT id FROM tag_map WHERE tag_map.tag_id=t.id AND tag_map.img_id=?1)";
+/// Filters images without any tag
+const ORPHANS: &str = "NOT EXISTS (SELECT id FROM tag_map WHERE tag_map.img_id=t.id)";
 /// Gets all images which have a given tag
-pub fn get_images_with_tag(tag_id: i64, conn: &Connection) -> Result<Vec<(i64, String)>> {
-    let mut stmt = conn.prepare(
-        "SELECT (images.id, images.path)
-            FROM images
-            JOIN tag_map
-            WHERE tag_map.tag_id=?1
-            AND tag_map.img_id=images.id",
-    )?;
-    let mut res: Vec<(i64, String)> = vec![];
-    let q = stmt.query_map([tag_id], |row| {
-        Ok((row.get::<usize, i64>(0)?, row.get::<usize, String>(1)?))
-    })?;
-    for img in q {
-        res.push(img.unwrap());
-    }
-    Ok(res)
+pub fn get_images_with_tag(tag_id: TagId, conn: &Connection) -> Result<Vec<(ImageId, String)>> {
+    Ok(get_images_with_tag_page(tag_id, &ListOptions::default(), conn)?.items)
+}
+/// Gets a page of the images which have a given tag
+pub fn get_images_with_tag_page(
+    tag_id: TagId,
+    options: &ListOptions,
+    conn: &Connection,
+) -> Result<Page<(ImageId, String)>> {
+    listing::list(
+        Table::Images,
+        WITH_TAG,
+        vec![tag_id.0.into()],
+        options,
+        conn,
+    )
 }
 /// Returns a vector of tag ids and names which are linked with the given image
-pub fn get_tags_of_img(img_id: i64, conn: &Connection) -> Vec<(i64, String)> {
-    let mut stmt = conn
-        .prepare(
-            "SELECT tag_map.tag_id, tags.name
-            FROM tag_map
-            INNER JOIN tags
-            WHERE tag_map.img_id=?1
-            AND tags.id=tag_map.tag_id",
-        )
-        .unwrap();
-    let q = stmt
-        .query_map([img_id], |row| Ok((row.get(0)?, row.get(1)?)))
-        .unwrap();
-    let mut res: Vec<(i64, String)> = vec![];
-    for id in q {
-        res.push(id.unwrap());
-    }
-    res
+pub fn get_tags_of_img(img_id: ImageId, conn: &Connection) -> Vec<(TagId, String)> {
+    get_tags_of_img_page(img_id, &ListOptions::default(), conn)
+        .unwrap()
+        .items
+}
+/// Gets a page of the tags which are linked with the given image
+pub fn get_tags_of_img_page(
+    img_id: ImageId,
+    options: &ListOptions,
+    conn: &Connection,
+) -> Result<Page<(TagId, String)>> {
+    listing::list(
+        Table::Tags,
+        TAGS_OF_IMG,
+        vec![img_id.0.into()],
+        options,
+        conn,
+    )
 }
 /// Gets the path of the given image id or None
-pub fn get_path(img_id: i64, conn: &Connection) -> Option<String> {
+pub fn get_path(img_id: ImageId, conn: &Connection) -> Option<String> {
     let mut stmt = conn.prepare("SELECT path FROM images WHERE id=?1").unwrap();
-    match stmt.query_row([img_id], |row| row.get(0)) {
-        Ok(id) => Some(id),
-        Err(_) => None,
-    }
+    stmt.query_row([img_id], |row| row.get(0)).ok()
+}
+/// Gets the id of the image with the given path
+pub fn get_image_by_path(path: &str, conn: &Connection) -> Option<ImageId> {
+    conn.query_row("SELECT id FROM images WHERE path=?1", [path], |row| {
+        row.get(0)
+    })
+    .ok()
 }
 /// Updates the path of a given image id with a new one
-pub fn update_path(id: i64, new_path: &str, conn: &Connection) -> Result<()> {
-    conn.execute(
-        "UPDATE images SET path=?1 WHERE id=?2",
-        [new_path, &format!("{}", id)],
-    )?;
+pub fn update_path(id: ImageId, new_path: &str, conn: &Connection) -> Result<()> {
+    let previous = get_path(id, conn);
+    let changed = conn.execute("UPDATE images SET path=?1 WHERE id=?2", (new_path, id))?;
+    if changed > 0 {
+        let change = Change {
+            img_id: Some(id),
+            previous,
+            ..Default::default()
+        };
+        history::record(Operation::UpdatePath, change, conn)?;
+    }
     Ok(())
 }
 /// Returns a vector of image ids and paths which aren't connected to any tags
-pub fn get_orphans(conn: &Connection) -> Vec<(i64, String)> {
-    let mut stmt = conn
-        .prepare(
-            "
-            SELECT id, path
-            FROM images
-            WHERE NOT EXISTS (
-                SELECT id
-                FROM tag_map
-                WHERE tag_map.img_id=images.id
-            )
-        ",
-        )
-        .unwrap();
-    let mut res: Vec<(i64, String)> = Vec::new();
-    for orphan in stmt
-        .query_map([], |row| Ok((row.get(0).unwrap(), row.get(1).unwrap())))
+pub fn get_orphans(conn: &Connection) -> Vec<(ImageId, String)> {
+    get_orphans_page(&ListOptions::default(), conn)
         .unwrap()
-    {
-        res.push(orphan.unwrap());
-    }
-    res
-}
-pub fn query_sql(query: &str, conn: &Connection) -> Vec<(i64, String)> {
-    let tokens = query.split(' ');
-    let mut constructed = String::new();
-    let mut res: Vec<(i64, String)> = vec![];
-    constructed.push_str("SELECT img.id, img.path FROM images img ");
-    let mut tag_id: i64;
-
-    //let mut exclude: Vec<&str> = vec![];
-    let mut joins = 0;
-    for token in tokens {
-        //if token.starts_with('!') {
-        //    exclude.push(&token[1..]); // exclude the exclamation mark
-        //}
-        if let Some(id) = utils::get_id("tags", &format!("name='{}'", token), conn) {
-            constructed.push_str(&format!(
-                "JOIN tag_map t{} ON img.id = t{}.img_id AND t{}.tag_id = '{}'",
-                joins, joins, joins, id
+        .items
+}
+/// Gets a page of the images which aren't connected to any tags
+pub fn get_orphans_page(
+    options: &ListOptions,
+    conn: &Connection,
+) -> Result<Page<(ImageId, String)>> {
+    listing::list(Table::Images, ORPHANS, vec![], options, conn)
+}
+/// Gets all images which have every known tag of the space separated query, unknown tags get
+/// ignored. `tag<op>value` with one of `= != < <= > >=` or `tag=low..high` also compares the
+/// value of the tag, `added:`, `updated:` and `tagged:` followed by a date or a comparison
+/// filter by time and `in:group` keeps the images of a group
+pub fn query_sql(query: &str, conn: &Connection) -> Vec<(ImageId, String)> {
+    query_sql_page(query, &ListOptions::default(), conn)
+        .unwrap()
+        .items
+}
+/// Gets a page of the images matching the query, see [`query_sql`]
+pub fn query_sql_page(
+    query: &str,
+    options: &ListOptions,
+    conn: &Connection,
+) -> Result<Page<(ImageId, String)>> {
+    let (filter, params) = query_filter(query, conn);
+    listing::list(Table::Images, &filter, params, options, conn)
+}
+/// Selects the columns of a full image, `images` is the selected table
+const SELECT_IMAGES: &str = "SELECT id, path,
+        (SELECT COUNT(*) FROM tag_map WHERE tag_map.img_id=images.id), created, updated
+    FROM images";
+fn image_from_row(row: &Row) -> Result<Image> {
+    Ok(Image {
+        id: row.get(0)?,
+        path: row.get(1)?,
+        tag_count: row.get(2)?,
+        created: row.get(3)?,
+        updated: row.get(4)?,
+    })
+}
+/// Builds the filter for the images having every known tag of the query, tokens like
+/// `rating>=4` also compare the value of the tag
+fn query_filter(query: &str, conn: &Connection) -> (String, Vec<Value>) {
+    let mut params: Vec<Value> = vec![];
+    let filter = build_filter(query, &mut params, &mut vec![], conn);
+    (filter, params)
+}
+/// Builds the filter for the members of a smart group with the given query
+pub(crate) fn smart_group_filter(
+    group_id: GroupId,
+    query: &str,
+    conn: &Connection,
+) -> (String, Vec<Value>) {
+    let mut params: Vec<Value> = vec![];
+    let filter = build_filter(query, &mut params, &mut vec![group_id], conn);
+    (filter, params)
+}
+/// Builds the filter of a query, groups are the smart groups whose queries are being inlined
+fn build_filter(
+    query: &str,
+    params: &mut Vec<Value>,
+    groups: &mut Vec<GroupId>,
+    conn: &Connection,
+) -> String {
+    let mut filter = String::from("1");
+    for token in query.split(' ') {
+        if let Some(condition) = date_filter(token, params) {
+            filter.push_str(&format!(" AND {}", condition));
+            continue;
+        }
+        if let Some(name) = token.strip_prefix("in:") {
+            let condition = group_filter(name, params, groups, conn);
+            filter.push_str(&format!(" AND {}", condition));
+            continue;
+        }
+        let (name, comparison) = match values::parse_condition(token) {
+            Some((name, comparison)) => (name, Some(comparison)),
+            None => (token, None),
+        };
+        if let Some(id) = tags::resolve(name, conn) {
+            params.push(id.0.into());
+            let tag_param = params.len();
+            let value_condition = comparison.map_or(String::new(), |comparison| {
+                format!(" AND {}", values::condition(&comparison, params))
+            });
+            filter.push_str(&format!(
+                " AND EXISTS (SELECT id FROM tag_map
+                    WHERE tag_map.img_id=t.id AND tag_map.tag_id=?{}{})",
+                tag_param, value_condition
             ));
-            joins += 1;
         }
     }
-
-    let mut stmt = conn.prepare(&constructed).unwrap();
-    for image in stmt
-        .query_map([], |row| Ok((row.get(0).unwrap(), row.get(1).unwrap())))
-        .unwrap()
-    {
-        match image {
-            Ok(val) => res.push(val),
-            Err(err) => eprint!("{}", err),
+    filter
+}
+/// Builds the condition of `in:group`. The query of a smart group which isn't materialized gets
+/// inlined, unknown groups and smart groups containing themselves match nothing
+fn group_filter(
+    name: &str,
+    params: &mut Vec<Value>,
+    groups: &mut Vec<GroupId>,
+    conn: &Connection,
+) -> String {
+    let group = conn
+        .query_row(
+            "SELECT id, query, materialized FROM groups WHERE name=?1",
+            [name],
+            |row| {
+                Ok((
+                    row.get::<usize, GroupId>(0)?,
+                    row.get::<usize, Option<String>>(1)?,
+                    row.get::<usize, bool>(2)?,
+                ))
+            },
+        )
+        .ok();
+    match group {
+        None => "0".to_owned(),
+        Some((id, _, _)) if groups.contains(&id) => "0".to_owned(),
+        Some((id, Some(query), false)) => {
+            groups.push(id);
+            let filter = build_filter(&query, params, groups, conn);
+            groups.pop();
+            format!("({})", filter)
+        }
+        Some((id, _, _)) => {
+            params.push(id.0.into());
+            format!(
+                "EXISTS (SELECT id FROM group_map
+                    WHERE group_map.img_id=t.id AND group_map.group_id=?{})",
+                params.len()
+            )
+        }
+    }
+}
+/// Builds the condition of a date predicate like `added:>2026-01-01`, `tagged:2026-01-01` or
+/// `updated:=2025-01-01..2025-06-30`. A date without time matches the whole day, so it is
+/// included in upper bounds and excluded by `>`
+fn date_filter(token: &str, params: &mut Vec<Value>) -> Option<String> {
+    let (key, rest) = token.split_once(':')?;
+    let expr = match key {
+        "added" => "t.created",
+        "updated" => "t.updated",
+        "tagged" => "(SELECT MAX(tag_map.created) FROM tag_map WHERE tag_map.img_id=t.id)",
+        _ => return None,
+    };
+    let comparison = values::parse_comparison(rest)
+        .or_else(|| values::parse_comparison(&format!("={}", rest)))?;
+    let end_of_day = |time: i64| {
+        if time % 86400 == 0 {
+            time + 86399
+        } else {
+            time
+        }
+    };
+    let comparison = match comparison {
+        Comparison::Eq(TagValue::Date(day)) if day % 86400 == 0 => {
+            Comparison::Between(TagValue::Date(day), TagValue::Date(end_of_day(day)))
+        }
+        Comparison::Between(from, TagValue::Date(to)) => {
+            Comparison::Between(from, TagValue::Date(end_of_day(to)))
+        }
+        Comparison::Le(TagValue::Date(day)) => Comparison::Le(TagValue::Date(end_of_day(day))),
+        Comparison::Gt(TagValue::Date(day)) => Comparison::Gt(TagValue::Date(end_of_day(day))),
+        comparison => comparison,
+    };
+    Some(values::compare(expr, &comparison, params))
+}
+/// Gets the full images for the given ids, missing ones get skipped
+pub fn load_images(ids: &[ImageId], conn: &Connection) -> Result<Vec<Image>> {
+    let mut stmt = conn.prepare(&format!("{} WHERE id=?1", SELECT_IMAGES))?;
+    let mut res: Vec<Image> = Vec::new();
+    for id in ids {
+        if let Some(image) = stmt.query_map([id], image_from_row)?.next() {
+            res.push(image?);
         }
     }
-
-    res
+    Ok(res)
+}
+/// Passes every image to f in order of their ids, without collecting them first
+pub fn for_each_image(conn: &Connection, f: impl FnMut(Image)) -> Result<()> {
+    listing::stream_rows(
+        &format!("{} ORDER BY id", SELECT_IMAGES),
+        [],
+        conn,
+        image_from_row,
+        f,
+    )
+}
+/// Streaming version of [`get_images_with_tag`]
+pub fn for_each_image_with_tag(
+    tag_id: TagId,
+    options: &ListOptions,
+    conn: &Connection,
+    f: impl FnMut(ImageId, String),
+) -> Result<()> {
+    listing::stream(
+        Table::Images,
+        WITH_TAG,
+        vec![tag_id.0.into()],
+        options,
+        conn,
+        f,
+    )
+}
+/// Streaming version of [`get_tags_of_img`]
+pub fn for_each_tag_of_img(
+    img_id: ImageId,
+    options: &ListOptions,
+    conn: &Connection,
+    f: impl FnMut(TagId, String),
+) -> Result<()> {
+    listing::stream(
+        Table::Tags,
+        TAGS_OF_IMG,
+        vec![img_id.0.into()],
+        options,
+        conn,
+        f,
+    )
+}
+/// Streaming version of [`get_orphans`]
+pub fn for_each_orphan(
+    options: &ListOptions,
+    conn: &Connection,
+    f: impl FnMut(ImageId, String),
+) -> Result<()> {
+    listing::stream(Table::Images, ORPHANS, vec![], options, conn, f)
+}
+/// Streaming version of [`query_sql`]
+pub fn for_each_query_match(
+    query: &str,
+    options: &ListOptions,
+    conn: &Connection,
+    f: impl FnMut(ImageId, String),
+) -> Result<()> {
+    let (filter, params) = query_filter(query, conn);
+    listing::stream(Table::Images, &filter, params, options, conn, f)
 }
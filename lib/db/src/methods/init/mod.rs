@@ -1,4 +1,6 @@
 use rusqlite::{Connection, Result};
+/// Version of the tables stored in `PRAGMA user_version`, raise it when [`migrate`] changes
+pub const SCHEMA_VERSION: i64 = 3;
 pub fn recreate_db(path: std::path::PathBuf) -> Connection {
     match std::fs::remove_file(&path) {
         Ok(_) => {}
@@ -11,24 +13,34 @@ pub fn recreate_db(path: std::path::PathBuf) -> Connection {
         panic!("Failed to open database")
     }
 }
-/// creates the tables needed
+/// creates the tables needed and turns on foreign keys, so deleting tags, images, namespaces
+/// and groups deletes their mappings
 pub fn init_tables(conn: &Connection) -> Result<()> {
+    conn.pragma_update(None, "foreign_keys", true)?;
     conn.execute(
         "CREATE TABLE IF NOT EXISTS tags (
-        id      INTEGER PRIMARY KEY,
-        name    TEXT NOT NULL UNIQUE)",
+        id          INTEGER PRIMARY KEY,
+        name        TEXT NOT NULL UNIQUE,
+        created     INTEGER,
+        updated     INTEGER,
+        description TEXT)",
         (),
     )?;
     conn.execute(
         "CREATE TABLE IF NOT EXISTS namespaces (
-        id      INTEGER PRIMARY KEY,
-        name    TEXT NOT NULL UNIQUE)",
+        id          INTEGER PRIMARY KEY,
+        name        TEXT NOT NULL UNIQUE,
+        description TEXT,
+        exclusive   TEXT)",
         (),
     )?;
     conn.execute(
         "CREATE TABLE IF NOT EXISTS images (
         id      INTEGER PRIMARY KEY,
-        path    TEXT NOT NULL UNIQUE)",
+        path    TEXT NOT NULL UNIQUE,
+        created INTEGER,
+        updated INTEGER,
+        hash    TEXT)",
         (),
     )?;
     conn.execute(
@@ -50,15 +62,249 @@ pub fn init_tables(conn: &Connection) -> Result<()> {
         CONSTRAINT duplicate_subtag UNIQUE(parent_id, child_id))",
         (),
     )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tag_aliases (
+        id          INTEGER PRIMARY KEY,
+        name        TEXT NOT NULL UNIQUE,
+        tag_id      INTEGER NOT NULL,
+        FOREIGN KEY(tag_id) REFERENCES tags(id) ON DELETE CASCADE)",
+        (),
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rules (
+        id          INTEGER PRIMARY KEY,
+        kind        TEXT NOT NULL,
+        target_id   INTEGER NOT NULL,
+        FOREIGN KEY(target_id) REFERENCES tags(id) ON DELETE CASCADE)",
+        (),
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rule_conditions (
+        id          INTEGER PRIMARY KEY,
+        rule_id     INTEGER NOT NULL,
+        tag_id      INTEGER NOT NULL,
+        FOREIGN KEY(rule_id) REFERENCES rules(id) ON DELETE CASCADE,
+        FOREIGN KEY(tag_id) REFERENCES tags(id) ON DELETE CASCADE,
+        CONSTRAINT duplicate_condition UNIQUE(rule_id, tag_id))",
+        (),
+    )?;
+    // a rule losing a condition would apply to more images, so it goes with the tag
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS rules_condition_deleted BEFORE DELETE ON tags
+        BEGIN
+            DELETE FROM rules WHERE id IN (SELECT rule_id FROM rule_conditions WHERE tag_id=OLD.id);
+        END",
+        (),
+    )?;
     conn.execute(
         "CREATE TABLE IF NOT EXISTS tag_map (
         id          INTEGER PRIMARY KEY,
         img_id      INTEGER,
         tag_id      INTEGER,
+        value,
+        value_type  TEXT,
+        created     INTEGER,
+        updated     INTEGER,
         FOREIGN KEY (img_id) REFERENCES images(id) ON DELETE CASCADE,
         FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE,
         CONSTRAINT duplicate_tag UNIQUE(img_id, tag_id))",
         (),
     )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS history (
+        id              INTEGER PRIMARY KEY,
+        timestamp       INTEGER NOT NULL,
+        operation       TEXT NOT NULL,
+        img_id          INTEGER,
+        img_path        TEXT,
+        tag_id          INTEGER,
+        tag_name        TEXT,
+        namespace_id    INTEGER,
+        namespace_name  TEXT,
+        parent_id       INTEGER,
+        parent_name     TEXT,
+        previous        TEXT,
+        actor           TEXT,
+        group_id        INTEGER,
+        group_name      TEXT,
+        search_id       INTEGER,
+        search_name     TEXT)",
+        (),
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS history_timestamp ON history(timestamp)",
+        (),
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS groups (
+        id              INTEGER PRIMARY KEY,
+        name            TEXT NOT NULL UNIQUE,
+        query           TEXT,
+        materialized    INTEGER NOT NULL DEFAULT 0)",
+        (),
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS group_map (
+        id          INTEGER PRIMARY KEY,
+        group_id    INTEGER,
+        img_id      INTEGER,
+        FOREIGN KEY (group_id) REFERENCES groups(id) ON DELETE CASCADE,
+        FOREIGN KEY (img_id) REFERENCES images(id) ON DELETE CASCADE,
+        CONSTRAINT duplicate_group_image UNIQUE(group_id, img_id))",
+        (),
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS properties (
+        id          INTEGER PRIMARY KEY,
+        img_id      INTEGER NOT NULL,
+        key         TEXT NOT NULL,
+        value       TEXT NOT NULL,
+        FOREIGN KEY (img_id) REFERENCES images(id) ON DELETE CASCADE,
+        CONSTRAINT duplicate_property UNIQUE(img_id, key))",
+        (),
+    )?;
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS saved_searches (
+            id          INTEGER PRIMARY KEY,
+            name        TEXT NOT NULL UNIQUE,
+            query       TEXT NOT NULL,
+            sort        TEXT NOT NULL DEFAULT 'id',
+            descending  INTEGER NOT NULL DEFAULT 0,
+            created     INTEGER DEFAULT ({}))",
+            NOW
+        ),
+        (),
+    )?;
+    migrate(conn)?;
+    conn.execute("CREATE INDEX IF NOT EXISTS images_hash ON images(hash)", ())?;
+    create_timestamp_triggers(conn)?;
+    create_description_index(conn)?;
+    conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    Ok(())
+}
+/// Creates the full text indexes over the descriptions of tags and namespaces, kept up to date
+/// by triggers. Databases which had descriptions before get them indexed
+fn create_description_index(conn: &Connection) -> Result<()> {
+    for table in ["tags", "namespaces"] {
+        let exists = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type='table' AND name=?1")?
+            .exists([format!("{}_fts", table)])?;
+        conn.execute_batch(&format!(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS {table}_fts
+                USING fts5(description, content='{table}', content_rowid='id');
+            CREATE TRIGGER IF NOT EXISTS {table}_fts_insert AFTER INSERT ON {table}
+            BEGIN
+                INSERT INTO {table}_fts(rowid, description) VALUES (NEW.id, NEW.description);
+            END;
+            CREATE TRIGGER IF NOT EXISTS {table}_fts_delete AFTER DELETE ON {table}
+            BEGIN
+                INSERT INTO {table}_fts({table}_fts, rowid, description)
+                    VALUES ('delete', OLD.id, OLD.description);
+            END;
+            CREATE TRIGGER IF NOT EXISTS {table}_fts_update AFTER UPDATE OF description ON {table}
+            BEGIN
+                INSERT INTO {table}_fts({table}_fts, rowid, description)
+                    VALUES ('delete', OLD.id, OLD.description);
+                INSERT INTO {table}_fts(rowid, description) VALUES (NEW.id, NEW.description);
+            END;",
+            table = table
+        ))?;
+        if !exists {
+            conn.execute(
+                &format!(
+                    "INSERT INTO {table}_fts({table}_fts) VALUES ('rebuild')",
+                    table = table
+                ),
+                (),
+            )?;
+        }
+    }
+    Ok(())
+}
+/// Current unix timestamp in SQL
+pub(crate) const NOW: &str = "CAST(strftime('%s', 'now') AS INTEGER)";
+/// Keeps the created and updated columns of tags, images and tag_map up to date, updated
+/// changes with the listed columns
+fn create_timestamp_triggers(conn: &Connection) -> Result<()> {
+    for (table, columns) in [
+        ("tags", "name"),
+        ("images", "path"),
+        ("tag_map", "value, value_type"),
+    ] {
+        conn.execute_batch(&format!(
+            "CREATE TRIGGER IF NOT EXISTS {table}_created AFTER INSERT ON {table}
+            BEGIN
+                UPDATE {table} SET created={now}, updated={now} WHERE id=NEW.id;
+            END;
+            CREATE TRIGGER IF NOT EXISTS {table}_updated AFTER UPDATE OF {columns} ON {table}
+            BEGIN
+                UPDATE {table} SET updated={now} WHERE id=NEW.id;
+            END;",
+            table = table,
+            columns = columns,
+            now = NOW,
+        ))?;
+    }
     Ok(())
 }
+/// Adds columns which were introduced after the tables of an existing database were created
+fn migrate(conn: &Connection) -> Result<()> {
+    add_column("history", "group_id", "INTEGER", conn)?;
+    add_column("history", "group_name", "TEXT", conn)?;
+    add_column("history", "search_id", "INTEGER", conn)?;
+    add_column("history", "search_name", "TEXT", conn)?;
+    // no type, so values keep the type they were stored with
+    add_column("tag_map", "value", "", conn)?;
+    add_column("tag_map", "value_type", "TEXT", conn)?;
+    add_column("groups", "query", "TEXT", conn)?;
+    add_column("groups", "materialized", "INTEGER NOT NULL DEFAULT 0", conn)?;
+    add_column("tags", "description", "TEXT", conn)?;
+    add_column("namespaces", "description", "TEXT", conn)?;
+    add_column("namespaces", "exclusive", "TEXT", conn)?;
+    add_column("images", "hash", "TEXT", conn)?;
+    // existing rows get the time from the history, if they are in it
+    for (table, filter) in [
+        ("tags", "operation='add_tag' AND history.tag_id=tags.id"),
+        (
+            "images",
+            "operation='add_image' AND history.img_id=images.id",
+        ),
+        (
+            "tag_map",
+            "operation='tag_image' AND history.img_id=tag_map.img_id
+                AND history.tag_id=tag_map.tag_id",
+        ),
+    ] {
+        if add_column(table, "created", "INTEGER", conn)? {
+            conn.execute(
+                &format!(
+                    "UPDATE {table} SET created=(SELECT MAX(timestamp) FROM history WHERE {filter})",
+                    table = table,
+                    filter = filter
+                ),
+                (),
+            )?;
+        }
+        if add_column(table, "updated", "INTEGER", conn)? {
+            conn.execute(&format!("UPDATE {} SET updated=created", table), ())?;
+        }
+    }
+    Ok(())
+}
+/// Adds the column to the table if it doesn't have it yet, returns if it was added
+fn add_column(table: &str, column: &str, definition: &str, conn: &Connection) -> Result<bool> {
+    let exists = conn
+        .prepare(&format!(
+            "SELECT name FROM pragma_table_info('{}') WHERE name=?1",
+            table
+        ))?
+        .exists([column])?;
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            (),
+        )?;
+    }
+    Ok(!exists)
+}
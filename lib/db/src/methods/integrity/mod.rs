@@ -0,0 +1,179 @@
+use crate::{
+    methods::{
+        history::{self, Change, Operation},
+        namespaces, rules, tags,
+    },
+    types::{Exclusivity, ImageId, NamespaceId, Problem, TagId},
+};
+use rusqlite::{Connection, Result};
+/// Connections from every tag to every tag below it, `start` reaches `id`
+const REACHABLE: &str = "WITH RECURSIVE reach(start, id) AS (
+        SELECT parent_id, child_id FROM subtag_map
+        UNION
+        SELECT reach.start, subtag_map.child_id FROM reach
+        JOIN subtag_map ON subtag_map.parent_id=reach.id
+    )";
+/// Finds everything wrong in the database: corruption reported by SQLite, rows referring to
+/// deleted rows, loops in the tag hierarchy, images missing the parents of their tags, and
+/// images breaking rules or exclusive namespaces
+pub fn check(conn: &Connection) -> Result<Vec<Problem>> {
+    let mut problems: Vec<Problem> = get_corruption(conn)?
+        .into_iter()
+        .map(Problem::Corruption)
+        .collect();
+    for (table, row_id, parent) in get_dangling(conn)? {
+        problems.push(Problem::Dangling {
+            table,
+            row_id,
+            parent,
+        });
+    }
+    for (_, parent_id, child_id) in get_cycles(conn)? {
+        problems.push(Problem::Cycle {
+            parent_id,
+            child_id,
+        });
+    }
+    for (img_id, tag_id, parent_id) in get_missing_parents(conn)? {
+        problems.push(Problem::MissingParent {
+            img_id,
+            tag_id,
+            parent_id,
+        });
+    }
+    problems.extend(rules::get_violations(conn)?.into_iter().map(Problem::Rule));
+    for (namespace_id, img_id) in namespaces::get_exclusive_violations(conn)? {
+        problems.push(Problem::Exclusive {
+            namespace_id,
+            img_id,
+        });
+    }
+    Ok(problems)
+}
+/// Fixes what [`check`] finds in one transaction and returns the problems which are left.
+/// Indexes get rebuilt, dangling rows deleted, loops broken by removing their newest connection
+/// and missing parents and implied tags added. Images with several tags of an exclusive
+/// namespace keep the newest one if it replaces and the oldest one if it refuses. Corruption
+/// of the tables themselves and tags excluded by rules stay
+pub fn repair(conn: &mut Connection) -> Result<Vec<Problem>> {
+    let tx = conn.transaction()?;
+    if !get_corruption(&tx)?.is_empty() {
+        tx.execute_batch("REINDEX")?;
+    }
+    // deleting a row can leave rows referring to it, e.g. the conditions of a rule
+    loop {
+        let dangling = get_dangling(&tx)?;
+        if dangling.is_empty() {
+            break;
+        }
+        for (table, row_id, _) in dangling {
+            tx.execute(&format!("DELETE FROM {} WHERE rowid=?1", table), [row_id])?;
+        }
+    }
+    while let Some((id, parent_id, child_id)) = get_cycles(&tx)?.into_iter().max() {
+        tx.execute("DELETE FROM subtag_map WHERE id=?1", [id])?;
+        let change = Change {
+            tag_id: Some(child_id),
+            parent_id: Some(parent_id),
+            ..Default::default()
+        };
+        history::record(Operation::RemoveConnection, change, &tx)?;
+    }
+    // parents of added parents can be missing too
+    loop {
+        let missing = get_missing_parents(&tx)?;
+        if missing.is_empty() {
+            break;
+        }
+        for (img_id, _, parent_id) in missing {
+            let added = tx.execute(
+                "INSERT OR IGNORE INTO tag_map(img_id, tag_id) VALUES (?1, ?2)",
+                (img_id, parent_id),
+            )?;
+            if added == 1 {
+                tags::record_tagging(Operation::TagImage, parent_id, img_id, &tx)?;
+            }
+        }
+    }
+    for (namespace_id, img_id) in namespaces::get_exclusive_violations(&tx)? {
+        for tag_id in extra_exclusive_tags(namespace_id, img_id, &tx)? {
+            tags::remove_tag_from_img(tag_id, img_id, &tx)?;
+        }
+    }
+    for violation in rules::get_violations(&tx)? {
+        rules::apply_rules(violation.img_id, &tx)?;
+    }
+    tx.commit()?;
+    check(conn)
+}
+/// Gets the messages of `PRAGMA integrity_check`, empty if the database is fine
+fn get_corruption(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let rows = stmt.query_map([], |row| row.get::<usize, String>(0))?;
+    let messages = rows.collect::<Result<Vec<String>>>()?;
+    Ok(messages
+        .into_iter()
+        .filter(|message| message != "ok")
+        .collect())
+}
+/// Gets the table, row id and parent table of rows referring to missing rows
+fn get_dangling(conn: &Connection) -> Result<Vec<(String, i64, String)>> {
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+    rows.collect()
+}
+/// Gets the id, parent and child of connections which are part of a loop, by id
+fn get_cycles(conn: &Connection) -> Result<Vec<(i64, TagId, TagId)>> {
+    let mut stmt = conn.prepare(&format!(
+        "{} SELECT id, parent_id, child_id FROM subtag_map
+        WHERE EXISTS (SELECT 1 FROM reach WHERE start=child_id AND reach.id=parent_id)
+        ORDER BY id",
+        REACHABLE
+    ))?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+    rows.collect()
+}
+/// Gets the image, tag and direct parent for images which have the tag but not the parent
+fn get_missing_parents(conn: &Connection) -> Result<Vec<(ImageId, TagId, TagId)>> {
+    let mut stmt = conn.prepare(
+        "SELECT tag_map.img_id, tag_map.tag_id, subtag_map.parent_id FROM tag_map
+        JOIN subtag_map ON subtag_map.child_id=tag_map.tag_id
+        WHERE NOT EXISTS (SELECT 1 FROM tag_map parents
+            WHERE parents.img_id=tag_map.img_id AND parents.tag_id=subtag_map.parent_id)
+        ORDER BY tag_map.img_id, tag_map.tag_id, subtag_map.parent_id",
+    )?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+    rows.collect()
+}
+/// Gets the tags of the namespace on the image which have to go, all but the newest for
+/// replacing namespaces and all but the oldest for refusing ones
+fn extra_exclusive_tags(
+    namespace_id: NamespaceId,
+    img_id: ImageId,
+    conn: &Connection,
+) -> Result<Vec<TagId>> {
+    let exclusive: Option<String> = conn.query_row(
+        "SELECT exclusive FROM namespaces WHERE id=?1",
+        [namespace_id],
+        |row| row.get(0),
+    )?;
+    let mut stmt = conn.prepare(
+        "SELECT tag_map.tag_id FROM tag_map
+        JOIN namespace_map ON namespace_map.tag_id=tag_map.tag_id
+        WHERE namespace_map.namespace_id=?1 AND tag_map.img_id=?2
+        ORDER BY tag_map.id",
+    )?;
+    let mut tags = stmt
+        .query_map((namespace_id, img_id), |row| row.get(0))?
+        .collect::<Result<Vec<TagId>>>()?;
+    match exclusive.and_then(|mode| mode.parse().ok()) {
+        Some(Exclusivity::Replace) => {
+            tags.pop();
+        }
+        Some(Exclusivity::Refuse) => {
+            tags.remove(0);
+        }
+        None => tags.clear(),
+    }
+    Ok(tags)
+}
@@ -0,0 +1,211 @@
+use crate::types::{Cursor, ListOptions, Page, SortBy, SortKey};
+use rusqlite::{
+    types::{FromSql, Value},
+    Connection, Result, Row,
+};
+
+/// Tables which can be listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Table {
+    Tags,
+    Images,
+    Namespaces,
+    Groups,
+}
+impl Table {
+    fn name(&self) -> &'static str {
+        match self {
+            Table::Tags => "tags",
+            Table::Images => "images",
+            Table::Namespaces => "namespaces",
+            Table::Groups => "groups",
+        }
+    }
+    /// Column listed next to the id
+    fn label(&self) -> &'static str {
+        match self {
+            Table::Images => "path",
+            _ => "name",
+        }
+    }
+    /// Expression the rows get sorted by, `t` is the listed table
+    fn sort_expr(&self, sort: SortBy) -> String {
+        match sort {
+            SortBy::Id => "t.id".to_owned(),
+            SortBy::Name => format!("t.{}", self.label()),
+            SortBy::Usage => match self {
+                Table::Tags => "(SELECT COUNT(*) FROM tag_map WHERE tag_map.tag_id=t.id)",
+                Table::Images => "(SELECT COUNT(*) FROM tag_map WHERE tag_map.img_id=t.id)",
+                Table::Namespaces => {
+                    "(SELECT COUNT(*) FROM namespace_map WHERE namespace_map.namespace_id=t.id)"
+                }
+                Table::Groups => "(SELECT COUNT(*) FROM group_map WHERE group_map.group_id=t.id)",
+            }
+            .to_owned(),
+            SortBy::Added if matches!(self, Table::Tags | Table::Images) => {
+                "COALESCE(t.created, 0)".to_owned()
+            }
+            SortBy::Updated if matches!(self, Table::Tags | Table::Images) => {
+                "COALESCE(t.updated, 0)".to_owned()
+            }
+            SortBy::Added | SortBy::Updated => {
+                let (operation, column) = match self {
+                    Table::Namespaces => ("add_namespace", "namespace_id"),
+                    _ => ("add_group", "group_id"),
+                };
+                // any change of a namespace or group counts as an update
+                let operation = match sort {
+                    SortBy::Added => format!("operation='{}' AND", operation),
+                    _ => String::new(),
+                };
+                format!(
+                    "COALESCE((SELECT MAX(timestamp) FROM history
+                        WHERE {} history.{}=t.id), 0)",
+                    operation, column
+                )
+            }
+            SortBy::Tagged => format!(
+                "COALESCE((SELECT MAX(tag_map.created) FROM tag_map {}), 0)",
+                match self {
+                    Table::Tags => "WHERE tag_map.tag_id=t.id",
+                    Table::Images => "WHERE tag_map.img_id=t.id",
+                    Table::Namespaces =>
+                        "JOIN namespace_map ON namespace_map.tag_id=tag_map.tag_id
+                        WHERE namespace_map.namespace_id=t.id",
+                    Table::Groups =>
+                        "JOIN group_map ON group_map.img_id=tag_map.img_id
+                        WHERE group_map.group_id=t.id",
+                }
+            ),
+        }
+    }
+}
+
+/// Builds the query listing the rows of table matching filter, returns it with its parameters.
+/// Rows have the columns id, name or path and the value they are sorted by
+fn select(
+    table: Table,
+    filter: &str,
+    mut params: Vec<Value>,
+    options: &ListOptions,
+    limit: Option<usize>,
+) -> (String, Vec<Value>) {
+    let direction = if options.descending { "DESC" } else { "ASC" };
+    let mut conditions = String::new();
+    if let Some(cursor) = &options.after {
+        let comparison = if options.descending { "<" } else { ">" };
+        params.push(match &cursor.key {
+            SortKey::Int(key) => Value::Integer(*key),
+            SortKey::Text(key) => Value::Text(key.clone()),
+        });
+        params.push(Value::Integer(cursor.id));
+        conditions = format!(
+            "WHERE key {cmp} ?{key} OR (key = ?{key} AND id {cmp} ?{id})",
+            cmp = comparison,
+            key = params.len() - 1,
+            id = params.len()
+        );
+    }
+    // -1 is no limit for SQLite, so are limits too large for it
+    params.push(Value::Integer(
+        limit.map_or(-1, |limit| i64::try_from(limit).unwrap_or(-1)),
+    ));
+    params.push(Value::Integer(
+        i64::try_from(options.offset).unwrap_or(i64::MAX),
+    ));
+    let sql = format!(
+        "SELECT id, label, key FROM (
+            SELECT t.id AS id, t.{label} AS label, {key} AS key
+            FROM {table} t
+            WHERE {filter}
+        )
+        {conditions}
+        ORDER BY key {dir}, id {dir}
+        LIMIT ?{limit} OFFSET ?{offset}",
+        label = table.label(),
+        key = table.sort_expr(options.sort),
+        table = table.name(),
+        filter = filter,
+        conditions = conditions,
+        dir = direction,
+        limit = params.len() - 1,
+        offset = params.len(),
+    );
+    (sql, params)
+}
+
+/// Rows per page, a limit of 0 lists everything like no limit
+fn page_size(options: &ListOptions) -> Option<usize> {
+    options.limit.filter(|&limit| limit > 0)
+}
+
+/// Lists (id, name or path) of the rows of table matching filter, a condition on the table
+/// aliased as `t` using the parameters ?1, ?2, ... from params
+pub fn list<I: FromSql>(
+    table: Table,
+    filter: &str,
+    params: Vec<Value>,
+    options: &ListOptions,
+    conn: &Connection,
+) -> Result<Page<(I, String)>> {
+    let page_size = page_size(options);
+    // one more row than needed, to know if there is a next page
+    let limit = page_size.map(|limit| limit.saturating_add(1));
+    let (sql, params) = select(table, filter, params, options, limit);
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
+    let mut items: Vec<(I, String)> = Vec::new();
+    let mut last: Option<Cursor> = None;
+    let mut next: Option<Cursor> = None;
+    while let Some(row) = rows.next()? {
+        if page_size.is_some_and(|limit| items.len() == limit) {
+            next = last;
+            break;
+        }
+        let key = match row.get::<usize, Value>(2)? {
+            Value::Text(key) => SortKey::Text(key),
+            Value::Integer(key) => SortKey::Int(key),
+            _ => SortKey::Int(0),
+        };
+        last = Some(Cursor {
+            key,
+            id: row.get(0)?,
+        });
+        items.push((row.get(0)?, row.get(1)?));
+    }
+    Ok(Page { items, next })
+}
+
+/// Like [`list`], but passes every row to f as soon as it is read instead of collecting them
+pub fn stream<I: FromSql>(
+    table: Table,
+    filter: &str,
+    params: Vec<Value>,
+    options: &ListOptions,
+    conn: &Connection,
+    mut f: impl FnMut(I, String),
+) -> Result<()> {
+    let (sql, params) = select(table, filter, params, options, page_size(options));
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
+    while let Some(row) = rows.next()? {
+        f(row.get(0)?, row.get(1)?);
+    }
+    Ok(())
+}
+
+/// Runs sql and passes every row, converted by from_row, to f as soon as it is read
+pub fn stream_rows<T>(
+    sql: &str,
+    params: impl rusqlite::Params,
+    conn: &Connection,
+    from_row: impl Fn(&Row) -> Result<T>,
+    mut f: impl FnMut(T),
+) -> Result<()> {
+    let mut stmt = conn.prepare(sql)?;
+    let mut rows = stmt.query(params)?;
+    while let Some(row) = rows.next()? {
+        f(from_row(row)?);
+    }
+    Ok(())
+}
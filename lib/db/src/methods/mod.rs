@@ -1,7 +1,18 @@
+pub mod backup;
+pub mod graph;
 pub mod groups;
+pub mod history;
 pub mod images;
 pub mod init;
+pub mod integrity;
+pub mod listing;
 pub mod namespaces;
+pub mod properties;
+pub mod rules;
+pub mod searches;
 pub mod subtags;
+pub mod suggestions;
+pub mod sync;
 pub mod tags;
 pub mod utils;
+pub mod values;
@@ -1,101 +1,397 @@
-use crate::methods::utils::macros::handle_unique;
-use rusqlite::{Connection, Result};
+use crate::{
+    methods::{
+        history::{self, Change, Operation},
+        listing::{self, Table},
+        utils::{
+            self,
+            macros::{handle_unique, insert_ret_id},
+        },
+    },
+    types::{Exclusivity, ImageId, ListOptions, Namespace, NamespaceId, Page, TagId},
+};
+use rusqlite::{ffi, Connection, OptionalExtension, Result, Row};
 /// Adds a namespace into the namespaces table, returns id if successful
-pub fn add_namespace(name: &str, conn: &Connection) -> Option<i64> {
-    match handle_unique!(conn.execute("INSERT INTO namespaces(name) VALUES (?1)", [name])) {
-        Ok(_) => Some(conn.last_insert_rowid()),
-        Err(_) => None,
-    }
+pub fn add_namespace(name: &str, conn: &Connection) -> Option<NamespaceId> {
+    utils::savepoint(conn, || {
+        let id = NamespaceId(insert_ret_id!(
+            conn.execute("INSERT INTO namespaces(name) VALUES (?1)", [name]),
+            &conn
+        )?);
+        let change = Change {
+            namespace_id: Some(id),
+            ..Default::default()
+        };
+        history::record(Operation::AddNamespace, change, conn).ok()?;
+        Some(id)
+    })
 }
 /// Changes namespace name of given namespace id
-pub fn rename_namespace(id: i64, new_name: &str, conn: &Connection) -> Result<()> {
-    conn.execute(
-        "UPDATE namespaces SET name=?1 WHERE id=?2",
-        [new_name, &format!("{}", id)],
-    )?;
+pub fn rename_namespace(id: NamespaceId, new_name: &str, conn: &Connection) -> Result<()> {
+    let previous = get_namespace_name(id, conn);
+    let changed = conn.execute("UPDATE namespaces SET name=?1 WHERE id=?2", (new_name, id))?;
+    if changed > 0 {
+        let change = Change {
+            namespace_id: Some(id),
+            previous,
+            ..Default::default()
+        };
+        history::record(Operation::RenameNamespace, change, conn)?;
+    }
     Ok(())
 }
 /// Adds a namespace to a tag, only one namespace per tag is allowed
-pub fn add_namespace_to_tag(namespace: i64, tag: i64, conn: &Connection) -> Result<()> {
+pub fn add_namespace_to_tag(namespace: NamespaceId, tag: TagId, conn: &Connection) -> Result<()> {
     conn.execute(
         "INSERT INTO namespace_map(namespace_id, tag_id) VALUES (?1, ?2)",
-        [namespace, tag],
+        (namespace, tag),
+    )?;
+    let change = Change {
+        tag_id: Some(tag),
+        namespace_id: Some(namespace),
+        ..Default::default()
+    };
+    history::record(Operation::AddNamespaceToTag, change, conn)?;
+    Ok(())
+}
+/// Makes images able to only have one tag of the namespace, None allows any number again
+pub fn set_exclusive(
+    namespace_id: NamespaceId,
+    exclusive: Option<Exclusivity>,
+    conn: &Connection,
+) -> Result<()> {
+    let previous: Option<String> = conn
+        .query_row(
+            "SELECT exclusive FROM namespaces WHERE id=?1",
+            [namespace_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+    let exclusive = exclusive.map(|exclusive| exclusive.as_str());
+    let changed = conn.execute(
+        "UPDATE namespaces SET exclusive=?1 WHERE id=?2",
+        (exclusive, namespace_id),
     )?;
+    if changed > 0 && previous.as_deref() != exclusive {
+        let change = Change {
+            namespace_id: Some(namespace_id),
+            previous,
+            ..Default::default()
+        };
+        history::record(Operation::SetExclusive, change, conn)?;
+    }
     Ok(())
 }
+/// Gets the tags of the image which share an exclusive namespace with one of the tags being
+/// added, with what should happen to them. Conflicts between the tags being added are handled
+/// by [`keep_one_exclusive`]
+pub fn exclusive_conflicts(
+    img_id: ImageId,
+    adding: &[TagId],
+    conn: &Connection,
+) -> Result<Vec<(TagId, Exclusivity)>> {
+    let mut stmt = conn.prepare(
+        "SELECT other.tag_id, namespaces.exclusive FROM namespace_map added
+        JOIN namespaces ON namespaces.id=added.namespace_id
+        JOIN namespace_map other ON other.namespace_id=added.namespace_id
+        JOIN tag_map ON tag_map.tag_id=other.tag_id AND tag_map.img_id=?1
+        WHERE added.tag_id=?2 AND namespaces.exclusive IS NOT NULL",
+    )?;
+    let mut conflicts: Vec<(TagId, Exclusivity)> = Vec::new();
+    for &tag in adding {
+        let rows = stmt.query_map((img_id, tag), |row| {
+            Ok((row.get::<usize, TagId>(0)?, row.get::<usize, String>(1)?))
+        })?;
+        for row in rows {
+            let (other, exclusive) = row?;
+            if adding.contains(&other) || conflicts.iter().any(|conflict| conflict.0 == other) {
+                continue;
+            }
+            if let Ok(exclusive) = exclusive.parse() {
+                conflicts.push((other, exclusive));
+            }
+        }
+    }
+    Ok(conflicts)
+}
+/// Drops tags being added which share a replacing exclusive namespace with another tag being
+/// added, so only one of the namespace stays: the last requested one, or the last one if only
+/// parents are in it. Errors if they share a refusing one
+pub(crate) fn keep_one_exclusive(
+    adding: &mut Vec<TagId>,
+    requested: &[TagId],
+    conn: &Connection,
+) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT namespaces.id, namespaces.exclusive FROM namespace_map
+        JOIN namespaces ON namespaces.id=namespace_map.namespace_id
+        WHERE namespace_map.tag_id=?1 AND namespaces.exclusive IS NOT NULL",
+    )?;
+    let mut later: Vec<(NamespaceId, TagId)> = Vec::new();
+    let mut dropped: Vec<TagId> = Vec::new();
+    for &tag in adding.iter().rev() {
+        let namespace = stmt
+            .query_row([tag], |row| {
+                Ok((
+                    row.get::<usize, NamespaceId>(0)?,
+                    row.get::<usize, String>(1)?,
+                ))
+            })
+            .optional()?;
+        let Some((namespace, exclusive)) = namespace else {
+            continue;
+        };
+        match later.iter_mut().find(|(other, _)| *other == namespace) {
+            Some((_, other)) => match exclusive.parse() {
+                Ok(Exclusivity::Refuse) => {
+                    return Err(rusqlite::Error::SqliteFailure(
+                        ffi::Error::new(ffi::SQLITE_CONSTRAINT_CHECK),
+                        Some(format!(
+                            "Tags {} and {} share an exclusive namespace",
+                            tag, other
+                        )),
+                    ))
+                }
+                // a requested tag wins over a parent
+                Ok(Exclusivity::Replace)
+                    if requested.contains(&tag) && !requested.contains(other) =>
+                {
+                    dropped.push(*other);
+                    *other = tag;
+                }
+                Ok(Exclusivity::Replace) => dropped.push(tag),
+                Err(_) => {}
+            },
+            None => later.push((namespace, tag)),
+        }
+    }
+    adding.retain(|tag| !dropped.contains(tag));
+    Ok(())
+}
+/// Error for tagging an image which already has a tag of a refusing exclusive namespace, like
+/// the one of a failed CHECK constraint
+pub(crate) fn exclusive_error(tag_id: TagId) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        ffi::Error::new(ffi::SQLITE_CONSTRAINT_CHECK),
+        Some(format!(
+            "Image already has a tag of the namespace of tag {}",
+            tag_id
+        )),
+    )
+}
+/// Gets the (namespace, image) pairs where an image has more than one tag of an exclusive
+/// namespace
+pub fn get_exclusive_violations(conn: &Connection) -> Result<Vec<(NamespaceId, ImageId)>> {
+    let mut stmt = conn.prepare(
+        "SELECT namespace_map.namespace_id, tag_map.img_id FROM tag_map
+        JOIN namespace_map ON namespace_map.tag_id=tag_map.tag_id
+        JOIN namespaces ON namespaces.id=namespace_map.namespace_id
+        WHERE namespaces.exclusive IS NOT NULL
+        GROUP BY namespace_map.namespace_id, tag_map.img_id HAVING COUNT(*) > 1
+        ORDER BY namespace_map.namespace_id, tag_map.img_id",
+    )?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+/// Gets the id of the namespace with the given name
+pub fn get_namespace_by_name(name: &str, conn: &Connection) -> Option<NamespaceId> {
+    conn.query_row("SELECT id FROM namespaces WHERE name=?1", [name], |row| {
+        row.get(0)
+    })
+    .ok()
+}
 /// Removes namespace from tag, as there can only be one you don't need to specify it
-pub fn remove_namespace_from_tag(tag: i64, conn: &Connection) -> Result<()> {
+pub fn remove_namespace_from_tag(tag: TagId, conn: &Connection) -> Result<()> {
+    if let Some(namespace) = get_namespace_of_tag(tag, conn) {
+        let change = Change {
+            tag_id: Some(tag),
+            namespace_id: Some(namespace),
+            ..Default::default()
+        };
+        history::record(Operation::RemoveNamespaceFromTag, change, conn)?;
+    }
     conn.execute("DELETE FROM namespace_map WHERE tag_id=?1", [tag])?;
     Ok(())
 }
 /// Removes the namespace from the namespaces table, removing all associations
-pub fn remove_namespace(namespace_id: i64, conn: &Connection) -> Result<()> {
+pub fn remove_namespace(namespace_id: NamespaceId, conn: &Connection) -> Result<()> {
+    if get_namespace_name(namespace_id, conn).is_some() {
+        let change = Change {
+            namespace_id: Some(namespace_id),
+            ..Default::default()
+        };
+        history::record(Operation::RemoveNamespace, change, conn)?;
+    }
     conn.execute("DELETE FROM namespaces WHERE id=?1", [namespace_id])?;
     Ok(())
 }
 /// Gets the namespace from a namespace id
-pub fn get_namespace_name(namespace_id: i64, conn: &Connection) -> Option<String> {
+pub fn get_namespace_name(namespace_id: NamespaceId, conn: &Connection) -> Option<String> {
     let mut stmt = conn
         .prepare("SELECT (name) FROM namespaces WHERE id=?1")
         .unwrap();
-    match stmt.query_row([namespace_id], |row| row.get(0)) {
-        Ok(name) => Some(name),
-        Err(_) => None,
+    stmt.query_row([namespace_id], |row| row.get(0)).ok()
+}
+/// Sets the description of a namespace, None removes it
+pub fn set_description(
+    namespace_id: NamespaceId,
+    description: Option<&str>,
+    conn: &Connection,
+) -> Result<()> {
+    let previous = get_description(namespace_id, conn);
+    let changed = conn.execute(
+        "UPDATE namespaces SET description=?1 WHERE id=?2",
+        (description, namespace_id),
+    )?;
+    if changed > 0 && previous.as_deref() != description {
+        let change = Change {
+            namespace_id: Some(namespace_id),
+            previous,
+            ..Default::default()
+        };
+        history::record(Operation::SetDescription, change, conn)?;
     }
+    Ok(())
+}
+/// Gets the description of a namespace
+pub fn get_description(namespace_id: NamespaceId, conn: &Connection) -> Option<String> {
+    conn.query_row(
+        "SELECT description FROM namespaces WHERE id=?1",
+        [namespace_id],
+        |row| row.get(0),
+    )
+    .ok()
+    .flatten()
+}
+/// Gets all namespaces whose description matches the full text query, see
+/// [`crate::methods::tags::search_descriptions`]
+pub fn search_descriptions(query: &str, conn: &Connection) -> Result<Vec<(NamespaceId, String)>> {
+    Ok(search_descriptions_page(query, &ListOptions::default(), conn)?.items)
+}
+/// Gets a page of the namespaces whose description matches the full text query
+pub fn search_descriptions_page(
+    query: &str,
+    options: &ListOptions,
+    conn: &Connection,
+) -> Result<Page<(NamespaceId, String)>> {
+    listing::list(
+        Table::Namespaces,
+        "t.id IN (SELECT rowid FROM namespaces_fts WHERE namespaces_fts MATCH ?1)",
+        vec![query.to_owned().into()],
+        options,
+        conn,
+    )
 }
 /// Gets the namespace id associated with a tag
-pub fn get_namespace_of_tag(tag_id: i64, conn: &Connection) -> Option<i64> {
+pub fn get_namespace_of_tag(tag_id: TagId, conn: &Connection) -> Option<NamespaceId> {
     let mut stmt = conn
         .prepare("SELECT namespace_id FROM namespace_map WHERE tag_id=?1")
         .unwrap();
-    match stmt.query_row([tag_id], |row| row.get::<usize, i64>(0)) {
-        Ok(id) => Some(id),
-        Err(err) => None,
-    }
+    stmt.query_row([tag_id], |row| row.get(0)).ok()
 }
+/// Filters tags on being connected with the namespace ?1
+const WITH_NAMESPACE: &str = "EXISTS (SELECT id FROM namespace_map
+    WHERE namespace_map.tag_id=t.id AND namespace_map.namespace_id=?1)";
+/// Filters namespaces without any tag
+const ORPHANS: &str =
+    "NOT EXISTS (SELECT id FROM namespace_map WHERE namespace_map.namespace_id=t.id)";
 /// Gets all of the tags connected with a namespace
-pub fn get_tags_with_namespace(namespace_id: i64, conn: &Connection) -> Vec<(i64, String)> {
-    let mut stmt = conn
-        .prepare(
-            "SELECT tags.id, tags.name
-            FROM tags
-            INNER JOIN namespace_map
-            WHERE namespace_map.namespace_id=?1
-            AND namespace_map.tag_id=tags.id",
-        )
-        .unwrap();
-    let tmp = stmt
-        .query_map([namespace_id], |row| {
-            Ok((row.get(0).unwrap(), row.get(1).unwrap()))
-        })
-        .unwrap();
-    let mut res: Vec<(i64, String)> = Vec::new();
-    for val in tmp {
-        res.push(val.unwrap());
-    }
-    res
+pub fn get_tags_with_namespace(
+    namespace_id: NamespaceId,
+    conn: &Connection,
+) -> Vec<(TagId, String)> {
+    get_tags_with_namespace_page(namespace_id, &ListOptions::default(), conn)
+        .unwrap()
+        .items
+}
+/// Gets a page of the tags connected with a namespace
+pub fn get_tags_with_namespace_page(
+    namespace_id: NamespaceId,
+    options: &ListOptions,
+    conn: &Connection,
+) -> Result<Page<(TagId, String)>> {
+    listing::list(
+        Table::Tags,
+        WITH_NAMESPACE,
+        vec![namespace_id.0.into()],
+        options,
+        conn,
+    )
 }
 /// Gets all namespaces which aren't linked with any tag
-pub fn get_orphans(conn: &Connection) -> Vec<(i64, String)> {
-    let mut stmt = conn
-        .prepare(
-            "
-            SELECT id, name
-            FROM namespaces
-            WHERE NOT EXISTS (
-                SELECT id
-                FROM namespace_map
-                WHERE namespace_map.namespace_id=namespaces.id
-            )
-        ",
-        )
-        .unwrap();
-    let mut res: Vec<(i64, String)> = Vec::new();
-    for orphan in stmt
-        .query_map([], |row| Ok((row.get(0).unwrap(), row.get(1).unwrap())))
+pub fn get_orphans(conn: &Connection) -> Vec<(NamespaceId, String)> {
+    get_orphans_page(&ListOptions::default(), conn)
         .unwrap()
-    {
-        res.push(orphan.unwrap());
+        .items
+}
+/// Gets a page of the namespaces which aren't linked with any tag
+pub fn get_orphans_page(
+    options: &ListOptions,
+    conn: &Connection,
+) -> Result<Page<(NamespaceId, String)>> {
+    listing::list(Table::Namespaces, ORPHANS, vec![], options, conn)
+}
+/// Selects the columns of a full namespace, `namespaces` is the selected table
+const SELECT_NAMESPACES: &str = "SELECT id, name,
+        (SELECT COUNT(*) FROM namespace_map WHERE namespace_map.namespace_id=namespaces.id),
+        (SELECT MAX(timestamp) FROM history
+            WHERE operation='add_namespace' AND history.namespace_id=namespaces.id),
+        description, exclusive
+    FROM namespaces";
+fn namespace_from_row(row: &Row) -> Result<Namespace> {
+    Ok(Namespace {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        tag_count: row.get(2)?,
+        created: row.get(3)?,
+        description: row.get(4)?,
+        exclusive: row
+            .get::<usize, Option<String>>(5)?
+            .and_then(|exclusive| exclusive.parse().ok()),
+    })
+}
+/// Gets the full namespaces for the given ids, missing ones get skipped
+pub fn load_namespaces(ids: &[NamespaceId], conn: &Connection) -> Result<Vec<Namespace>> {
+    let mut stmt = conn.prepare(&format!("{} WHERE id=?1", SELECT_NAMESPACES))?;
+    let mut res: Vec<Namespace> = Vec::new();
+    for id in ids {
+        if let Some(namespace) = stmt.query_map([id], namespace_from_row)?.next() {
+            res.push(namespace?);
+        }
     }
-    res
+    Ok(res)
+}
+/// Passes every namespace to f in order of their ids, without collecting them first
+pub fn for_each_namespace(conn: &Connection, f: impl FnMut(Namespace)) -> Result<()> {
+    listing::stream_rows(
+        &format!("{} ORDER BY id", SELECT_NAMESPACES),
+        [],
+        conn,
+        namespace_from_row,
+        f,
+    )
+}
+/// Streaming version of [`get_tags_with_namespace`]
+pub fn for_each_tag_with_namespace(
+    namespace_id: NamespaceId,
+    options: &ListOptions,
+    conn: &Connection,
+    f: impl FnMut(TagId, String),
+) -> Result<()> {
+    listing::stream(
+        Table::Tags,
+        WITH_NAMESPACE,
+        vec![namespace_id.0.into()],
+        options,
+        conn,
+        f,
+    )
+}
+/// Streaming version of [`get_orphans`]
+pub fn for_each_orphan(
+    options: &ListOptions,
+    conn: &Connection,
+    f: impl FnMut(NamespaceId, String),
+) -> Result<()> {
+    listing::stream(Table::Namespaces, ORPHANS, vec![], options, conn, f)
 }
@@ -0,0 +1,113 @@
+use crate::{
+    methods::{
+        history::{self, Change, Operation},
+        listing::{self, Table},
+    },
+    types::{ImageId, ListOptions, Page},
+};
+use rusqlite::{Connection, OptionalExtension, Result};
+fn record_property(
+    operation: Operation,
+    img_id: ImageId,
+    key: &str,
+    previous: Option<String>,
+    conn: &Connection,
+) -> Result<()> {
+    let change = Change {
+        img_id: Some(img_id),
+        previous: Some(match previous {
+            Some(previous) => format!("{}={}", key, previous),
+            None => key.to_owned(),
+        }),
+        ..Default::default()
+    };
+    history::record(operation, change, conn)
+}
+/// Sets a property of the image, replacing the old value of the key
+pub fn set_property(img_id: ImageId, key: &str, value: &str, conn: &Connection) -> Result<()> {
+    let previous = get_property(img_id, key, conn)?;
+    if previous.as_deref() == Some(value) {
+        return Ok(());
+    }
+    conn.execute(
+        "INSERT INTO properties(img_id, key, value) VALUES (?1, ?2, ?3)
+        ON CONFLICT(img_id, key) DO UPDATE SET value=excluded.value",
+        (img_id, key, value),
+    )?;
+    record_property(Operation::SetProperty, img_id, key, previous, conn)
+}
+/// Gets the value of a property of the image
+pub fn get_property(img_id: ImageId, key: &str, conn: &Connection) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT value FROM properties WHERE img_id=?1 AND key=?2",
+        (img_id, key),
+        |row| row.get(0),
+    )
+    .optional()
+}
+/// Removes a property from the image
+pub fn remove_property(img_id: ImageId, key: &str, conn: &Connection) -> Result<()> {
+    if let Some(previous) = get_property(img_id, key, conn)? {
+        conn.execute(
+            "DELETE FROM properties WHERE img_id=?1 AND key=?2",
+            (img_id, key),
+        )?;
+        record_property(Operation::RemoveProperty, img_id, key, Some(previous), conn)?;
+    }
+    Ok(())
+}
+/// Gets all (key, value) properties of the image sorted by key
+pub fn get_properties(img_id: ImageId, conn: &Connection) -> Result<Vec<(String, String)>> {
+    let mut stmt =
+        conn.prepare("SELECT key, value FROM properties WHERE img_id=?1 ORDER BY key")?;
+    let rows = stmt.query_map([img_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+/// Gets all images where the property has exactly the value
+pub fn get_images_with_property(
+    key: &str,
+    value: &str,
+    conn: &Connection,
+) -> Result<Vec<(ImageId, String)>> {
+    Ok(get_images_with_property_page(key, value, &ListOptions::default(), conn)?.items)
+}
+/// Gets a page of the images where the property has exactly the value
+pub fn get_images_with_property_page(
+    key: &str,
+    value: &str,
+    options: &ListOptions,
+    conn: &Connection,
+) -> Result<Page<(ImageId, String)>> {
+    listing::list(
+        Table::Images,
+        "EXISTS (SELECT id FROM properties
+            WHERE properties.img_id=t.id AND properties.key=?1 AND properties.value=?2)",
+        vec![key.to_owned().into(), value.to_owned().into()],
+        options,
+        conn,
+    )
+}
+/// Gets all images where the value of the property contains the search term, ignoring case
+pub fn search_property(
+    key: &str,
+    search_term: &str,
+    conn: &Connection,
+) -> Result<Vec<(ImageId, String)>> {
+    Ok(search_property_page(key, search_term, &ListOptions::default(), conn)?.items)
+}
+/// Gets a page of the images where the value of the property contains the search term
+pub fn search_property_page(
+    key: &str,
+    search_term: &str,
+    options: &ListOptions,
+    conn: &Connection,
+) -> Result<Page<(ImageId, String)>> {
+    listing::list(
+        Table::Images,
+        "EXISTS (SELECT id FROM properties
+            WHERE properties.img_id=t.id AND properties.key=?1 AND properties.value LIKE ?2)",
+        vec![key.to_owned().into(), format!("%{}%", search_term).into()],
+        options,
+        conn,
+    )
+}
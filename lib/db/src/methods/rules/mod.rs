@@ -0,0 +1,234 @@
+use crate::{
+    methods::{
+        history::{self, Change, Operation},
+        namespaces, subtags, tags, utils,
+    },
+    types::{ImageId, Rule, RuleId, RuleKind, TagId, Violation},
+};
+use rusqlite::{ffi, Connection, Result};
+use std::iter;
+
+/// Adds a rule in a single transaction and returns its id. None if there are no conditions or
+/// the target is one of them
+pub fn add_rule(
+    kind: RuleKind,
+    conditions: &[TagId],
+    target: TagId,
+    conn: &mut Connection,
+) -> Option<RuleId> {
+    if conditions.is_empty() || conditions.contains(&target) {
+        return None;
+    }
+    let tx = conn.transaction().ok()?;
+    tx.execute(
+        "INSERT INTO rules(kind, target_id) VALUES (?1, ?2)",
+        (kind.as_str(), target),
+    )
+    .ok()?;
+    let id = RuleId(tx.last_insert_rowid());
+    for condition in conditions {
+        tx.execute(
+            "INSERT OR IGNORE INTO rule_conditions(rule_id, tag_id) VALUES (?1, ?2)",
+            (id, condition),
+        )
+        .ok()?;
+    }
+    record_rule(Operation::AddRule, id, &tx).ok()?;
+    tx.commit().ok()?;
+    Some(id)
+}
+/// Removes a rule, tags it added stay
+pub fn remove_rule(rule_id: RuleId, conn: &Connection) -> Result<()> {
+    utils::savepoint(conn, || {
+        record_rule(Operation::RemoveRule, rule_id, conn)?;
+        conn.execute("DELETE FROM rule_conditions WHERE rule_id=?1", [rule_id])?;
+        conn.execute("DELETE FROM rules WHERE id=?1", [rule_id])?;
+        Ok(())
+    })
+}
+/// Records a change of the rule with its target and, as previous, its kind and conditions.
+/// Nothing gets recorded for rules which don't exist
+fn record_rule(operation: Operation, rule_id: RuleId, conn: &Connection) -> Result<()> {
+    let Some(rule) = load_rules(&format!("r.id={}", rule_id.0), conn)?.pop() else {
+        return Ok(());
+    };
+    let conditions: Vec<String> = rule
+        .conditions
+        .iter()
+        .filter_map(|&condition| tags::get_name(condition, conn))
+        .collect();
+    let change = Change {
+        tag_id: Some(rule.target),
+        previous: Some(format!("{} when {}", rule.kind, conditions.join(", "))),
+        ..Default::default()
+    };
+    history::record(operation, change, conn)
+}
+/// Gets all rules ordered by id
+pub fn get_rules(conn: &Connection) -> Result<Vec<Rule>> {
+    load_rules("1", conn)
+}
+/// Loads the rules matching filter, a condition on the rules aliased as `r`, with their
+/// conditions in one query
+fn load_rules(filter: &str, conn: &Connection) -> Result<Vec<Rule>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT r.id, r.kind, r.target_id, c.tag_id FROM rules r
+        LEFT JOIN rule_conditions c ON c.rule_id=r.id
+        WHERE {} ORDER BY r.id, c.tag_id",
+        filter
+    ))?;
+    let mut rows = stmt.query([])?;
+    let mut rules: Vec<Rule> = Vec::new();
+    while let Some(row) = rows.next()? {
+        let id: RuleId = row.get(0)?;
+        let condition: Option<TagId> = row.get(3)?;
+        match rules.last_mut() {
+            Some(rule) if rule.id == id => rule.conditions.extend(condition),
+            _ => {
+                let kind: String = row.get(1)?;
+                rules.push(Rule {
+                    id,
+                    kind: kind.parse::<RuleKind>().map_err(|_| {
+                        rusqlite::Error::InvalidColumnType(
+                            1,
+                            "kind".to_owned(),
+                            rusqlite::types::Type::Text,
+                        )
+                    })?,
+                    conditions: condition.into_iter().collect(),
+                    target: row.get(2)?,
+                });
+            }
+        }
+    }
+    Ok(rules)
+}
+/// Filters rules on being exclusions
+const EXCLUSIONS: &str = "r.kind='excludes'";
+/// Gets the exclusion rule adding the tags to the image would break, tags the image already
+/// has don't count as added
+pub fn excluding_rule(
+    img_id: ImageId,
+    adding: &[TagId],
+    conn: &Connection,
+) -> Result<Option<RuleId>> {
+    let exclusions = load_rules(EXCLUSIONS, conn)?;
+    excluding(img_id, adding, &exclusions, conn)
+}
+/// [`excluding_rule`] with the exclusion rules already loaded
+fn excluding(
+    img_id: ImageId,
+    adding: &[TagId],
+    exclusions: &[Rule],
+    conn: &Connection,
+) -> Result<Option<RuleId>> {
+    let mut tags: Vec<TagId> = conn
+        .prepare_cached("SELECT tag_id FROM tag_map WHERE img_id=?1")?
+        .query_map([img_id], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+    let added: Vec<TagId> = adding
+        .iter()
+        .filter(|tag| !tags.contains(tag))
+        .copied()
+        .collect();
+    tags.extend_from_slice(&added);
+    for rule in exclusions {
+        let involved: Vec<&TagId> = rule
+            .conditions
+            .iter()
+            .chain(iter::once(&rule.target))
+            .collect();
+        if involved.iter().all(|tag| tags.contains(tag))
+            && involved.iter().any(|tag| added.contains(tag))
+        {
+            return Ok(Some(rule.id));
+        }
+    }
+    Ok(None)
+}
+/// Error for changes breaking an exclusion rule, like the one of a failed CHECK constraint
+pub(crate) fn excluded_error(rule_id: RuleId) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        ffi::Error::new(ffi::SQLITE_CONSTRAINT_CHECK),
+        Some(format!("Tags are excluded by rule {}", rule_id)),
+    )
+}
+/// Implied tags the image is missing, the image has all their conditions
+const IMPLIED: &str = "SELECT r.target_id FROM rules r WHERE r.kind='implies'
+    AND NOT EXISTS (SELECT id FROM tag_map WHERE tag_map.img_id=?1 AND tag_map.tag_id=r.target_id)
+    AND NOT EXISTS (SELECT id FROM rule_conditions c WHERE c.rule_id=r.id AND NOT EXISTS
+        (SELECT id FROM tag_map WHERE tag_map.img_id=?1 AND tag_map.tag_id=c.tag_id))
+    ORDER BY r.id";
+/// Adds the tags implied by the rules (and their parents) to the image until no rule adds
+/// more, returns the added tags. Implied tags breaking an exclusion rule or sharing an
+/// exclusive namespace with a tag of the image get skipped
+pub fn apply_rules(img_id: ImageId, conn: &Connection) -> Result<Vec<TagId>> {
+    let mut implied = conn.prepare(IMPLIED)?;
+    let mut insert =
+        conn.prepare("INSERT OR IGNORE INTO tag_map(img_id, tag_id) VALUES (?1, ?2)")?;
+    let exclusions = load_rules(EXCLUSIONS, conn)?;
+    let mut added: Vec<TagId> = Vec::new();
+    loop {
+        let targets: Vec<TagId> = implied
+            .query_map([img_id], |row| row.get(0))?
+            .collect::<Result<_>>()?;
+        let count = added.len();
+        for target in targets {
+            let tags: Vec<TagId> = iter::once(target)
+                .chain(subtags::get_parents(target, conn)?)
+                .collect();
+            if excluding(img_id, &tags, &exclusions, conn)?.is_some()
+                || !namespaces::exclusive_conflicts(img_id, &tags, conn)?.is_empty()
+            {
+                continue;
+            }
+            for tag in tags {
+                if insert.execute((img_id, tag))? > 0 {
+                    let change = Change {
+                        img_id: Some(img_id),
+                        tag_id: Some(tag),
+                        ..Default::default()
+                    };
+                    history::record(Operation::TagImage, change, conn)?;
+                    added.push(tag);
+                }
+            }
+        }
+        if added.len() == count {
+            return Ok(added);
+        }
+    }
+}
+/// Applies the rules to every image in a single transaction, returns how many tags got added
+pub fn apply_rules_to_all(conn: &mut Connection) -> Result<usize> {
+    let tx = conn.transaction()?;
+    let imgs: Vec<ImageId> = tx
+        .prepare("SELECT DISTINCT img_id FROM tag_map ORDER BY img_id")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+    let mut added = 0;
+    for img in imgs {
+        added += apply_rules(img, &tx)?.len();
+    }
+    tx.commit()?;
+    Ok(added)
+}
+/// Gets the images which have all conditions of a rule but miss its implied tag or have its
+/// excluded tag, ordered by rule
+pub fn get_violations(conn: &Connection) -> Result<Vec<Violation>> {
+    let mut stmt = conn.prepare(
+        "SELECT r.id, i.img_id FROM rules r JOIN (SELECT DISTINCT img_id FROM tag_map) i
+        WHERE NOT EXISTS (SELECT id FROM rule_conditions c WHERE c.rule_id=r.id AND NOT EXISTS
+            (SELECT id FROM tag_map WHERE tag_map.img_id=i.img_id AND tag_map.tag_id=c.tag_id))
+        AND EXISTS (SELECT id FROM tag_map
+            WHERE tag_map.img_id=i.img_id AND tag_map.tag_id=r.target_id) = (r.kind='excludes')
+        ORDER BY r.id, i.img_id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(Violation {
+            rule_id: row.get(0)?,
+            img_id: row.get(1)?,
+        })
+    })?;
+    rows.collect()
+}
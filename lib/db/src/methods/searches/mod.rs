@@ -0,0 +1,141 @@
+use crate::{
+    methods::{
+        history::{self, Change, Operation},
+        images,
+        utils::{
+            self,
+            macros::{handle_unique, insert_ret_id},
+        },
+    },
+    types::{ImageId, ListOptions, Page, SavedSearch, SearchId, SortBy},
+};
+use rusqlite::{Connection, OptionalExtension, Result, Row};
+
+const SELECT_SEARCHES: &str =
+    "SELECT id, name, query, sort, descending, created FROM saved_searches";
+fn search_from_row(row: &Row) -> Result<SavedSearch> {
+    let sort: String = row.get(3)?;
+    Ok(SavedSearch {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        query: row.get(2)?,
+        sort: sort.parse().unwrap_or_default(),
+        descending: row.get(4)?,
+        created: row.get(5)?,
+    })
+}
+/// Saves a query under a unique name, returns its id if successful
+pub fn add_search(
+    name: &str,
+    query: &str,
+    sort: SortBy,
+    descending: bool,
+    conn: &Connection,
+) -> Option<SearchId> {
+    utils::savepoint(conn, || {
+        let id = SearchId(insert_ret_id!(
+            conn.execute(
+                "INSERT INTO saved_searches(name, query, sort, descending) VALUES (?1, ?2, ?3, ?4)",
+                (name, query, sort.as_str(), descending),
+            ),
+            &conn
+        )?);
+        record_search(Operation::AddSearch, id, None, conn).ok()?;
+        Some(id)
+    })
+}
+fn record_search(
+    operation: Operation,
+    search_id: SearchId,
+    previous: Option<String>,
+    conn: &Connection,
+) -> Result<()> {
+    let change = Change {
+        search_id: Some(search_id),
+        previous,
+        ..Default::default()
+    };
+    history::record(operation, change, conn)
+}
+/// Renames a saved search
+pub fn rename_search(search_id: SearchId, new_name: &str, conn: &Connection) -> Result<()> {
+    let previous = get_search(search_id, conn)?.map(|search| search.name);
+    let changed = conn.execute(
+        "UPDATE saved_searches SET name=?1 WHERE id=?2",
+        (new_name, search_id),
+    )?;
+    if changed > 0 {
+        record_search(Operation::RenameSearch, search_id, previous, conn)?;
+    }
+    Ok(())
+}
+/// Changes the query and order of a saved search
+pub fn update_search(
+    search_id: SearchId,
+    query: &str,
+    sort: SortBy,
+    descending: bool,
+    conn: &Connection,
+) -> Result<()> {
+    let previous = get_search(search_id, conn)?.map(|search| search.query);
+    let changed = conn.execute(
+        "UPDATE saved_searches SET query=?1, sort=?2, descending=?3 WHERE id=?4",
+        (query, sort.as_str(), descending, search_id),
+    )?;
+    if changed > 0 {
+        record_search(Operation::UpdateSearch, search_id, previous, conn)?;
+    }
+    Ok(())
+}
+/// Removes a saved search
+pub fn remove_search(search_id: SearchId, conn: &Connection) -> Result<()> {
+    if get_search(search_id, conn)?.is_some() {
+        record_search(Operation::RemoveSearch, search_id, None, conn)?;
+    }
+    conn.execute("DELETE FROM saved_searches WHERE id=?1", [search_id])?;
+    Ok(())
+}
+/// Gets the saved search with the given id
+pub fn get_search(search_id: SearchId, conn: &Connection) -> Result<Option<SavedSearch>> {
+    conn.query_row(
+        &format!("{} WHERE id=?1", SELECT_SEARCHES),
+        [search_id],
+        search_from_row,
+    )
+    .optional()
+}
+/// Gets the saved search with the given name
+pub fn get_search_by_name(name: &str, conn: &Connection) -> Result<Option<SavedSearch>> {
+    conn.query_row(
+        &format!("{} WHERE name=?1", SELECT_SEARCHES),
+        [name],
+        search_from_row,
+    )
+    .optional()
+}
+/// Gets all saved searches sorted by name
+pub fn get_searches(conn: &Connection) -> Result<Vec<SavedSearch>> {
+    let mut stmt = conn.prepare(&format!("{} ORDER BY name", SELECT_SEARCHES))?;
+    let rows = stmt.query_map([], search_from_row)?;
+    rows.collect()
+}
+/// Runs a saved search, the saved order replaces the one of options. Returns None if the
+/// search doesn't exist
+pub fn run_search_page(
+    search_id: SearchId,
+    options: &ListOptions,
+    conn: &Connection,
+) -> Result<Option<Page<(ImageId, String)>>> {
+    let Some(search) = get_search(search_id, conn)? else {
+        return Ok(None);
+    };
+    let options = options.clone().sorted(search.sort, search.descending);
+    images::query_sql_page(&search.query, &options, conn).map(Some)
+}
+/// Gets all images matched by a saved search, in the saved order
+pub fn run_search(
+    search_id: SearchId,
+    conn: &Connection,
+) -> Result<Option<Vec<(ImageId, String)>>> {
+    Ok(run_search_page(search_id, &ListOptions::default(), conn)?.map(|page| page.items))
+}
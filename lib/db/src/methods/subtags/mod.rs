@@ -1,14 +1,22 @@
-use crate::methods::utils::{self, macros::handle_unique};
+use crate::{
+    methods::tags,
+    methods::{
+        history::{self, Change, Operation},
+        utils::macros::handle_unique,
+    },
+    types::{Tag, TagId, TagNode},
+};
 use rusqlite::{Connection, Result};
+use std::collections::BTreeMap;
 /// Gets all results of a query with a given variable as well as the results of the query with
 /// the results
 macro_rules! recurse {
     ($query:expr, $primary_id:expr) => {{
         let prim_ids = $query.query_map([$primary_id], |row| row.get(0)).unwrap();
 
-        let mut curr: Vec<i64> = Vec::new();
-        let mut total: Vec<i64> = Vec::new();
-        let mut curr_id: i64;
+        let mut curr: Vec<TagId> = Vec::new();
+        let mut total: Vec<TagId> = Vec::new();
+        let mut curr_id: TagId;
 
         for primary in prim_ids {
             curr.push(primary.unwrap());
@@ -24,26 +32,46 @@ macro_rules! recurse {
     }};
 }
 /// Gets all parents of a tag
-pub fn get_parents(child_id: i64, conn: &Connection) -> Result<Vec<i64>> {
+pub fn get_parents(child_id: TagId, conn: &Connection) -> Result<Vec<TagId>> {
     let mut stmt = conn.prepare("SELECT parent_id FROM subtag_map WHERE child_id=?1")?;
     recurse!(stmt, child_id)
 }
 /// Gets all children of a tag
-pub fn get_children(parent_id: i64, conn: &Connection) -> Result<Vec<i64>> {
+pub fn get_children(parent_id: TagId, conn: &Connection) -> Result<Vec<TagId>> {
     let mut stmt = conn.prepare("SELECT child_id FROM subtag_map WHERE parent_id=?1")?;
     recurse!(stmt, parent_id)
 }
-/// Sets a tag as parent of another tag, returns None if it fails
-pub fn parent_tag(parent_id: i64, child_id: i64, conn: &Connection) -> Option<()> {
-    if Option::is_none(&utils::get_id(
-        "subtag_map",
-        &format!("parent_id={} AND child_id={}", child_id, parent_id),
-        conn,
-    )) {
+/// Gets all tags below a tag once, ending at loops. The tag itself is only included if it is
+/// in a loop
+pub(crate) fn get_descendants(parent_id: TagId, conn: &Connection) -> Result<Vec<TagId>> {
+    let mut stmt = conn.prepare(
+        "WITH RECURSIVE below(id) AS (
+            SELECT child_id FROM subtag_map WHERE parent_id=?1
+            UNION
+            SELECT subtag_map.child_id FROM subtag_map
+            JOIN below ON subtag_map.parent_id=below.id
+        )
+        SELECT id FROM below",
+    )?;
+    let rows = stmt.query_map([parent_id], |row| row.get(0))?;
+    rows.collect()
+}
+/// Sets a tag as parent of another tag, returns None if it fails or the parent is already
+/// below the child
+pub fn parent_tag(parent_id: TagId, child_id: TagId, conn: &Connection) -> Option<()> {
+    if parent_id != child_id && !get_descendants(child_id, conn).ok()?.contains(&parent_id) {
         match handle_unique!(conn.execute(
             "INSERT INTO subtag_map(parent_id, child_id) VALUES (?1, ?2)",
-            [parent_id, child_id],
+            (parent_id, child_id),
         )) {
+            Ok(1) => {
+                let change = Change {
+                    tag_id: Some(child_id),
+                    parent_id: Some(parent_id),
+                    ..Default::default()
+                };
+                history::record(Operation::AddParent, change, conn).ok()?;
+            }
             Ok(_) => {}
             Err(err) => return None,
         }
@@ -53,7 +81,24 @@ pub fn parent_tag(parent_id: i64, child_id: i64, conn: &Connection) -> Option<()
     Some(())
 }
 /// Deletes the connection between two tags
-pub fn remove_connection(tag1: i64, tag2: i64, conn: &Connection) -> Option<()> {
+pub fn remove_connection(tag1: TagId, tag2: TagId, conn: &Connection) -> Option<()> {
+    let existing = conn
+        .query_row(
+            "SELECT parent_id, child_id FROM subtag_map
+            WHERE (parent_id=?1 AND child_id=?2)
+            OR (parent_id=?2 AND child_id=?1)",
+            [tag1, tag2],
+            |row| Ok((row.get::<usize, TagId>(0)?, row.get::<usize, TagId>(1)?)),
+        )
+        .ok();
+    if let Some((parent_id, child_id)) = existing {
+        let change = Change {
+            tag_id: Some(child_id),
+            parent_id: Some(parent_id),
+            ..Default::default()
+        };
+        history::record(Operation::RemoveConnection, change, conn).ok()?;
+    }
     match conn.execute(
         "
                      DELETE FROM subtag_map
@@ -69,3 +114,109 @@ pub fn remove_connection(tag1: i64, tag2: i64, conn: &Connection) -> Option<()>
         }
     }
 }
+/// Gets the tag and everything below it as a tree
+pub fn get_tree(root: TagId, conn: &Connection) -> Result<TagNode> {
+    let mut ids = get_descendants(root, conn)?;
+    if !ids.contains(&root) {
+        ids.push(root);
+    }
+    let tags: BTreeMap<TagId, Tag> = tags::load_tags(&ids, conn)?
+        .into_iter()
+        .map(|tag| (tag.id, tag))
+        .collect();
+    let children = children_of(&tags);
+    build_node(root, &tags, &children, &mut Vec::new()).ok_or(rusqlite::Error::QueryReturnedNoRows)
+}
+/// Gets a tree for every tag without parents, sorted by name
+pub fn get_forest(conn: &Connection) -> Result<Vec<TagNode>> {
+    let mut tags = BTreeMap::new();
+    tags::for_each_tag(conn, |tag| {
+        tags.insert(tag.id, tag);
+    })?;
+    let children = children_of(&tags);
+    let mut roots: Vec<&Tag> = tags.values().filter(|tag| tag.parents.is_empty()).collect();
+    roots.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(roots
+        .into_iter()
+        .filter_map(|root| build_node(root.id, &tags, &children, &mut Vec::new()))
+        .collect())
+}
+/// Gets the tags without parents
+pub fn get_roots(conn: &Connection) -> Result<Vec<TagId>> {
+    let mut stmt = conn.prepare(
+        "SELECT id FROM tags WHERE id NOT IN (SELECT child_id FROM subtag_map) ORDER BY id",
+    )?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    rows.collect()
+}
+/// Gets the tags without children
+pub fn get_leaves(conn: &Connection) -> Result<Vec<TagId>> {
+    let mut stmt = conn.prepare(
+        "SELECT id FROM tags WHERE id NOT IN (SELECT parent_id FROM subtag_map) ORDER BY id",
+    )?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    rows.collect()
+}
+/// Gets the depth of every tag, the number of tags on the longest chain of parents above it.
+/// Tags without parents have depth 0. Chains end before repeating a tag, tags which are only
+/// below loops count from the loop
+pub fn get_depths(conn: &Connection) -> Result<Vec<(TagId, usize)>> {
+    let mut stmt = conn.prepare(
+        "WITH RECURSIVE roots(id) AS (
+            SELECT id FROM tags WHERE id NOT IN (SELECT child_id FROM subtag_map)
+        ),
+        reached(id) AS (
+            SELECT id FROM roots
+            UNION
+            SELECT subtag_map.child_id FROM subtag_map JOIN reached ON subtag_map.parent_id=reached.id
+        ),
+        depths(id, depth, path) AS (
+            SELECT id, 0, ',' || id || ',' FROM tags
+            WHERE id IN (SELECT id FROM roots) OR id NOT IN (SELECT id FROM reached)
+            UNION ALL
+            SELECT subtag_map.child_id, depths.depth + 1, depths.path || subtag_map.child_id || ','
+            FROM subtag_map JOIN depths ON subtag_map.parent_id=depths.id
+            WHERE instr(depths.path, ',' || subtag_map.child_id || ',') = 0
+        )
+        SELECT id, MAX(depth) FROM depths GROUP BY id ORDER BY id",
+    )?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+/// Direct children of the loaded tags, sorted by name
+fn children_of(tags: &BTreeMap<TagId, Tag>) -> BTreeMap<TagId, Vec<TagId>> {
+    let mut children: BTreeMap<TagId, Vec<TagId>> = BTreeMap::new();
+    for tag in tags.values() {
+        for parent in &tag.parents {
+            children.entry(*parent).or_default().push(tag.id);
+        }
+    }
+    for ids in children.values_mut() {
+        ids.sort_by(|a, b| tags[a].name.cmp(&tags[b].name));
+    }
+    children
+}
+/// Builds the node of a loaded tag, path holds the tags above it so loops end
+fn build_node(
+    id: TagId,
+    tags: &BTreeMap<TagId, Tag>,
+    children: &BTreeMap<TagId, Vec<TagId>>,
+    path: &mut Vec<TagId>,
+) -> Option<TagNode> {
+    let tag = tags.get(&id)?;
+    path.push(id);
+    let mut nodes = Vec::new();
+    for child in children.get(&id).into_iter().flatten() {
+        if !path.contains(child) {
+            nodes.extend(build_node(*child, tags, children, path));
+        }
+    }
+    path.pop();
+    Some(TagNode {
+        id,
+        name: tag.name.clone(),
+        namespace: tag.namespace.clone(),
+        image_count: tag.image_count,
+        children: nodes,
+    })
+}
@@ -0,0 +1,43 @@
+use crate::types::{ImageId, Suggestion};
+use rusqlite::{Connection, Result};
+/// Suggests tags for an image from how often other tags appear together with its tags,
+/// best first. The confidence of a tag is the highest share of images with one of the tags
+/// of the image which also have it, ties are broken by the PMI and the number of images
+/// having both. Images without tags get no suggestions
+pub fn suggest_tags(img_id: ImageId, limit: usize, conn: &Connection) -> Result<Vec<Suggestion>> {
+    let tagged: i64 = conn.query_row("SELECT COUNT(DISTINCT img_id) FROM tag_map", [], |row| {
+        row.get(0)
+    })?;
+    let mut stmt = conn.prepare(
+        "WITH own AS (SELECT tag_id FROM tag_map WHERE img_id=?1),
+        counts AS (SELECT tag_id, COUNT(*) AS n FROM tag_map GROUP BY tag_id),
+        pairs AS (
+            SELECT a.tag_id AS tag, b.tag_id AS candidate, COUNT(*) AS together
+            FROM tag_map a JOIN tag_map b ON b.img_id=a.img_id
+            WHERE a.tag_id IN own AND b.tag_id NOT IN own
+            GROUP BY a.tag_id, b.tag_id
+        )
+        SELECT pairs.candidate, tags.name,
+            MAX(CAST(pairs.together AS REAL) / tag_counts.n) AS confidence,
+            MAX(CAST(pairs.together AS REAL) * ?2 / (tag_counts.n * candidate_counts.n)) AS lift,
+            SUM(pairs.together) AS support
+        FROM pairs
+        JOIN counts tag_counts ON tag_counts.tag_id=pairs.tag
+        JOIN counts candidate_counts ON candidate_counts.tag_id=pairs.candidate
+        JOIN tags ON tags.id=pairs.candidate
+        GROUP BY pairs.candidate
+        ORDER BY confidence DESC, lift DESC, support DESC, tags.name
+        LIMIT ?3",
+    )?;
+    let rows = stmt.query_map((img_id, tagged, limit as i64), |row| {
+        Ok(Suggestion {
+            tag_id: row.get(0)?,
+            name: row.get(1)?,
+            confidence: row.get(2)?,
+            // SQLite doesn't always come with math functions
+            pmi: row.get::<usize, f64>(3)?.log2(),
+            support: row.get(4)?,
+        })
+    })?;
+    rows.collect()
+}
@@ -0,0 +1,554 @@
+use crate::{
+    methods::{images, init, namespaces, subtags, tags},
+    types::{Conflict, ConflictKind, ImageId, NamespaceId, Side, SyncChange, SyncReport, TagId},
+};
+use rusqlite::{ffi, types::FromSql, Connection, OpenFlags, OptionalExtension, Params, Result};
+use sha2::{Digest, Sha256};
+use std::{fs::File, io, path::Path};
+/// One of the databases getting synchronized, with the changes made to it
+struct Peer<'c> {
+    side: Side,
+    conn: &'c Connection,
+    changes: Vec<SyncChange>,
+}
+/// A step of the sync, handling what src has and dst doesn't
+type Step = fn(&mut Peer, &mut Peer, &mut Vec<Conflict>) -> Result<()>;
+/// Makes both databases hold the same tags, images, tags of images, parents and namespaces of
+/// tags. Tags are matched by name and images by path, or by the hash of their file if the
+/// paths differ, e.g. on another computer. Whatever only one database has gets copied, unless
+/// the history of the other one shows it deleted, renamed or moved it, then that gets repeated.
+/// Deletions of something changed afterwards on the other side are skipped and reported as
+/// conflicts, so the clocks of both computers should agree. Values, properties and groups
+/// aren't synchronized. With dry_run nothing changes and the report shows what would.
+/// Images of the local database get hashed first, the remote one's paths belong to another
+/// computer, so [`hash_images`] has to run there. The remote database gets committed first,
+/// if committing the local one fails after that the error says so and syncing again finishes
+/// the local side
+pub fn sync(local: &mut Connection, remote: &mut Connection, dry_run: bool) -> Result<SyncReport> {
+    let local_tx = local.transaction()?;
+    let remote_tx = remote.transaction()?;
+    hash_images(&local_tx)?;
+    let mut local_peer = Peer {
+        side: Side::Local,
+        conn: &local_tx,
+        changes: Vec::new(),
+    };
+    let mut remote_peer = Peer {
+        side: Side::Remote,
+        conn: &remote_tx,
+        changes: Vec::new(),
+    };
+    let mut conflicts = Vec::new();
+    let steps: [Step; 5] = [
+        sync_tags,
+        sync_images,
+        sync_tagging,
+        sync_parents,
+        sync_namespaces,
+    ];
+    for step in steps {
+        step(&mut local_peer, &mut remote_peer, &mut conflicts)?;
+        step(&mut remote_peer, &mut local_peer, &mut conflicts)?;
+    }
+    let report = SyncReport {
+        local: local_peer.changes,
+        remote: remote_peer.changes,
+        conflicts,
+    };
+    if !dry_run {
+        remote_tx.commit()?;
+        local_tx.commit().map_err(|err| {
+            rusqlite::Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_ERROR),
+                Some(format!(
+                    "Only the remote database got synced, committing the local one failed: {}",
+                    err
+                )),
+            )
+        })?;
+    }
+    Ok(report)
+}
+/// Opens the other database of a sync, a missing file is an error instead of a new empty
+/// database everything would get copied to
+pub fn open_remote(path: &Path) -> Result<Connection> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+    init::init_tables(&conn)?;
+    Ok(conn)
+}
+/// Stores the SHA-256 of the files of images without one, images whose file can't be read are
+/// skipped. Returns how many got hashed
+pub fn hash_images(conn: &Connection) -> Result<usize> {
+    let missing = conn
+        .prepare("SELECT id, path FROM images WHERE hash IS NULL")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<(ImageId, String)>>>()?;
+    let mut hashed = 0;
+    for (id, path) in missing {
+        if let Ok(hash) = hash_file(&path) {
+            conn.execute("UPDATE images SET hash=?1 WHERE id=?2", (hash, id))?;
+            hashed += 1;
+        }
+    }
+    Ok(hashed)
+}
+fn hash_file(path: &str) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+/// Tags only src has get renamed, merged or removed like on dst, or copied to dst
+fn sync_tags(src: &mut Peer, dst: &mut Peer, conflicts: &mut Vec<Conflict>) -> Result<()> {
+    for name in column("SELECT name FROM tags ORDER BY id", src.conn)? {
+        // earlier tags of this step can rename or merge it away
+        let Some(id) = tag_id(&name, src.conn)? else {
+            continue;
+        };
+        if tag_id(&name, dst.conn)?.is_some() {
+            continue;
+        }
+        let moved: Option<TagId> = latest(
+            "tag_id",
+            "operation IN ('rename_tag', 'merge_tag') AND previous=?1",
+            [&name],
+            dst.conn,
+        )?;
+        if let Some(current) = moved.and_then(|moved| tags::get_name(moved, dst.conn)) {
+            match tag_id(&current, src.conn)? {
+                Some(target) => {
+                    tags::merge_into(id, target, src.conn)?;
+                    src.changes.push(SyncChange::MergeTag {
+                        from: name,
+                        to: current,
+                    });
+                }
+                None => {
+                    tags::rename_tag(id, &current, src.conn)?;
+                    src.changes.push(SyncChange::RenameTag {
+                        from: name,
+                        to: current,
+                    });
+                }
+            }
+            continue;
+        }
+        let removed: Option<i64> = match moved {
+            Some(moved) => latest(
+                "timestamp",
+                "operation='remove_tag' AND tag_id=?1",
+                [moved],
+                dst.conn,
+            )?,
+            None => latest(
+                "timestamp",
+                "operation='remove_tag' AND tag_name=?1",
+                [&name],
+                dst.conn,
+            )?,
+        };
+        if let Some(removed) = removed {
+            let changed: i64 = latest(
+                "timestamp",
+                "(tag_name=?1 OR parent_name=?1) AND operation!='remove_tag'",
+                [&name],
+                src.conn,
+            )?
+            .unwrap_or(0);
+            let change = SyncChange::RemoveTag(name.clone());
+            if changed <= removed {
+                tags::remove_tag(id, src.conn)?;
+                src.changes.push(change);
+                continue;
+            }
+            conflicts.push(Conflict {
+                side: src.side,
+                skipped: change,
+                kind: ConflictKind::ChangedAfterDelete,
+            });
+        }
+        let origin: Option<String> = latest(
+            "previous",
+            "operation='rename_tag' AND tag_name=?1",
+            [&name],
+            src.conn,
+        )?;
+        if let Some(origin) = origin.filter(|origin| *origin != name) {
+            if let Some(old) = tag_id(&origin, dst.conn)? {
+                if tag_id(&origin, src.conn)?.is_none() {
+                    tags::rename_tag(old, &name, dst.conn)?;
+                    dst.changes.push(SyncChange::RenameTag {
+                        from: origin,
+                        to: name,
+                    });
+                    continue;
+                }
+            }
+            let other: Option<TagId> = latest(
+                "tag_id",
+                "operation='rename_tag' AND previous=?1",
+                [&origin],
+                dst.conn,
+            )?;
+            if let Some(other) = other.and_then(|other| tags::get_name(other, dst.conn)) {
+                conflicts.push(Conflict {
+                    side: dst.side,
+                    skipped: SyncChange::RenameTag {
+                        from: other,
+                        to: name,
+                    },
+                    kind: ConflictKind::RenamedDifferently,
+                });
+                continue;
+            }
+        }
+        if tags::add_tag(&name, dst.conn).is_some() {
+            dst.changes.push(SyncChange::AddTag(name));
+        }
+    }
+    Ok(())
+}
+/// Images only src has get moved or removed like on dst, or copied to dst
+fn sync_images(src: &mut Peer, dst: &mut Peer, conflicts: &mut Vec<Conflict>) -> Result<()> {
+    for path in column("SELECT path FROM images ORDER BY id", src.conn)? {
+        let Some(id) = image_id(&path, src.conn)? else {
+            continue;
+        };
+        if counterpart(id, src.conn, dst.conn)?.is_some() {
+            continue;
+        }
+        let moved: Option<ImageId> = latest(
+            "img_id",
+            "operation='update_path' AND previous=?1",
+            [&path],
+            dst.conn,
+        )?;
+        if let Some(current) = moved.and_then(|moved| images::get_path(moved, dst.conn)) {
+            if image_id(&current, src.conn)?.is_none() {
+                images::update_path(id, &current, src.conn)?;
+                src.changes.push(SyncChange::MoveImage {
+                    from: path,
+                    to: current,
+                });
+                continue;
+            }
+        }
+        let removed: Option<i64> = match moved {
+            Some(moved) => latest(
+                "timestamp",
+                "operation='remove_image' AND img_id=?1",
+                [moved],
+                dst.conn,
+            )?,
+            None => latest(
+                "timestamp",
+                "operation='remove_image' AND img_path=?1",
+                [&path],
+                dst.conn,
+            )?,
+        };
+        if let Some(removed) = removed {
+            let changed: i64 = latest(
+                "timestamp",
+                "img_path=?1 AND operation!='remove_image'",
+                [&path],
+                src.conn,
+            )?
+            .unwrap_or(0);
+            let change = SyncChange::RemoveImage(path.clone());
+            if changed <= removed {
+                images::remove_image_path(&path, src.conn)?;
+                src.changes.push(change);
+                continue;
+            }
+            conflicts.push(Conflict {
+                side: src.side,
+                skipped: change,
+                kind: ConflictKind::ChangedAfterDelete,
+            });
+        }
+        let origin: Option<String> = latest(
+            "previous",
+            "operation='update_path' AND img_path=?1",
+            [&path],
+            src.conn,
+        )?;
+        if let Some(origin) = origin.filter(|origin| *origin != path) {
+            if let Some(old) = image_id(&origin, dst.conn)? {
+                if image_id(&origin, src.conn)?.is_none() {
+                    images::update_path(old, &path, dst.conn)?;
+                    dst.changes.push(SyncChange::MoveImage {
+                        from: origin,
+                        to: path,
+                    });
+                    continue;
+                }
+            }
+        }
+        if let Some(new) = images::add_image(&path, dst.conn) {
+            dst.conn.execute(
+                "UPDATE images SET hash=(?1) WHERE id=?2",
+                (hash_of(id, src.conn)?, new),
+            )?;
+            dst.changes.push(SyncChange::AddImage(path));
+        }
+    }
+    Ok(())
+}
+/// Tags only src has on an image get removed if dst removed them later, or added on dst
+fn sync_tagging(src: &mut Peer, dst: &mut Peer, conflicts: &mut Vec<Conflict>) -> Result<()> {
+    for path in column("SELECT path FROM images ORDER BY id", src.conn)? {
+        let Some(img) = image_id(&path, src.conn)? else {
+            continue;
+        };
+        let Some(other) = counterpart(img, src.conn, dst.conn)? else {
+            continue;
+        };
+        let other_path = images::get_path(other, dst.conn).unwrap_or_default();
+        for (id, tag) in images::get_tags_of_img(img, src.conn) {
+            // adding a tag adds its parents too
+            if tag_id(&tag, dst.conn)?.is_none() || has_tag(other, &tag, dst.conn)? {
+                continue;
+            }
+            let untagged: Option<i64> = latest(
+                "timestamp",
+                "operation='untag_image' AND img_id=?1 AND tag_name=?2",
+                (other, &tag),
+                dst.conn,
+            )?;
+            let tagged: i64 = latest(
+                "timestamp",
+                "operation='tag_image' AND img_id=?1 AND tag_name=?2",
+                (img, &tag),
+                src.conn,
+            )?
+            .unwrap_or(0);
+            if untagged.is_some_and(|untagged| untagged >= tagged) {
+                tags::remove_tag_from_img(id, img, src.conn)?;
+                src.changes.push(SyncChange::UntagImage {
+                    path: path.clone(),
+                    tag,
+                });
+                continue;
+            }
+            let added = tags::add_tag_to_img(&tag, other, false, dst.conn).is_some();
+            let change = SyncChange::TagImage {
+                path: other_path.clone(),
+                tag,
+            };
+            if added {
+                dst.changes.push(change);
+            } else {
+                conflicts.push(Conflict {
+                    side: dst.side,
+                    skipped: change,
+                    kind: ConflictKind::Refused,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+/// Parents only src has get removed if dst removed them later, or added on dst
+fn sync_parents(src: &mut Peer, dst: &mut Peer, conflicts: &mut Vec<Conflict>) -> Result<()> {
+    let connections = pairs(
+        "SELECT parents.name, children.name FROM subtag_map
+        JOIN tags parents ON parents.id=subtag_map.parent_id
+        JOIN tags children ON children.id=subtag_map.child_id
+        ORDER BY subtag_map.id",
+        src.conn,
+    )?;
+    for (parent, child) in connections {
+        let (Some(parent_id), Some(child_id)) =
+            (tag_id(&parent, dst.conn)?, tag_id(&child, dst.conn)?)
+        else {
+            continue;
+        };
+        let connected: bool = dst.conn.query_row(
+            "SELECT EXISTS (SELECT id FROM subtag_map WHERE parent_id=?1 AND child_id=?2)",
+            [parent_id, child_id],
+            |row| row.get(0),
+        )?;
+        if connected {
+            continue;
+        }
+        let removed: Option<i64> = latest(
+            "timestamp",
+            "operation='remove_connection' AND tag_name=?1 AND parent_name=?2",
+            [&child, &parent],
+            dst.conn,
+        )?;
+        let added: i64 = latest(
+            "timestamp",
+            "operation='add_parent' AND tag_name=?1 AND parent_name=?2",
+            [&child, &parent],
+            src.conn,
+        )?
+        .unwrap_or(0);
+        if removed.is_some_and(|removed| removed >= added) {
+            if let (Some(src_parent), Some(src_child)) =
+                (tag_id(&parent, src.conn)?, tag_id(&child, src.conn)?)
+            {
+                subtags::remove_connection(src_parent, src_child, src.conn);
+                src.changes.push(SyncChange::RemoveParent { parent, child });
+            }
+            continue;
+        }
+        let change = SyncChange::AddParent { parent, child };
+        if subtags::parent_tag(parent_id, child_id, dst.conn).is_some() {
+            dst.changes.push(change);
+        } else {
+            conflicts.push(Conflict {
+                side: dst.side,
+                skipped: change,
+                kind: ConflictKind::Refused,
+            });
+        }
+    }
+    Ok(())
+}
+/// Namespaces of tags which differ get set to the newer one, namespaces only src has get
+/// removed if dst removed them later, or set on dst
+fn sync_namespaces(src: &mut Peer, dst: &mut Peer, _: &mut Vec<Conflict>) -> Result<()> {
+    let namespaced = pairs(
+        "SELECT tags.name, namespaces.name FROM namespace_map
+        JOIN tags ON tags.id=namespace_map.tag_id
+        JOIN namespaces ON namespaces.id=namespace_map.namespace_id
+        ORDER BY tags.id",
+        src.conn,
+    )?;
+    for (tag, namespace) in namespaced {
+        let Some(dst_tag) = tag_id(&tag, dst.conn)? else {
+            continue;
+        };
+        let current = namespaces::get_namespace_of_tag(dst_tag, dst.conn);
+        let current_name = current.and_then(|id| namespaces::get_namespace_name(id, dst.conn));
+        if current_name.as_ref() == Some(&namespace) {
+            continue;
+        }
+        let set = |conn: &Connection| {
+            latest::<i64>(
+                "timestamp",
+                "operation='add_namespace_to_tag' AND tag_name=?1",
+                [&tag],
+                conn,
+            )
+            .map(Option::unwrap_or_default)
+        };
+        let src_set = set(src.conn)?;
+        if current.is_some() {
+            // the newer namespace wins, the local one on ties
+            let dst_set = set(dst.conn)?;
+            if src_set < dst_set || (src_set == dst_set && src.side == Side::Remote) {
+                continue;
+            }
+            namespaces::remove_namespace_from_tag(dst_tag, dst.conn)?;
+        } else {
+            let removed: Option<i64> = latest(
+                "timestamp",
+                "operation='remove_namespace_from_tag' AND tag_name=?1",
+                [&tag],
+                dst.conn,
+            )?;
+            if removed.is_some_and(|removed| removed >= src_set) {
+                if let Some(src_tag) = tag_id(&tag, src.conn)? {
+                    namespaces::remove_namespace_from_tag(src_tag, src.conn)?;
+                    src.changes.push(SyncChange::RemoveNamespace { tag });
+                }
+                continue;
+            }
+        }
+        let namespace_id: Option<NamespaceId> = dst
+            .conn
+            .query_row(
+                "SELECT id FROM namespaces WHERE name=?1",
+                [&namespace],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(namespace_id) =
+            namespace_id.or_else(|| namespaces::add_namespace(&namespace, dst.conn))
+        else {
+            continue;
+        };
+        namespaces::add_namespace_to_tag(namespace_id, dst_tag, dst.conn)?;
+        dst.changes
+            .push(SyncChange::SetNamespace { tag, namespace });
+    }
+    Ok(())
+}
+/// Gets the column of the latest history entry matching the filter, None if there is none or
+/// the column is NULL
+fn latest<T: FromSql>(
+    column: &str,
+    filter: &str,
+    params: impl Params,
+    conn: &Connection,
+) -> Result<Option<T>> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM history WHERE {} ORDER BY id DESC LIMIT 1",
+            column, filter
+        ),
+        params,
+        |row| row.get::<usize, Option<T>>(0),
+    )
+    .optional()
+    .map(Option::flatten)
+}
+fn column(query: &str, conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(query)?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    rows.collect()
+}
+fn pairs(query: &str, conn: &Connection) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare(query)?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+/// Gets the id of the tag with exactly this name, aliases don't count
+fn tag_id(name: &str, conn: &Connection) -> Result<Option<TagId>> {
+    conn.query_row("SELECT id FROM tags WHERE name=?1", [name], |row| {
+        row.get(0)
+    })
+    .optional()
+}
+fn image_id(path: &str, conn: &Connection) -> Result<Option<ImageId>> {
+    conn.query_row("SELECT id FROM images WHERE path=?1", [path], |row| {
+        row.get(0)
+    })
+    .optional()
+}
+fn hash_of(img: ImageId, conn: &Connection) -> Result<Option<String>> {
+    conn.query_row("SELECT hash FROM images WHERE id=?1", [img], |row| {
+        row.get(0)
+    })
+}
+fn has_tag(img: ImageId, tag: &str, conn: &Connection) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT tag_map.id FROM tag_map JOIN tags ON tags.id=tag_map.tag_id
+            WHERE tag_map.img_id=?1 AND tags.name=?2)",
+        (img, tag),
+        |row| row.get(0),
+    )
+}
+/// Gets the image of dst with the same path, or else with the same hash and a path src
+/// doesn't have
+fn counterpart(img: ImageId, src: &Connection, dst: &Connection) -> Result<Option<ImageId>> {
+    let path = images::get_path(img, src).unwrap_or_default();
+    if let Some(id) = image_id(&path, dst)? {
+        return Ok(Some(id));
+    }
+    let Some(hash) = hash_of(img, src)? else {
+        return Ok(None);
+    };
+    let mut stmt = dst.prepare("SELECT id, path FROM images WHERE hash=?1 ORDER BY id")?;
+    let candidates = stmt
+        .query_map([hash], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<(ImageId, String)>>>()?;
+    for (id, path) in candidates {
+        if image_id(&path, src)?.is_none() {
+            return Ok(Some(id));
+        }
+    }
+    Ok(None)
+}
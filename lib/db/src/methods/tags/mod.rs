@@ -1,118 +1,631 @@
-use crate::methods::{
-    subtags, tags,
-    utils::{
-        self,
-        macros::{handle_unique, insert_ret_id},
+use crate::{
+    methods::{
+        history::{self, Change, Operation},
+        listing::{self, Table},
+        namespaces, rules, subtags, tags,
+        utils::{
+            self,
+            macros::{handle_unique, insert_ret_id},
+        },
     },
+    types::{Exclusivity, ImageId, ListOptions, NamespaceId, Page, Tag, TagId},
 };
-use rusqlite::{Connection, Result};
-/// adds tag to image, if create is true nonexistent tags will get created
-pub fn add_tag_to_img(tag: &str, img: i64, create: bool, conn: &Connection) -> Option<()> {
-    let mut insert = conn
-        .prepare("INSERT INTO tag_map(img_id, tag_id) VALUES (?1, ?2)")
-        .unwrap();
-    // primary tag
-    let primary_id = match utils::get_id("tags", &format!("name='{}'", tag), conn) {
-        Some(id) => id,
-        None => {
-            if create {
-                tags::add_tag(tag, conn).unwrap()
-            } else {
-                eprintln!("Tag {} doesn't exist and shouldn't be created", tag);
-                return None;
+use regex::Regex;
+use rusqlite::{ffi, Connection, OptionalExtension, Result, Row};
+use std::collections::HashMap;
+/// adds tag to image, if create is true nonexistent tags will get created. Tags implied by rules
+/// get added too, tags excluded by a rule don't get added. Other tags of an exclusive namespace
+/// get replaced or keep the tag from being added. Nothing changes if any step fails
+pub fn add_tag_to_img(tag: &str, img: ImageId, create: bool, conn: &Connection) -> Option<()> {
+    utils::savepoint(conn, || {
+        let mut insert = conn
+            .prepare("INSERT INTO tag_map(img_id, tag_id) VALUES (?1, ?2)")
+            .ok()?;
+        // primary tag
+        let primary_id = match resolve(tag, conn) {
+            Some(id) => id,
+            None => {
+                if create {
+                    tags::add_tag(tag, conn)?
+                } else {
+                    eprintln!("Tag {} doesn't exist and shouldn't be created", tag);
+                    return None;
+                }
+            }
+        };
+        let parents = subtags::get_parents(primary_id, conn).unwrap_or({
+            eprintln!("Failed to get parents for tag {}", primary_id);
+            vec![]
+        });
+        let mut adding: Vec<TagId> = std::iter::once(primary_id).chain(parents).collect();
+        if let Err(err) = namespaces::keep_one_exclusive(&mut adding, &[primary_id], conn) {
+            eprintln!("Tag {} can't be added: {}", tag, err);
+            return None;
+        }
+        if let Some(rule) = rules::excluding_rule(img, &adding, conn).ok()? {
+            eprintln!("Tag {} is excluded by rule {}", tag, rule);
+            return None;
+        }
+        let conflicts = namespaces::exclusive_conflicts(img, &adding, conn).ok()?;
+        if conflicts
+            .iter()
+            .any(|conflict| conflict.1 == Exclusivity::Refuse)
+        {
+            eprintln!("Image already has a tag of the namespace of {}", tag);
+            return None;
+        }
+        for (other, _) in conflicts {
+            remove_tag_from_img(other, img, conn).ok()?;
+        }
+        // primary tag, then its parents
+        for tag in adding {
+            if handle_unique!(insert.execute((img, tag))).ok()? == 1 {
+                record_tagging(Operation::TagImage, tag, img, conn).ok()?;
+            }
+        }
+        rules::apply_rules(img, conn).ok()?;
+        Some(())
+    })
+}
+pub(crate) fn record_tagging(
+    operation: Operation,
+    tag_id: TagId,
+    img_id: ImageId,
+    conn: &Connection,
+) -> Result<()> {
+    let change = Change {
+        img_id: Some(img_id),
+        tag_id: Some(tag_id),
+        ..Default::default()
+    };
+    history::record(operation, change, conn)
+}
+/// Tags added to and removed from an image by a batch operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BatchSummary {
+    pub img_id: ImageId,
+    /// Ids of the tags which got added, including implied parents
+    pub added: Vec<TagId>,
+    /// Ids of the tags which got removed
+    pub removed: Vec<TagId>,
+}
+/// Removes and then adds the given tags (and parents of added tags) on all given images in a
+/// single transaction, then the tags implied by rules. Other tags of exclusive namespaces which
+/// replace them get removed. Tag names and parents only get resolved once. If an image doesn't
+/// exist, a tag doesn't exist and create is false, an exclusion rule would be broken or a
+/// refusing exclusive namespace has a tag on the image or more than one of the tags being added,
+/// nothing gets changed and an error is returned. Of tags being added which share a replacing
+/// one only the last given one stays, parents only stay if no given tag is in it
+pub fn batch_tag(
+    imgs: &[ImageId],
+    add: &[&str],
+    remove: &[&str],
+    create: bool,
+    conn: &mut Connection,
+) -> Result<Vec<BatchSummary>> {
+    let tx = conn.transaction()?;
+    let mut summaries: Vec<BatchSummary> = Vec::new();
+    {
+        let mut get_tag = tx.prepare(RESOLVE)?;
+        let mut to_add: Vec<TagId> = Vec::new();
+        let mut requested: Vec<TagId> = Vec::new();
+        for name in add {
+            let id = match get_tag.query_row([name], |row| row.get(0)) {
+                Ok(id) => id,
+                Err(rusqlite::Error::QueryReturnedNoRows) if create => {
+                    add_tag(name, &tx).ok_or(rusqlite::Error::QueryReturnedNoRows)?
+                }
+                Err(err) => return Err(err),
+            };
+            requested.push(id);
+            for tag in std::iter::once(id).chain(subtags::get_parents(id, &tx)?) {
+                if !to_add.contains(&tag) {
+                    to_add.push(tag);
+                }
+            }
+        }
+        namespaces::keep_one_exclusive(&mut to_add, &requested, &tx)?;
+        let mut to_remove: Vec<TagId> = Vec::new();
+        for name in remove {
+            match get_tag.query_row([name], |row| row.get(0)) {
+                Ok(id) => to_remove.push(id),
+                Err(rusqlite::Error::QueryReturnedNoRows) => {} // nothing to remove
+                Err(err) => return Err(err),
             }
         }
-    }; // if there was no id found, panic
-    handle_unique!(insert.execute([img, primary_id])).unwrap();
 
-    // parent tags
-    let parents = subtags::get_parents(primary_id, conn).unwrap_or({
-        eprintln!("Failed to get parents for tag {}", primary_id);
-        vec![]
-    });
-    for parent in parents {
-        insert.execute([img, parent]).unwrap();
+        let mut image_exists = tx.prepare("SELECT id FROM images WHERE id=?1")?;
+        let mut insert =
+            tx.prepare("INSERT OR IGNORE INTO tag_map(img_id, tag_id) VALUES (?1, ?2)")?;
+        let mut delete = tx.prepare("DELETE FROM tag_map WHERE img_id=?1 AND tag_id=?2")?;
+        for &img in imgs {
+            image_exists.query_row([img], |row| row.get::<usize, i64>(0))?;
+            let mut summary = BatchSummary {
+                img_id: img,
+                added: vec![],
+                removed: vec![],
+            };
+            for &tag in &to_remove {
+                if delete.execute((img, tag))? > 0 {
+                    record_tagging(Operation::UntagImage, tag, img, &tx)?;
+                    summary.removed.push(tag);
+                }
+            }
+            if let Some(rule) = rules::excluding_rule(img, &to_add, &tx)? {
+                return Err(rules::excluded_error(rule));
+            }
+            for (other, exclusive) in namespaces::exclusive_conflicts(img, &to_add, &tx)? {
+                if exclusive == Exclusivity::Refuse {
+                    return Err(namespaces::exclusive_error(other));
+                }
+                delete.execute((img, other))?;
+                record_tagging(Operation::UntagImage, other, img, &tx)?;
+                summary.removed.push(other);
+            }
+            for &tag in &to_add {
+                if insert.execute((img, tag))? > 0 {
+                    record_tagging(Operation::TagImage, tag, img, &tx)?;
+                    summary.added.push(tag);
+                }
+            }
+            summary.added.extend(rules::apply_rules(img, &tx)?);
+            summaries.push(summary);
+        }
     }
-    Some(())
+    tx.commit()?;
+    Ok(summaries)
 }
 /// removes given tag from given image
-pub fn remove_tag_from_img(tag_id: i64, img_id: i64, conn: &Connection) -> Result<()> {
+pub fn remove_tag_from_img(tag_id: TagId, img_id: ImageId, conn: &Connection) -> Result<()> {
     let mut rem = conn.prepare("DELETE FROM tag_map WHERE tag_id=?1 AND img_id=?2")?;
-    rem.execute([tag_id, img_id])?;
+    if rem.execute((tag_id, img_id))? > 0 {
+        record_tagging(Operation::UntagImage, tag_id, img_id, conn)?;
+    }
     Ok(())
 }
 /// Adds tag into the tags table and returns id if successful
-pub fn add_tag(tag: &str, conn: &Connection) -> Option<i64> {
-    insert_ret_id!(
-        conn.execute("INSERT INTO tags(name) VALUES (?1)", [tag]),
-        &conn
-    )
+pub fn add_tag(tag: &str, conn: &Connection) -> Option<TagId> {
+    utils::savepoint(conn, || {
+        let id = TagId(insert_ret_id!(
+            conn.execute("INSERT INTO tags(name) VALUES (?1)", [tag]),
+            &conn
+        )?);
+        record_tag(Operation::AddTag, id, None, conn).ok()?;
+        Some(id)
+    })
+}
+fn record_tag(
+    operation: Operation,
+    tag_id: TagId,
+    previous: Option<String>,
+    conn: &Connection,
+) -> Result<()> {
+    let change = Change {
+        tag_id: Some(tag_id),
+        previous,
+        ..Default::default()
+    };
+    history::record(operation, change, conn)
 }
 /// Adds multiple tags and return a vector of None and Some(id) values
-pub fn add_tags(tags: Vec<&str>, conn: &mut Connection) -> Vec<Option<i64>> {
-    let mut ids: Vec<Option<i64>> = Vec::new();
+pub fn add_tags(tags: Vec<&str>, conn: &mut Connection) -> Vec<Option<TagId>> {
+    let mut ids: Vec<Option<TagId>> = Vec::new();
     let mut stmt = conn.prepare("INSERT INTO tags(name) VALUES (?1)").unwrap();
     for tag in tags {
-        ids.push(insert_ret_id!(stmt.execute([tag]), conn)); // works if the insert worked or the tag already exists, else it panics
+        let id = insert_ret_id!(stmt.execute([tag]), conn).map(TagId); // works if the insert worked or the tag already exists, else it panics
+        if let Some(id) = id {
+            record_tag(Operation::AddTag, id, None, conn).unwrap();
+        }
+        ids.push(id);
     }
     ids
 }
 /// Deletes given tag from the tags table, all rows which use the tag also get deleted
-pub fn remove_tag(tag: i64, conn: &Connection) -> Result<()> {
+pub fn remove_tag(tag: TagId, conn: &Connection) -> Result<()> {
+    if get_name(tag, conn).is_some() {
+        record_tag(Operation::RemoveTag, tag, None, conn)?;
+    }
     conn.execute("DELETE FROM tags WHERE id=?1", [tag])?;
     // doesn't need any other stuff because of the ON DELETE CASCADE action
     Ok(())
 }
 /// Deletes all tags, delete cascades to all connected rows
-pub fn remove_tags(tags: Vec<i64>, conn: &Connection) -> Result<()> {
+pub fn remove_tags(tags: Vec<TagId>, conn: &Connection) -> Result<()> {
     for tag in tags {
         remove_tag(tag, conn)?;
     }
     Ok(())
 }
 /// Gets name of the given id or None
-pub fn get_name(tag_id: i64, conn: &Connection) -> Option<String> {
+pub fn get_name(tag_id: TagId, conn: &Connection) -> Option<String> {
     let mut stmt = conn.prepare("SELECT name FROM tags WHERE id=?1").unwrap();
-    match stmt.query_row([tag_id], |row| row.get(0)) {
-        Ok(id) => Some(id),
-        Err(_) => None,
-    }
+    stmt.query_row([tag_id], |row| row.get(0)).ok()
 }
 /// Renames the tag
-pub fn rename_tag(tag_id: i64, new_name: &str, conn: &Connection) -> Result<()> {
+pub fn rename_tag(tag_id: TagId, new_name: &str, conn: &Connection) -> Result<()> {
+    let previous = get_name(tag_id, conn);
+    let changed = conn.execute("UPDATE tags SET name=?1 WHERE id=?2", (new_name, tag_id))?;
+    if changed > 0 {
+        record_tag(Operation::RenameTag, tag_id, previous, conn)?;
+    }
+    Ok(())
+}
+const RESOLVE: &str = "SELECT id FROM tags WHERE name=?1
+    UNION ALL SELECT tag_id FROM tag_aliases WHERE name=?1 LIMIT 1";
+/// Gets the id of the tag with the given name or alias
+pub fn resolve(name: &str, conn: &Connection) -> Option<TagId> {
+    conn.query_row(RESOLVE, [name], |row| row.get(0)).ok()
+}
+/// Moves the images, subtag connections and namespace of the source tags to the target tag and
+/// deletes the sources in a single transaction. The target keeps its own namespace and values
+/// if it has them. With keep_aliases the source names keep resolving to the target. Fails
+/// without merging anything if a target would become its own subtag
+pub fn merge_tags(
+    sources: &[TagId],
+    target: TagId,
+    keep_aliases: bool,
+    conn: &mut Connection,
+) -> Result<()> {
+    let tx = conn.transaction()?;
+    get_name(target, &tx).ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+    for &source in sources {
+        if source == target {
+            continue;
+        }
+        let name = merge_into(source, target, &tx)?;
+        if keep_aliases {
+            add_alias(target, &name, &tx)?;
+        }
+    }
+    tx.commit()
+}
+/// Moves everything of the source to the target and deletes it, returns the name of the source.
+/// Fails if the target would end up above itself, the caller's transaction has to be dropped then
+pub(crate) fn merge_into(source: TagId, target: TagId, conn: &Connection) -> Result<String> {
+    let name = get_name(source, conn).ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+    conn.execute_batch(&format!(
+        "UPDATE tag_map SET value=(SELECT s.value FROM tag_map s
+                WHERE s.tag_id={source} AND s.img_id=tag_map.img_id),
+            value_type=(SELECT s.value_type FROM tag_map s
+                WHERE s.tag_id={source} AND s.img_id=tag_map.img_id)
+        WHERE tag_id={target} AND value_type IS NULL AND img_id IN
+            (SELECT img_id FROM tag_map WHERE tag_id={source} AND value_type IS NOT NULL);
+        UPDATE OR IGNORE tag_map SET tag_id={target} WHERE tag_id={source};
+        DELETE FROM tag_map WHERE tag_id={source};
+        UPDATE OR IGNORE subtag_map SET parent_id={target} WHERE parent_id={source};
+        UPDATE OR IGNORE subtag_map SET child_id={target} WHERE child_id={source};
+        DELETE FROM subtag_map
+            WHERE parent_id={source} OR child_id={source} OR parent_id=child_id;
+        UPDATE OR IGNORE namespace_map SET tag_id={target} WHERE tag_id={source};
+        DELETE FROM namespace_map WHERE tag_id={source};
+        UPDATE tags SET description=(SELECT description FROM tags WHERE id={source})
+            WHERE id={target} AND description IS NULL;
+        UPDATE tag_aliases SET tag_id={target} WHERE tag_id={source};
+        UPDATE OR IGNORE rule_conditions SET tag_id={target} WHERE tag_id={source};
+        DELETE FROM rule_conditions WHERE tag_id={source};
+        UPDATE rules SET target_id={target} WHERE target_id={source};
+        DELETE FROM rules WHERE EXISTS (SELECT id FROM rule_conditions c
+            WHERE c.rule_id=rules.id AND c.tag_id=rules.target_id);",
+        source = source,
+        target = target
+    ))?;
+    // a parent of the source which is a child of the target is now both
+    let looped: bool = conn.query_row(
+        "WITH RECURSIVE reach(id) AS (
+            SELECT child_id FROM subtag_map WHERE parent_id=?1
+            UNION
+            SELECT subtag_map.child_id FROM reach
+            JOIN subtag_map ON subtag_map.parent_id=reach.id
+        )
+        SELECT EXISTS(SELECT id FROM reach WHERE id=?1)",
+        [target],
+        |row| row.get(0),
+    )?;
+    if looped {
+        return Err(rusqlite::Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_CONSTRAINT_CHECK),
+            Some(format!(
+                "Merging tag {} into {} would make it its own subtag",
+                source, target
+            )),
+        ));
+    }
+    let change = Change {
+        tag_id: Some(target),
+        previous: Some(name.clone()),
+        ..Default::default()
+    };
+    history::record(Operation::MergeTag, change, conn)?;
+    conn.execute("DELETE FROM tags WHERE id=?1", [source])?;
+    Ok(name)
+}
+/// A tag getting a new name from a bulk rename
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rename {
+    pub tag_id: TagId,
+    pub old: String,
+    pub new: String,
+    /// Tag which ends up with the same name, this tag gets merged into it if collisions are
+    /// merged
+    pub collision: Option<TagId>,
+}
+/// Gets the renames replacing every match of the regex in the tag names (`$1` or `${name}` in
+/// the replacement insert capture groups), only for tags of the namespace if one is given.
+/// Tags which end up with the same name collide with the one keeping its name, or the one
+/// with the lowest id
+pub fn preview_rename(
+    pattern: &str,
+    replacement: &str,
+    namespace: Option<NamespaceId>,
+    conn: &Connection,
+) -> Result<Vec<Rename>> {
+    // an invalid pattern is a parameter which can't be used
+    let regex =
+        Regex::new(pattern).map_err(|err| rusqlite::Error::ToSqlConversionFailure(err.into()))?;
+    let mut stmt = conn.prepare(
+        "SELECT tags.id, tags.name, namespace_map.namespace_id FROM tags
+        LEFT JOIN namespace_map ON namespace_map.tag_id=tags.id ORDER BY tags.id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<usize, TagId>(0)?,
+            row.get::<usize, String>(1)?,
+            row.get::<usize, Option<NamespaceId>>(2)?,
+        ))
+    })?;
+    let mut renames: Vec<Rename> = Vec::new();
+    // name every tag has after the rename, with the tag which keeps it
+    let mut keepers: HashMap<String, TagId> = HashMap::new();
+    let mut unchanged: Vec<(TagId, String)> = Vec::new();
+    for row in rows {
+        let (tag_id, name, tag_namespace) = row?;
+        let new = match namespace {
+            Some(namespace) if tag_namespace != Some(namespace) => None,
+            _ => Some(regex.replace_all(&name, replacement)),
+        };
+        match new {
+            Some(new) if new != name => renames.push(Rename {
+                tag_id,
+                new: new.into_owned(),
+                old: name,
+                collision: None,
+            }),
+            _ => unchanged.push((tag_id, name)),
+        }
+    }
+    for (tag_id, name) in unchanged {
+        keepers.insert(name, tag_id);
+    }
+    for rename in &mut renames {
+        match keepers.get(&rename.new) {
+            Some(&keeper) => rename.collision = Some(keeper),
+            None => {
+                keepers.insert(rename.new.clone(), rename.tag_id);
+            }
+        }
+    }
+    Ok(renames)
+}
+/// Applies the renames of [`preview_rename`] in a single transaction. Colliding tags get merged
+/// if merge is true, else nothing changes and the unique constraint error is returned
+pub fn bulk_rename(
+    pattern: &str,
+    replacement: &str,
+    namespace: Option<NamespaceId>,
+    merge: bool,
+    conn: &mut Connection,
+) -> Result<Vec<Rename>> {
+    let tx = conn.transaction()?;
+    let renames = preview_rename(pattern, replacement, namespace, &tx)?;
+    {
+        let mut rename = tx.prepare("UPDATE tags SET name=?1 WHERE id=?2")?;
+        // placeholder names first, so renames can swap names or take the name of a renamed tag
+        for change in &renames {
+            if change.collision.is_none() || !merge {
+                rename.execute((format!("\0{}", change.tag_id), change.tag_id))?;
+            }
+        }
+        for change in &renames {
+            match change.collision {
+                Some(keeper) if merge => {
+                    merge_into(change.tag_id, keeper, &tx)?;
+                }
+                _ => {
+                    rename.execute((&change.new, change.tag_id))?;
+                    record_tag(
+                        Operation::RenameTag,
+                        change.tag_id,
+                        Some(change.old.clone()),
+                        &tx,
+                    )?;
+                }
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(renames)
+}
+/// Lets the name resolve to the tag, replacing an alias with the same name
+pub fn add_alias(tag_id: TagId, name: &str, conn: &Connection) -> Result<()> {
     conn.execute(
-        "UPDATE tags SET name=?1 WHERE id=?2",
-        [new_name, &format!("{}", tag_id)],
+        "INSERT INTO tag_aliases(name, tag_id) VALUES (?1, ?2)
+        ON CONFLICT(name) DO UPDATE SET tag_id=excluded.tag_id",
+        (name, tag_id),
+    )?;
+    record_tag(Operation::AddAlias, tag_id, Some(name.to_owned()), conn)
+}
+/// Removes an alias, the tag stays
+pub fn remove_alias(name: &str, conn: &Connection) -> Result<()> {
+    let tag_id: Option<TagId> = conn
+        .query_row(
+            "SELECT tag_id FROM tag_aliases WHERE name=?1",
+            [name],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(tag_id) = tag_id {
+        record_tag(Operation::RemoveAlias, tag_id, Some(name.to_owned()), conn)?;
+    }
+    conn.execute("DELETE FROM tag_aliases WHERE name=?1", [name])?;
+    Ok(())
+}
+/// Gets the aliases of a tag sorted by name
+pub fn get_aliases(tag_id: TagId, conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM tag_aliases WHERE tag_id=?1 ORDER BY name")?;
+    let rows = stmt.query_map([tag_id], |row| row.get(0))?;
+    rows.collect()
+}
+/// Sets the description of a tag, None removes it
+pub fn set_description(tag_id: TagId, description: Option<&str>, conn: &Connection) -> Result<()> {
+    let previous = get_description(tag_id, conn);
+    let changed = conn.execute(
+        "UPDATE tags SET description=?1 WHERE id=?2",
+        (description, tag_id),
     )?;
+    if changed > 0 && previous.as_deref() != description {
+        record_tag(Operation::SetDescription, tag_id, previous, conn)?;
+    }
     Ok(())
 }
+/// Gets the description of a tag
+pub fn get_description(tag_id: TagId, conn: &Connection) -> Option<String> {
+    conn.query_row(
+        "SELECT description FROM tags WHERE id=?1",
+        [tag_id],
+        |row| row.get(0),
+    )
+    .ok()
+    .flatten()
+}
+/// Gets all tags whose description matches the full text query, see SQLite's FTS5 for the
+/// syntax. Plain words have to appear in the description
+pub fn search_descriptions(query: &str, conn: &Connection) -> Result<Vec<(TagId, String)>> {
+    Ok(search_descriptions_page(query, &ListOptions::default(), conn)?.items)
+}
+/// Gets a page of the tags whose description matches the full text query
+pub fn search_descriptions_page(
+    query: &str,
+    options: &ListOptions,
+    conn: &Connection,
+) -> Result<Page<(TagId, String)>> {
+    listing::list(
+        Table::Tags,
+        "t.id IN (SELECT rowid FROM tags_fts WHERE tags_fts MATCH ?1)",
+        vec![query.to_owned().into()],
+        options,
+        conn,
+    )
+}
+/// Filters tags without connection to an image
+const ORPHANS: &str = "NOT EXISTS (SELECT id FROM tag_map WHERE tag_map.tag_id=t.id)";
 /// Gets all tags without connection to an image
-pub fn get_orphans(conn: &Connection) -> Vec<(i64, String)> {
-    let mut stmt = conn.prepare("SELECT id, name FROM tags WHERE NOT EXISTS (SELECT id FROM tag_map WHERE tag_map.tag_id=tags.id)").unwrap();
-    let mut res: Vec<(i64, String)> = Vec::new();
-    for orphan in stmt
-        .query_map([], |row| Ok((row.get(0).unwrap(), row.get(1).unwrap())))
+pub fn get_orphans(conn: &Connection) -> Vec<(TagId, String)> {
+    get_orphans_page(&ListOptions::default(), conn)
         .unwrap()
-    {
-        res.push(orphan.unwrap());
+        .items
+}
+/// Gets a page of the tags without connection to an image
+pub fn get_orphans_page(options: &ListOptions, conn: &Connection) -> Result<Page<(TagId, String)>> {
+    listing::list(Table::Tags, ORPHANS, vec![], options, conn)
+}
+pub fn get_tags_with(search_term: &str, conn: &Connection) -> Vec<(TagId, String)> {
+    get_tags_with_page(search_term, &ListOptions::default(), conn)
+        .unwrap()
+        .items
+}
+/// Gets a page of the tags containing the search term
+pub fn get_tags_with_page(
+    search_term: &str,
+    options: &ListOptions,
+    conn: &Connection,
+) -> Result<Page<(TagId, String)>> {
+    if search_term.is_empty() {
+        return Ok(Page {
+            items: vec![],
+            next: None,
+        });
+    }
+    listing::list(
+        Table::Tags,
+        "t.name LIKE ?1",
+        vec![format!("%{}%", search_term).into()],
+        options,
+        conn,
+    )
+}
+/// Selects the columns of a full tag, `tags` is the selected table
+const SELECT_TAGS: &str = "SELECT tags.id, tags.name, namespaces.id, namespaces.name,
+        (SELECT group_concat(parent_id) FROM subtag_map WHERE child_id=tags.id),
+        (SELECT COUNT(*) FROM tag_map WHERE tag_map.tag_id=tags.id),
+        tags.created, tags.updated, tags.description
+    FROM tags
+    LEFT JOIN namespace_map ON namespace_map.tag_id=tags.id
+    LEFT JOIN namespaces ON namespaces.id=namespace_map.namespace_id";
+fn tag_from_row(row: &Row) -> Result<Tag> {
+    let parents: Option<String> = row.get(4)?;
+    Ok(Tag {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        namespace_id: row.get(2)?,
+        namespace: row.get(3)?,
+        parents: parents
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|parent| parent.parse().ok())
+            .collect(),
+        image_count: row.get(5)?,
+        created: row.get(6)?,
+        updated: row.get(7)?,
+        description: row.get(8)?,
+    })
+}
+/// Gets the full tags for the given ids, missing ones get skipped
+pub fn load_tags(ids: &[TagId], conn: &Connection) -> Result<Vec<Tag>> {
+    let mut stmt = conn.prepare(&format!("{} WHERE tags.id=?1", SELECT_TAGS))?;
+    let mut res: Vec<Tag> = Vec::new();
+    for id in ids {
+        if let Some(tag) = stmt.query_map([id], tag_from_row)?.next() {
+            res.push(tag?);
+        }
     }
-    res
+    Ok(res)
+}
+/// Passes every tag to f in order of their ids, without collecting them first
+pub fn for_each_tag(conn: &Connection, f: impl FnMut(Tag)) -> Result<()> {
+    listing::stream_rows(
+        &format!("{} ORDER BY tags.id", SELECT_TAGS),
+        [],
+        conn,
+        tag_from_row,
+        f,
+    )
+}
+/// Streaming version of [`get_orphans`]
+pub fn for_each_orphan(
+    options: &ListOptions,
+    conn: &Connection,
+    f: impl FnMut(TagId, String),
+) -> Result<()> {
+    listing::stream(Table::Tags, ORPHANS, vec![], options, conn, f)
 }
-pub fn get_tags_with(search_term: &str, conn: &Connection) -> Vec<(i64, String)> {
+/// Streaming version of [`get_tags_with`]
+pub fn for_each_tag_with(
+    search_term: &str,
+    options: &ListOptions,
+    conn: &Connection,
+    f: impl FnMut(TagId, String),
+) -> Result<()> {
     if search_term.is_empty() {
-        return vec![];
-    }
-    let mut stmt = conn
-        .prepare("SELECT id, name FROM tags WHERE name LIKE ?1")
-        .unwrap();
-    let mut res: Vec<(i64, String)> = Vec::new();
-    for matching in stmt
-        .query_map([&format!("%{}%", search_term)], |row| {
-            Ok((row.get(0).unwrap(), row.get(1).unwrap()))
-        })
-        .unwrap()
-    {
-        res.push(matching.unwrap());
+        return Ok(());
     }
-    res
+    listing::stream(
+        Table::Tags,
+        "t.name LIKE ?1",
+        vec![format!("%{}%", search_term).into()],
+        options,
+        conn,
+        f,
+    )
 }
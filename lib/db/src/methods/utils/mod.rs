@@ -1,19 +1,87 @@
+use crate::{
+    methods::history::{self, Change, Operation},
+    types::{ImageId, NamespaceId, TagId},
+};
 use rusqlite::{Connection, Result};
 /// returns either id or none of table with matching query
 pub fn get_id(table: &str, query: &str, conn: &Connection) -> Option<i64> {
     let mut stmt = conn
         .prepare(&format!("SELECT id FROM {} WHERE {}", table, query))
         .unwrap();
-    match stmt.query_row((), |row| row.get(0)) {
-        Ok(res) => Some(res),
-        Err(_) => None,
-    }
+    stmt.query_row((), |row| row.get(0)).ok()
 }
 /// removes the row with matching id in the specified table
 pub fn remove_id(id: i64, table: &str, conn: &Connection) -> Result<()> {
+    let recorded = match table {
+        "tags" => Some((
+            Operation::RemoveTag,
+            Change {
+                tag_id: Some(TagId(id)),
+                ..Default::default()
+            },
+        )),
+        "images" => Some((
+            Operation::RemoveImage,
+            Change {
+                img_id: Some(ImageId(id)),
+                ..Default::default()
+            },
+        )),
+        "namespaces" => Some((
+            Operation::RemoveNamespace,
+            Change {
+                namespace_id: Some(NamespaceId(id)),
+                ..Default::default()
+            },
+        )),
+        _ => None,
+    };
+    if let Some((operation, change)) = recorded {
+        if get_id(table, &format!("id={}", id), conn).is_some() {
+            history::record(operation, change, conn)?;
+        }
+    }
     conn.execute(&format!("DELETE FROM {} WHERE id=?1", table), [id])?;
     Ok(())
 }
+/// What changes in a savepoint return, None or an error undoes them
+pub(crate) trait Outcome {
+    fn succeeded(&self) -> bool;
+    fn failed(err: rusqlite::Error) -> Self;
+}
+impl<T> Outcome for Option<T> {
+    fn succeeded(&self) -> bool {
+        self.is_some()
+    }
+    fn failed(_: rusqlite::Error) -> Self {
+        None
+    }
+}
+impl<T> Outcome for Result<T> {
+    fn succeeded(&self) -> bool {
+        self.is_ok()
+    }
+    fn failed(err: rusqlite::Error) -> Self {
+        Err(err)
+    }
+}
+/// Runs the changes in a savepoint, they get undone if they fail. A savepoint instead of a
+/// transaction, callers can already be in one
+pub(crate) fn savepoint<R: Outcome>(conn: &Connection, changes: impl FnOnce() -> R) -> R {
+    if let Err(err) = conn.execute_batch("SAVEPOINT changes") {
+        return R::failed(err);
+    }
+    let done = changes();
+    let end = if done.succeeded() {
+        "RELEASE changes"
+    } else {
+        "ROLLBACK TO changes; RELEASE changes"
+    };
+    match conn.execute_batch(end) {
+        Ok(()) => done,
+        Err(err) => R::failed(err),
+    }
+}
 pub mod macros {
     macro_rules! handle_unique {
         ($command:expr) => {
@@ -47,3 +115,60 @@ pub mod macros {
     pub(crate) use handle_unique;
     pub(crate) use insert_ret_id;
 }
+/// Formats a unix timestamp as `YYYY-MM-DD HH:MM:SS` (UTC)
+pub fn format_timestamp(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(86400);
+    let secs = timestamp.rem_euclid(86400);
+    // civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+/// Parses `YYYY-MM-DD` or `YYYY-MM-DD HH:MM:SS` (UTC) into a unix timestamp
+pub fn parse_date(date: &str) -> Option<i64> {
+    let (date, time) = match date.trim().split_once([' ', 'T']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (date.trim(), None),
+    };
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut secs = 0;
+    if let Some(time) = time {
+        let mut parts = time.splitn(3, ':');
+        let hours: i64 = parts.next()?.parse().ok()?;
+        let minutes: i64 = parts.next().unwrap_or("0").parse().ok()?;
+        let seconds: i64 = parts.next().unwrap_or("0").parse().ok()?;
+        if hours > 23 || minutes > 59 || seconds > 59 {
+            return None;
+        }
+        secs = hours * 3600 + minutes * 60 + seconds;
+    }
+    // days since the epoch from a civil date, inverse of format_timestamp
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some((era * 146097 + doe - 719468) * 86400 + secs)
+}
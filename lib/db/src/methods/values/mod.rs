@@ -0,0 +1,131 @@
+use crate::{
+    methods::history::{self, Change, Operation},
+    types::{Comparison, ImageId, TagId, TagValue},
+};
+use rusqlite::{types::Value, Connection, OptionalExtension, Result, Row};
+
+fn value_from_row(row: &Row, kind: usize, value: usize) -> Result<TagValue> {
+    let kind: String = row.get(kind)?;
+    let column = row.get_ref(value)?;
+    TagValue::from_columns(&kind, column).map_err(|err| {
+        rusqlite::Error::FromSqlConversionFailure(value, column.data_type(), err.into())
+    })
+}
+/// Sets the value of a tag on an image, None removes it. The image needs to have the tag
+pub fn set_value(
+    tag_id: TagId,
+    img_id: ImageId,
+    value: Option<&TagValue>,
+    conn: &Connection,
+) -> Result<()> {
+    let previous = get_value(tag_id, img_id, conn)?;
+    let changed = conn.execute(
+        "UPDATE tag_map SET value=?1, value_type=?2 WHERE tag_id=?3 AND img_id=?4",
+        (value, value.map(TagValue::kind), tag_id, img_id),
+    )?;
+    if changed == 0 {
+        return Err(rusqlite::Error::QueryReturnedNoRows);
+    }
+    if previous.as_ref() != value {
+        let change = Change {
+            img_id: Some(img_id),
+            tag_id: Some(tag_id),
+            previous: previous.map(|previous| previous.to_string()),
+            ..Default::default()
+        };
+        history::record(Operation::SetValue, change, conn)?;
+    }
+    Ok(())
+}
+/// Gets the value of a tag on an image, None if the image doesn't have the tag or it has no value
+pub fn get_value(tag_id: TagId, img_id: ImageId, conn: &Connection) -> Result<Option<TagValue>> {
+    conn.query_row(
+        "SELECT value_type, value FROM tag_map
+        WHERE tag_id=?1 AND img_id=?2 AND value_type IS NOT NULL",
+        (tag_id, img_id),
+        |row| value_from_row(row, 0, 1),
+    )
+    .optional()
+}
+/// Gets the tags of an image which have a value, with their names and values
+pub fn get_values_of_img(
+    img_id: ImageId,
+    conn: &Connection,
+) -> Result<Vec<(TagId, String, TagValue)>> {
+    let mut stmt = conn.prepare(
+        "SELECT tags.id, tags.name, tag_map.value_type, tag_map.value FROM tag_map
+        JOIN tags ON tags.id=tag_map.tag_id
+        WHERE tag_map.img_id=?1 AND tag_map.value_type IS NOT NULL
+        ORDER BY tags.id",
+    )?;
+    let rows = stmt.query_map([img_id], |row| {
+        Ok((row.get(0)?, row.get(1)?, value_from_row(row, 2, 3)?))
+    })?;
+    rows.collect()
+}
+/// Splits a query token like `rating>=4` or `year=2015..2018` into the tag name and the
+/// comparison, None if the token has no operator
+pub fn parse_condition(token: &str) -> Option<(&str, Comparison)> {
+    let start = token.find(['<', '>', '=', '!'])?;
+    let (name, rest) = token.split_at(start);
+    Some((name, parse_comparison(rest)?))
+}
+/// Parses an operator followed by a value like `>=4`, `=2015..2018` is an inclusive range
+pub fn parse_comparison(s: &str) -> Option<Comparison> {
+    let (operator, value) = ["<=", ">=", "!=", "<", ">", "="]
+        .into_iter()
+        .find_map(|operator| Some((operator, s.strip_prefix(operator)?)))?;
+    let parse = |value: &str| value.parse::<TagValue>().unwrap();
+    Some(match operator {
+        "=" => match value.split_once("..") {
+            Some((low, high)) => Comparison::Between(parse(low), parse(high)),
+            None => Comparison::Eq(parse(value)),
+        },
+        "!=" => Comparison::Ne(parse(value)),
+        "<" => Comparison::Lt(parse(value)),
+        "<=" => Comparison::Le(parse(value)),
+        ">" => Comparison::Gt(parse(value)),
+        _ => Comparison::Ge(parse(value)),
+    })
+}
+/// Builds a condition on tag_map matching values of the comparison, its values get pushed to
+/// params
+pub(crate) fn condition(comparison: &Comparison, params: &mut Vec<Value>) -> String {
+    let kinds = match comparison {
+        Comparison::Eq(value)
+        | Comparison::Ne(value)
+        | Comparison::Lt(value)
+        | Comparison::Le(value)
+        | Comparison::Gt(value)
+        | Comparison::Ge(value)
+        | Comparison::Between(value, _) => value.comparable_kinds(),
+    };
+    format!(
+        "tag_map.value_type IN ({}) AND {}",
+        kinds,
+        compare("tag_map.value", comparison, params)
+    )
+}
+/// Builds a condition comparing the SQL expression, the values get pushed to params
+pub(crate) fn compare(expr: &str, comparison: &Comparison, params: &mut Vec<Value>) -> String {
+    let mut push = |value: &TagValue| {
+        params.push(match value {
+            TagValue::Int(value) | TagValue::Date(value) => Value::Integer(*value),
+            TagValue::Float(value) => Value::Real(*value),
+            TagValue::Text(value) => Value::Text(value.clone()),
+        });
+        params.len()
+    };
+    let (operator, value) = match comparison {
+        Comparison::Eq(value) => ("=", value),
+        Comparison::Ne(value) => ("!=", value),
+        Comparison::Lt(value) => ("<", value),
+        Comparison::Le(value) => ("<=", value),
+        Comparison::Gt(value) => (">", value),
+        Comparison::Ge(value) => (">=", value),
+        Comparison::Between(low, high) => {
+            return format!("{} BETWEEN ?{} AND ?{}", expr, push(low), push(high))
+        }
+    };
+    format!("{} {} ?{}", expr, operator, push(value))
+}
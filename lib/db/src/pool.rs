@@ -0,0 +1,114 @@
+use rusqlite::{Connection, InterruptHandle, OpenFlags, Result};
+use std::{
+    cell::RefCell,
+    ops::{Deref, DerefMut},
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+/// How long a connection waits on a locked database before giving up
+pub const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Maximum number of concurrent read connections
+pub const READERS: u32 = 8;
+
+/// Sets the options every connection to the database needs
+pub fn configure(conn: &Connection) -> Result<()> {
+    conn.busy_timeout(BUSY_TIMEOUT)
+}
+
+/// Opens read only connections to a database file for the pool
+pub struct ReadManager {
+    path: PathBuf,
+}
+impl ReadManager {
+    pub fn new(path: &str) -> Self {
+        ReadManager { path: path.into() }
+    }
+}
+impl r2d2::ManageConnection for ReadManager {
+    type Connection = Connection;
+    type Error = rusqlite::Error;
+
+    fn connect(&self) -> Result<Connection> {
+        let conn = Connection::open_with_flags(
+            &self.path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_URI
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        configure(&conn)?;
+        Ok(conn)
+    }
+    fn is_valid(&self, conn: &mut Connection) -> Result<()> {
+        conn.execute_batch("")
+    }
+    fn has_broken(&self, _conn: &mut Connection) -> bool {
+        false
+    }
+}
+
+/// A connection used for reading, either from the pool or the writer for in memory databases
+pub enum Reader<'a> {
+    Pooled(r2d2::PooledConnection<ReadManager>),
+    Writer(MutexGuard<'a, Connection>),
+}
+impl Deref for Reader<'_> {
+    type Target = Connection;
+    fn deref(&self) -> &Connection {
+        match self {
+            Reader::Pooled(conn) => conn,
+            Reader::Writer(conn) => conn,
+        }
+    }
+}
+impl DerefMut for Reader<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        match self {
+            Reader::Pooled(conn) => conn,
+            Reader::Writer(conn) => conn,
+        }
+    }
+}
+
+/// Locks the writer, a panic during an earlier write doesn't leave the connection unusable
+pub fn lock(writer: &Mutex<Connection>) -> MutexGuard<'_, Connection> {
+    writer
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Holds the interrupt handle of the connection a watched task is currently using
+pub type InterruptSlot = Arc<Mutex<Option<InterruptHandle>>>;
+
+thread_local! {
+    static WATCHED: RefCell<Option<InterruptSlot>> = const { RefCell::new(None) };
+}
+
+/// Runs f while storing the interrupt handle of every connection it takes in the slot, so
+/// another thread can interrupt its queries. The slot gets cleared once f returns
+pub fn watch_interrupts<T>(slot: InterruptSlot, f: impl FnOnce() -> T) -> T {
+    /// Clears the slot even if f panics
+    struct Unwatch(InterruptSlot);
+    impl Drop for Unwatch {
+        fn drop(&mut self) {
+            WATCHED.with(|watched| *watched.borrow_mut() = None);
+            *self
+                .0
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+        }
+    }
+    WATCHED.with(|watched| *watched.borrow_mut() = Some(slot.clone()));
+    let _unwatch = Unwatch(slot);
+    f()
+}
+/// Stores the interrupt handle of conn if the current thread is being watched
+pub fn register(conn: &Connection) {
+    WATCHED.with(|watched| {
+        if let Some(slot) = watched.borrow().as_ref() {
+            *slot.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) =
+                Some(conn.get_interrupt_handle());
+        }
+    });
+}
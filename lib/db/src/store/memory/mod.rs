@@ -0,0 +1,260 @@
+use super::TagStore;
+use crate::types::{ImageId, NamespaceId, TagId};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A store keeping everything in maps, for tests and tools which don't want a database file.
+/// Nothing gets recorded in a history
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStore {
+    tags: BTreeMap<TagId, String>,
+    images: BTreeMap<ImageId, String>,
+    namespaces: BTreeMap<NamespaceId, String>,
+    /// tag id to namespace id
+    namespace_map: BTreeMap<TagId, NamespaceId>,
+    /// (parent id, child id)
+    subtag_map: BTreeSet<(TagId, TagId)>,
+    /// (image id, tag id)
+    tag_map: BTreeSet<(ImageId, TagId)>,
+}
+
+/// Gets the id the next row would get, like SQLite's rowid this is one more than the highest
+fn next_id<I: Copy + From<i64> + Into<i64>>(table: &BTreeMap<I, String>) -> I {
+    I::from(table.keys().next_back().map_or(1, |&id| id.into() + 1))
+}
+/// Inserts a value which has to be unique within the table
+fn insert_unique<I: Copy + Ord + From<i64> + Into<i64>>(
+    table: &mut BTreeMap<I, String>,
+    value: &str,
+) -> Option<I> {
+    if table.values().any(|existing| existing == value) {
+        return None;
+    }
+    let id = next_id(table);
+    table.insert(id, value.to_owned());
+    Some(id)
+}
+/// Renames a value which has to stay unique within the table
+fn rename_unique<I: Ord>(table: &mut BTreeMap<I, String>, id: I, value: &str) -> Option<()> {
+    if table
+        .iter()
+        .any(|(other, existing)| *other != id && existing == value)
+    {
+        return None;
+    }
+    if let Some(existing) = table.get_mut(&id) {
+        *existing = value.to_owned();
+    }
+    Some(())
+}
+fn find<I: Copy>(table: &BTreeMap<I, String>, value: &str) -> Option<I> {
+    table
+        .iter()
+        .find(|(_, existing)| *existing == value)
+        .map(|(id, _)| *id)
+}
+fn rows<'a, I: Copy + Ord + 'a>(
+    table: &BTreeMap<I, String>,
+    ids: impl Iterator<Item = &'a I>,
+) -> Vec<(I, String)> {
+    let ids: BTreeSet<I> = ids.copied().collect();
+    ids.into_iter()
+        .filter_map(|id| table.get(&id).map(|value| (id, value.clone())))
+        .collect()
+}
+
+impl MemoryStore {
+    /// Follows subtag connections from id, towards_parents decides the direction
+    fn walk(&self, id: TagId, towards_parents: bool) -> Vec<TagId> {
+        let next = |id: TagId| -> Vec<TagId> {
+            self.subtag_map
+                .iter()
+                .filter_map(|&(parent, child)| match towards_parents {
+                    true if child == id => Some(parent),
+                    false if parent == id => Some(child),
+                    _ => None,
+                })
+                .collect()
+        };
+        let mut total: Vec<TagId> = Vec::new();
+        let mut curr = next(id);
+        while let Some(curr_id) = curr.pop() {
+            if total.contains(&curr_id) {
+                continue;
+            }
+            total.push(curr_id);
+            for new in next(curr_id) {
+                curr.insert(0, new);
+            }
+        }
+        total
+    }
+}
+
+impl TagStore for MemoryStore {
+    fn add_tag(&mut self, name: &str) -> Option<TagId> {
+        insert_unique(&mut self.tags, name)
+    }
+    fn remove_tag(&mut self, tag_id: TagId) -> Option<()> {
+        self.tags.remove(&tag_id);
+        self.namespace_map.remove(&tag_id);
+        self.subtag_map
+            .retain(|&(parent, child)| parent != tag_id && child != tag_id);
+        self.tag_map.retain(|&(_, tag)| tag != tag_id);
+        Some(())
+    }
+    fn rename_tag(&mut self, tag_id: TagId, new_name: &str) -> Option<()> {
+        rename_unique(&mut self.tags, tag_id, new_name)
+    }
+    fn get_tag_name(&self, tag_id: TagId) -> Option<String> {
+        self.tags.get(&tag_id).cloned()
+    }
+    fn get_tag_id(&self, name: &str) -> Option<TagId> {
+        find(&self.tags, name)
+    }
+    fn get_tags_with(&self, search_term: &str) -> Vec<(TagId, String)> {
+        if search_term.is_empty() {
+            return vec![];
+        }
+        let search_term = search_term.to_ascii_lowercase();
+        self.tags
+            .iter()
+            .filter(|(_, name)| name.to_ascii_lowercase().contains(&search_term))
+            .map(|(id, name)| (*id, name.clone()))
+            .collect()
+    }
+    fn get_tag_orphans(&self) -> Vec<(TagId, String)> {
+        let used: BTreeSet<_> = self.tag_map.iter().map(|&(_, tag)| tag).collect();
+        rows(&self.tags, self.tags.keys().filter(|id| !used.contains(id)))
+    }
+
+    fn add_image(&mut self, path: &str) -> Option<ImageId> {
+        insert_unique(&mut self.images, path)
+    }
+    fn remove_image(&mut self, img_id: ImageId) -> Option<()> {
+        self.images.remove(&img_id);
+        self.tag_map.retain(|&(img, _)| img != img_id);
+        Some(())
+    }
+    fn get_image_path(&self, img_id: ImageId) -> Option<String> {
+        self.images.get(&img_id).cloned()
+    }
+    fn get_image_id(&self, path: &str) -> Option<ImageId> {
+        find(&self.images, path)
+    }
+    fn update_image_path(&mut self, img_id: ImageId, new_path: &str) -> Option<()> {
+        rename_unique(&mut self.images, img_id, new_path)
+    }
+    fn get_image_orphans(&self) -> Vec<(ImageId, String)> {
+        let used: BTreeSet<_> = self.tag_map.iter().map(|&(img, _)| img).collect();
+        rows(
+            &self.images,
+            self.images.keys().filter(|id| !used.contains(id)),
+        )
+    }
+    fn add_tag_to_img(&mut self, tag: &str, img_id: ImageId, create: bool) -> Option<()> {
+        let primary_id = match self.get_tag_id(tag) {
+            Some(id) => id,
+            None if create => self.add_tag(tag)?,
+            None => return None,
+        };
+        if self.images.contains_key(&img_id) {
+            self.tag_map.insert((img_id, primary_id));
+            for parent in self.get_parents(primary_id) {
+                self.tag_map.insert((img_id, parent));
+            }
+        }
+        Some(())
+    }
+    fn remove_tag_from_img(&mut self, tag_id: TagId, img_id: ImageId) -> Option<()> {
+        self.tag_map.remove(&(img_id, tag_id));
+        Some(())
+    }
+    fn get_tags_of_img(&self, img_id: ImageId) -> Vec<(TagId, String)> {
+        rows(
+            &self.tags,
+            self.tag_map
+                .iter()
+                .filter(|(img, _)| *img == img_id)
+                .map(|(_, tag)| tag),
+        )
+    }
+    fn get_images_with_tag(&self, tag_id: TagId) -> Vec<(ImageId, String)> {
+        rows(
+            &self.images,
+            self.tag_map
+                .iter()
+                .filter(|(_, tag)| *tag == tag_id)
+                .map(|(img, _)| img),
+        )
+    }
+
+    fn add_namespace(&mut self, name: &str) -> Option<NamespaceId> {
+        insert_unique(&mut self.namespaces, name)
+    }
+    fn remove_namespace(&mut self, namespace_id: NamespaceId) -> Option<()> {
+        self.namespaces.remove(&namespace_id);
+        self.namespace_map
+            .retain(|_, namespace| *namespace != namespace_id);
+        Some(())
+    }
+    fn rename_namespace(&mut self, namespace_id: NamespaceId, new_name: &str) -> Option<()> {
+        rename_unique(&mut self.namespaces, namespace_id, new_name)
+    }
+    fn get_namespace_name(&self, namespace_id: NamespaceId) -> Option<String> {
+        self.namespaces.get(&namespace_id).cloned()
+    }
+    fn add_namespace_to_tag(&mut self, namespace_id: NamespaceId, tag_id: TagId) -> Option<()> {
+        if !self.namespaces.contains_key(&namespace_id)
+            || !self.tags.contains_key(&tag_id)
+            || self.namespace_map.contains_key(&tag_id)
+        {
+            return None;
+        }
+        self.namespace_map.insert(tag_id, namespace_id);
+        Some(())
+    }
+    fn remove_namespace_from_tag(&mut self, tag_id: TagId) -> Option<()> {
+        self.namespace_map.remove(&tag_id);
+        Some(())
+    }
+    fn get_namespace_of_tag(&self, tag_id: TagId) -> Option<NamespaceId> {
+        self.namespace_map.get(&tag_id).copied()
+    }
+    fn get_tags_with_namespace(&self, namespace_id: NamespaceId) -> Vec<(TagId, String)> {
+        rows(
+            &self.tags,
+            self.namespace_map
+                .iter()
+                .filter(|(_, namespace)| **namespace == namespace_id)
+                .map(|(tag, _)| tag),
+        )
+    }
+    fn get_namespace_orphans(&self) -> Vec<(NamespaceId, String)> {
+        let used: BTreeSet<_> = self.namespace_map.values().copied().collect();
+        rows(
+            &self.namespaces,
+            self.namespaces.keys().filter(|id| !used.contains(id)),
+        )
+    }
+
+    fn add_parent(&mut self, parent_id: TagId, child_id: TagId) -> Option<()> {
+        if self.subtag_map.contains(&(child_id, parent_id)) {
+            return None;
+        }
+        if self.tags.contains_key(&parent_id) && self.tags.contains_key(&child_id) {
+            self.subtag_map.insert((parent_id, child_id));
+        }
+        Some(())
+    }
+    fn remove_connection(&mut self, tag1: TagId, tag2: TagId) -> Option<()> {
+        self.subtag_map.remove(&(tag1, tag2));
+        self.subtag_map.remove(&(tag2, tag1));
+        Some(())
+    }
+    fn get_parents(&self, tag_id: TagId) -> Vec<TagId> {
+        self.walk(tag_id, true)
+    }
+    fn get_children(&self, tag_id: TagId) -> Vec<TagId> {
+        self.walk(tag_id, false)
+    }
+}
@@ -0,0 +1,80 @@
+//! Storage agnostic interface over the tag, image, namespace and subtag operations
+pub mod memory;
+pub mod sqlite;
+
+use crate::types::{ImageId, NamespaceId, TagId};
+
+/// Operations every storage backend provides.
+///
+/// Ids start at 1, adding something which already exists returns None and removing something
+/// which doesn't exist does nothing. Removing tags, images or namespaces also removes all of
+/// their connections.
+pub trait TagStore {
+    // tags
+    /// Adds a tag and returns its id
+    fn add_tag(&mut self, name: &str) -> Option<TagId>;
+    /// Removes a tag and all of its connections
+    fn remove_tag(&mut self, tag_id: TagId) -> Option<()>;
+    /// Renames a tag, fails if the name is taken
+    fn rename_tag(&mut self, tag_id: TagId, new_name: &str) -> Option<()>;
+    /// Gets the name of a tag
+    fn get_tag_name(&self, tag_id: TagId) -> Option<String>;
+    /// Gets the id of the tag with the given name
+    fn get_tag_id(&self, name: &str) -> Option<TagId>;
+    /// Gets all tags containing the search term, ignoring ASCII case
+    fn get_tags_with(&self, search_term: &str) -> Vec<(TagId, String)>;
+    /// Gets all tags without images
+    fn get_tag_orphans(&self) -> Vec<(TagId, String)>;
+
+    // images
+    /// Adds an image and returns its id
+    fn add_image(&mut self, path: &str) -> Option<ImageId>;
+    /// Removes an image and its tags
+    fn remove_image(&mut self, img_id: ImageId) -> Option<()>;
+    /// Gets the path of an image
+    fn get_image_path(&self, img_id: ImageId) -> Option<String>;
+    /// Gets the id of the image with the given path
+    fn get_image_id(&self, path: &str) -> Option<ImageId>;
+    /// Changes the path of an image, fails if the path is taken
+    fn update_image_path(&mut self, img_id: ImageId, new_path: &str) -> Option<()>;
+    /// Gets all images without tags
+    fn get_image_orphans(&self) -> Vec<(ImageId, String)>;
+    /// Adds the tag and its parents to the image, if create is true a missing tag gets created
+    fn add_tag_to_img(&mut self, tag: &str, img_id: ImageId, create: bool) -> Option<()>;
+    /// Removes the tag from the image
+    fn remove_tag_from_img(&mut self, tag_id: TagId, img_id: ImageId) -> Option<()>;
+    /// Gets all tags of an image
+    fn get_tags_of_img(&self, img_id: ImageId) -> Vec<(TagId, String)>;
+    /// Gets all images with the tag
+    fn get_images_with_tag(&self, tag_id: TagId) -> Vec<(ImageId, String)>;
+
+    // namespaces
+    /// Adds a namespace and returns its id
+    fn add_namespace(&mut self, name: &str) -> Option<NamespaceId>;
+    /// Removes a namespace and its connections to tags
+    fn remove_namespace(&mut self, namespace_id: NamespaceId) -> Option<()>;
+    /// Renames a namespace, fails if the name is taken
+    fn rename_namespace(&mut self, namespace_id: NamespaceId, new_name: &str) -> Option<()>;
+    /// Gets the name of a namespace
+    fn get_namespace_name(&self, namespace_id: NamespaceId) -> Option<String>;
+    /// Gives a tag a namespace, fails if the tag already has one
+    fn add_namespace_to_tag(&mut self, namespace_id: NamespaceId, tag_id: TagId) -> Option<()>;
+    /// Removes the namespace of a tag
+    fn remove_namespace_from_tag(&mut self, tag_id: TagId) -> Option<()>;
+    /// Gets the namespace of a tag
+    fn get_namespace_of_tag(&self, tag_id: TagId) -> Option<NamespaceId>;
+    /// Gets all tags with the namespace
+    fn get_tags_with_namespace(&self, namespace_id: NamespaceId) -> Vec<(TagId, String)>;
+    /// Gets all namespaces without tags
+    fn get_namespace_orphans(&self) -> Vec<(NamespaceId, String)>;
+
+    // subtags
+    /// Makes a tag the parent of another, fails if the child already is the parent's parent
+    fn add_parent(&mut self, parent_id: TagId, child_id: TagId) -> Option<()>;
+    /// Removes the connection between two tags, no matter which is the parent
+    fn remove_connection(&mut self, tag1: TagId, tag2: TagId) -> Option<()>;
+    /// Gets all parents and the parent's parents of a tag
+    fn get_parents(&self, tag_id: TagId) -> Vec<TagId>;
+    /// Gets all children and the children's children of a tag
+    fn get_children(&self, tag_id: TagId) -> Vec<TagId>;
+}
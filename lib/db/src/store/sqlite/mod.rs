@@ -0,0 +1,109 @@
+use super::TagStore;
+use crate::{
+    methods::{images, namespaces, subtags, tags, utils},
+    types::{ImageId, NamespaceId, TagId},
+};
+use rusqlite::Connection;
+
+/// The SQLite backend, a thin layer over the functions in [`crate::methods`]
+impl TagStore for Connection {
+    fn add_tag(&mut self, name: &str) -> Option<TagId> {
+        tags::add_tag(name, self)
+    }
+    fn remove_tag(&mut self, tag_id: TagId) -> Option<()> {
+        tags::remove_tag(tag_id, self).ok()
+    }
+    fn rename_tag(&mut self, tag_id: TagId, new_name: &str) -> Option<()> {
+        tags::rename_tag(tag_id, new_name, self).ok()
+    }
+    fn get_tag_name(&self, tag_id: TagId) -> Option<String> {
+        tags::get_name(tag_id, self)
+    }
+    fn get_tag_id(&self, name: &str) -> Option<TagId> {
+        self.query_row("SELECT id FROM tags WHERE name=?1", [name], |row| {
+            row.get(0)
+        })
+        .ok()
+    }
+    fn get_tags_with(&self, search_term: &str) -> Vec<(TagId, String)> {
+        tags::get_tags_with(search_term, self)
+    }
+    fn get_tag_orphans(&self) -> Vec<(TagId, String)> {
+        tags::get_orphans(self)
+    }
+
+    fn add_image(&mut self, path: &str) -> Option<ImageId> {
+        images::add_image(path, self)
+    }
+    fn remove_image(&mut self, img_id: ImageId) -> Option<()> {
+        utils::remove_id(img_id.0, "images", self).ok()
+    }
+    fn get_image_path(&self, img_id: ImageId) -> Option<String> {
+        images::get_path(img_id, self)
+    }
+    fn get_image_id(&self, path: &str) -> Option<ImageId> {
+        self.query_row("SELECT id FROM images WHERE path=?1", [path], |row| {
+            row.get(0)
+        })
+        .ok()
+    }
+    fn update_image_path(&mut self, img_id: ImageId, new_path: &str) -> Option<()> {
+        images::update_path(img_id, new_path, self).ok()
+    }
+    fn get_image_orphans(&self) -> Vec<(ImageId, String)> {
+        images::get_orphans(self)
+    }
+    fn add_tag_to_img(&mut self, tag: &str, img_id: ImageId, create: bool) -> Option<()> {
+        tags::add_tag_to_img(tag, img_id, create, self)
+    }
+    fn remove_tag_from_img(&mut self, tag_id: TagId, img_id: ImageId) -> Option<()> {
+        tags::remove_tag_from_img(tag_id, img_id, self).ok()
+    }
+    fn get_tags_of_img(&self, img_id: ImageId) -> Vec<(TagId, String)> {
+        images::get_tags_of_img(img_id, self)
+    }
+    fn get_images_with_tag(&self, tag_id: TagId) -> Vec<(ImageId, String)> {
+        images::get_images_with_tag(tag_id, self).unwrap_or_default()
+    }
+
+    fn add_namespace(&mut self, name: &str) -> Option<NamespaceId> {
+        namespaces::add_namespace(name, self)
+    }
+    fn remove_namespace(&mut self, namespace_id: NamespaceId) -> Option<()> {
+        namespaces::remove_namespace(namespace_id, self).ok()
+    }
+    fn rename_namespace(&mut self, namespace_id: NamespaceId, new_name: &str) -> Option<()> {
+        namespaces::rename_namespace(namespace_id, new_name, self).ok()
+    }
+    fn get_namespace_name(&self, namespace_id: NamespaceId) -> Option<String> {
+        namespaces::get_namespace_name(namespace_id, self)
+    }
+    fn add_namespace_to_tag(&mut self, namespace_id: NamespaceId, tag_id: TagId) -> Option<()> {
+        namespaces::add_namespace_to_tag(namespace_id, tag_id, self).ok()
+    }
+    fn remove_namespace_from_tag(&mut self, tag_id: TagId) -> Option<()> {
+        namespaces::remove_namespace_from_tag(tag_id, self).ok()
+    }
+    fn get_namespace_of_tag(&self, tag_id: TagId) -> Option<NamespaceId> {
+        namespaces::get_namespace_of_tag(tag_id, self)
+    }
+    fn get_tags_with_namespace(&self, namespace_id: NamespaceId) -> Vec<(TagId, String)> {
+        namespaces::get_tags_with_namespace(namespace_id, self)
+    }
+    fn get_namespace_orphans(&self) -> Vec<(NamespaceId, String)> {
+        namespaces::get_orphans(self)
+    }
+
+    fn add_parent(&mut self, parent_id: TagId, child_id: TagId) -> Option<()> {
+        subtags::parent_tag(parent_id, child_id, self)
+    }
+    fn remove_connection(&mut self, tag1: TagId, tag2: TagId) -> Option<()> {
+        subtags::remove_connection(tag1, tag2, self)
+    }
+    fn get_parents(&self, tag_id: TagId) -> Vec<TagId> {
+        subtags::get_parents(tag_id, self).unwrap_or_default()
+    }
+    fn get_children(&self, tag_id: TagId) -> Vec<TagId> {
+        subtags::get_children(tag_id, self).unwrap_or_default()
+    }
+}
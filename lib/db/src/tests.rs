@@ -1,4 +1,4 @@
-use crate::methods::*;
+use crate::{methods::*, types::*};
 use rusqlite::Connection;
 
 fn init_db() -> Connection {
@@ -15,14 +15,14 @@ mod util {
     fn remove_id() {
         let conn = init_db();
         let t_id = tags::add_tag("test", &conn).unwrap();
-        utils::remove_id(t_id, "tags", &conn).unwrap();
+        utils::remove_id(t_id.0, "tags", &conn).unwrap();
         assert!(Option::is_none(&tags::get_name(t_id, &conn)));
     }
 }
 
 #[cfg(test)]
 mod tag_tests {
-    use super::{images, init_db, tags, utils};
+    use super::{images, init_db, tags, utils, TagId};
     mod adding {
         use super::*;
         #[test]
@@ -31,7 +31,7 @@ mod tag_tests {
             // with getting the name and the other way around
             let conn = init_db();
             tags::add_tag("test", &conn).unwrap();
-            assert_eq!(tags::get_name(1, &conn), Some("test".to_owned()));
+            assert_eq!(tags::get_name(TagId(1), &conn), Some("test".to_owned()));
         }
         #[test]
         fn add_tag_twice() {
@@ -43,16 +43,16 @@ mod tag_tests {
         fn add_multiple_tags() {
             let mut conn = init_db();
             let t_ids = tags::add_tags(vec!["test1", "test2", "test3"], &mut conn);
-            assert_eq!(t_ids[0], Some(1));
-            assert_eq!(t_ids[1], Some(2));
-            assert_eq!(t_ids[2], Some(3));
+            assert_eq!(t_ids[0], Some(TagId(1)));
+            assert_eq!(t_ids[1], Some(TagId(2)));
+            assert_eq!(t_ids[2], Some(TagId(3)));
         }
         #[test]
         fn add_multiple_tags_twice() {
             let mut conn = init_db();
             let t_ids = tags::add_tags(vec!["test", "test", "test"], &mut conn);
             assert_eq!(t_ids.len(), 3);
-            assert_eq!(t_ids, vec![Some(1), None, None]);
+            assert_eq!(t_ids, vec![Some(TagId(1)), None, None]);
         }
     }
     mod deleting {
@@ -67,7 +67,7 @@ mod tag_tests {
         #[test]
         fn delete_nonexistent_tag() {
             let conn = init_db();
-            tags::remove_tag(1, &conn).unwrap();
+            tags::remove_tag(TagId(1), &conn).unwrap();
         }
     }
     mod getting {
@@ -82,12 +82,12 @@ mod tag_tests {
         fn get_tag_name() {
             let conn = init_db();
             tags::add_tag("test", &conn).unwrap();
-            assert_eq!(tags::get_name(1, &conn), Some("test".to_owned()));
+            assert_eq!(tags::get_name(TagId(1), &conn), Some("test".to_owned()));
         }
         #[test]
         fn get_nonexistent_tag_name() {
             let conn = init_db();
-            assert!(Option::is_none(&tags::get_name(1, &conn)));
+            assert!(Option::is_none(&tags::get_name(TagId(1), &conn)));
         }
 
         #[test]
@@ -120,7 +120,7 @@ mod tag_tests {
 
 #[cfg(test)]
 mod image_test {
-    use super::{images, init_db, tags, utils};
+    use super::{images, init_db, subtags, tags, utils, ImageId, TagId};
     mod adding {
         use super::*;
         #[test]
@@ -132,7 +132,7 @@ mod image_test {
         #[test]
         fn add_img_twice() {
             let conn = init_db();
-            assert_eq!(Some(1), images::add_image("test.jpg", &conn));
+            assert_eq!(Some(ImageId(1)), images::add_image("test.jpg", &conn));
             assert!(Option::is_none(&images::add_image("test.jpg", &conn)));
         }
         #[test]
@@ -143,7 +143,7 @@ mod image_test {
             tags::add_tag_to_img("test", im_id, true, &conn).unwrap();
             assert_eq!(
                 images::get_tags_of_img(im_id, &conn),
-                vec![(1, "test".to_owned())]
+                vec![(TagId(1), "test".to_owned())]
             );
         }
         #[test]
@@ -154,6 +154,45 @@ mod image_test {
             tags::add_tag_to_img("test", im_id, false, &conn).unwrap();
         }
         #[test]
+        fn batch_tag() {
+            let mut conn = init_db();
+            let parent = tags::add_tag("animal", &conn).unwrap();
+            let cat = tags::add_tag("cat", &conn).unwrap();
+            subtags::parent_tag(parent, cat, &conn).unwrap();
+            let im1 = images::add_image("test1.jpg", &conn).unwrap();
+            let im2 = images::add_image("test2.jpg", &conn).unwrap();
+            tags::add_tag_to_img("old", im1, true, &conn).unwrap();
+            let old = TagId(utils::get_id("tags", "name='old'", &conn).unwrap());
+            let summaries =
+                tags::batch_tag(&[im1, im2], &["cat", "new"], &["old"], true, &mut conn).unwrap();
+            let new = TagId(utils::get_id("tags", "name='new'", &conn).unwrap());
+            assert_eq!(
+                summaries,
+                vec![
+                    tags::BatchSummary {
+                        img_id: im1,
+                        added: vec![cat, parent, new],
+                        removed: vec![old],
+                    },
+                    tags::BatchSummary {
+                        img_id: im2,
+                        added: vec![cat, parent, new],
+                        removed: vec![],
+                    },
+                ]
+            );
+            assert_eq!(images::get_tags_of_img(im1, &conn).len(), 3);
+        }
+        #[test]
+        fn batch_tag_rolls_back() {
+            let mut conn = init_db();
+            let im = images::add_image("test.jpg", &conn).unwrap();
+            assert!(tags::batch_tag(&[im, ImageId(42)], &["new"], &[], true, &mut conn).is_err());
+            assert!(tags::batch_tag(&[im], &["missing"], &[], false, &mut conn).is_err());
+            assert_eq!(utils::get_id("tags", "name='new'", &conn), None);
+            assert!(images::get_tags_of_img(im, &conn).is_empty());
+        }
+        #[test]
         fn add_tag_to_img_twice() {
             let conn = init_db();
             let _ = tags::add_tag("test", &conn);
@@ -171,13 +210,20 @@ mod image_test {
             images::remove_image_path("test.jpg", &conn).unwrap();
             assert_eq!(utils::get_id("images", "path='test.jpg'", &conn), None);
         }
+        #[test]
+        fn delete_image_with_quote() {
+            let conn = init_db();
+            let im_id = images::add_image("it's.jpg", &conn).unwrap();
+            images::remove_image_path("it's.jpg", &conn).unwrap();
+            assert_eq!(images::get_path(im_id, &conn), None);
+        }
     }
     mod getting {
         use super::*;
         #[test]
         fn get_tags_of_nonexistent_image() {
             let conn = init_db();
-            assert!(images::get_tags_of_img(1, &conn).is_empty());
+            assert!(images::get_tags_of_img(ImageId(1), &conn).is_empty());
         }
 
         #[test]
@@ -186,8 +232,8 @@ mod image_test {
             let im_id = images::add_image("test.jpg", &conn).unwrap();
             let _ = tags::add_tag_to_img("test", im_id, true, &conn);
             assert_eq!(
-                images::get_tags_of_img(1, &conn),
-                vec![(1, "test".to_owned())]
+                images::get_tags_of_img(ImageId(1), &conn),
+                vec![(TagId(1), "test".to_owned())]
             );
         }
 
@@ -196,7 +242,7 @@ mod image_test {
             let conn = init_db();
             let im_id = images::add_image("test.jpg", &conn).unwrap();
             assert_eq!(images::get_path(im_id, &conn), Some("test.jpg".to_owned()));
-            let _ = utils::remove_id(im_id, "images", &conn);
+            let _ = utils::remove_id(im_id.0, "images", &conn);
             assert_eq!(images::get_path(im_id, &conn), None);
         }
         #[test]
@@ -221,10 +267,10 @@ mod image_test {
 
 #[cfg(test)]
 mod namespaces_and_parents {
-    use super::{init_db, namespaces, subtags, tags, utils};
+    use super::{init_db, namespaces, subtags, tags, utils, NamespaceId, TagId};
 
     mod namespacing {
-        use super::{init_db, namespaces, tags, utils};
+        use super::{init_db, namespaces, tags, utils, NamespaceId};
 
         mod adding {
             use super::*;
@@ -235,14 +281,14 @@ mod namespaces_and_parents {
                 let ns_id = namespaces::add_namespace("test", &conn).unwrap();
                 assert_eq!(
                     utils::get_id("namespaces", "name='test'", &conn),
-                    Some(ns_id)
+                    Some(ns_id.0)
                 );
             }
             #[test]
             fn remove_namespace() {
                 let conn = init_db();
                 let ns_id = namespaces::add_namespace("test", &conn).unwrap();
-                utils::remove_id(ns_id, "namespaces", &conn).unwrap();
+                utils::remove_id(ns_id.0, "namespaces", &conn).unwrap();
                 assert!(Option::is_none(&utils::get_id(
                     "namespaces",
                     "name='test'",
@@ -257,7 +303,7 @@ mod namespaces_and_parents {
                 let t_id = tags::add_tag("testtag", &conn).unwrap();
                 namespaces::add_namespace_to_tag(ns_id, t_id, &conn).unwrap();
                 assert_eq!(namespaces::get_namespace_of_tag(t_id, &conn), Some(ns_id));
-                let _ = utils::remove_id(ns_id, "namespaces", &conn);
+                let _ = utils::remove_id(ns_id.0, "namespaces", &conn);
                 assert_eq!(namespaces::get_namespace_of_tag(t_id, &conn), None);
                 assert!(Option::is_none(&namespaces::get_namespace_name(
                     ns_id, &conn
@@ -273,7 +319,10 @@ mod namespaces_and_parents {
                 let t_id = tags::add_tag("testtag", &conn).unwrap();
                 let ns_id = namespaces::add_namespace("test", &conn).unwrap();
                 namespaces::add_namespace_to_tag(ns_id, t_id, &conn).unwrap();
-                assert_eq!(namespaces::get_namespace_of_tag(t_id, &conn), Some(1));
+                assert_eq!(
+                    namespaces::get_namespace_of_tag(t_id, &conn),
+                    Some(NamespaceId(1))
+                );
                 namespaces::remove_namespace_from_tag(t_id, &conn).unwrap();
                 assert_eq!(namespaces::get_namespace_of_tag(t_id, &conn), None);
             }
@@ -358,7 +407,7 @@ mod namespaces_and_parents {
         }
     }
     mod parents {
-        use super::{init_db, subtags, tags, utils};
+        use super::{init_db, subtags, tags, utils, TagId};
         mod adding {
             use super::*;
 
@@ -379,7 +428,7 @@ mod namespaces_and_parents {
                 let t_ids = tags::add_tags(vec!["one", "two"], &mut conn);
                 let _ = subtags::parent_tag(t_ids[0].unwrap(), t_ids[1].unwrap(), &conn);
                 subtags::parent_tag(t_ids[1].unwrap(), t_ids[0].unwrap(), &conn).unwrap();
-                subtags::get_parents(1, &conn).unwrap();
+                subtags::get_parents(TagId(1), &conn).unwrap();
             }
         }
         mod deleting {
@@ -391,8 +440,14 @@ mod namespaces_and_parents {
                 let t_ids = tags::add_tags(vec!["a", "b"], &mut conn);
                 let _ = subtags::parent_tag(t_ids[0].unwrap(), t_ids[1].unwrap(), &conn);
                 subtags::remove_connection(t_ids[0].unwrap(), t_ids[1].unwrap(), &conn).unwrap();
-                assert_eq!(utils::get_id("tags", "name='a'", &conn), t_ids[0]);
-                assert_eq!(utils::get_id("tags", "name='b'", &conn), t_ids[1]);
+                assert_eq!(
+                    utils::get_id("tags", "name='a'", &conn).map(TagId),
+                    t_ids[0]
+                );
+                assert_eq!(
+                    utils::get_id("tags", "name='b'", &conn).map(TagId),
+                    t_ids[1]
+                );
                 assert!(subtags::get_children(t_ids[0].unwrap(), &conn)
                     .unwrap()
                     .is_empty());
@@ -441,9 +496,1903 @@ mod namespaces_and_parents {
             fn get_none() {
                 let conn = init_db();
                 let _ = tags::add_tag("a", &conn);
-                assert!(subtags::get_parents(1, &conn).unwrap().is_empty());
-                assert!(subtags::get_children(1, &conn).unwrap().is_empty());
+                assert!(subtags::get_parents(TagId(1), &conn).unwrap().is_empty());
+                assert!(subtags::get_children(TagId(1), &conn).unwrap().is_empty());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod listing_tests {
+    use super::{images, init_db, tags, Cursor, ListOptions, SortBy};
+
+    #[test]
+    fn pages_cover_everything_once() {
+        let conn = init_db();
+        for i in 0..10 {
+            images::add_image(&format!("{}.jpg", i), &conn).unwrap();
+        }
+        let mut seen = vec![];
+        let mut options = ListOptions::limit(3).sorted(SortBy::Name, true);
+        loop {
+            let page = images::get_orphans_page(&options, &conn).unwrap();
+            assert!(page.items.len() <= 3);
+            seen.extend(page.items.into_iter().map(|image| image.1));
+            match page.next {
+                Some(next) => options = options.after(Some(next)),
+                None => break,
+            }
+        }
+        let expected: Vec<String> = (0..10).rev().map(|i| format!("{}.jpg", i)).collect();
+        assert_eq!(seen, expected);
+    }
+    #[test]
+    fn zero_and_huge_limits_list_everything() {
+        let conn = init_db();
+        for i in 0..3 {
+            images::add_image(&format!("{}.jpg", i), &conn).unwrap();
+        }
+        for limit in [0, usize::MAX] {
+            let options = ListOptions {
+                offset: usize::MAX,
+                ..ListOptions::limit(limit)
+            };
+            assert!(images::get_orphans_page(&options, &conn)
+                .unwrap()
+                .items
+                .is_empty());
+            let page = images::get_orphans_page(&ListOptions::limit(limit), &conn).unwrap();
+            assert_eq!(page.items.len(), 3);
+            assert_eq!(page.next, None);
+        }
+    }
+    #[test]
+    fn sort_by_usage() {
+        let conn = init_db();
+        let im1 = images::add_image("1.jpg", &conn).unwrap();
+        let im2 = images::add_image("2.jpg", &conn).unwrap();
+        tags::add_tag_to_img("solo", im1, true, &conn).unwrap();
+        tags::add_tag_to_img("common", im1, true, &conn).unwrap();
+        tags::add_tag_to_img("common", im2, true, &conn).unwrap();
+        let options = ListOptions::default().sorted(SortBy::Usage, true);
+        let page = tags::get_tags_with_page("o", &options, &conn).unwrap();
+        let names: Vec<String> = page.items.into_iter().map(|tag| tag.1).collect();
+        assert_eq!(names, vec!["common", "solo"]);
+        assert_eq!(page.next, None);
+    }
+    #[test]
+    fn offset_and_query() {
+        let conn = init_db();
+        for i in 0..5 {
+            let img = images::add_image(&format!("{}.jpg", i), &conn).unwrap();
+            tags::add_tag_to_img("all", img, true, &conn).unwrap();
+            if i % 2 == 0 {
+                tags::add_tag_to_img("even", img, true, &conn).unwrap();
+            }
+        }
+        let options = ListOptions {
+            offset: 1,
+            ..ListOptions::limit(1)
+        };
+        let page = images::query_sql_page("all even", &options, &conn).unwrap();
+        assert_eq!(page.items[0].1, "2.jpg");
+        assert!(page.next.is_some());
+        assert_eq!(images::query_sql("even unknown", &conn).len(), 3);
+    }
+    #[test]
+    fn streams_visit_rows_in_order() {
+        let conn = init_db();
+        for i in 0..4 {
+            let img = images::add_image(&format!("{}.jpg", i), &conn).unwrap();
+            if i != 2 {
+                tags::add_tag_to_img("seen", img, true, &conn).unwrap();
+            }
+        }
+        let mut paths = vec![];
+        images::for_each_image(&conn, |image| paths.push((image.path, image.tag_count))).unwrap();
+        assert_eq!(paths[0], ("0.jpg".to_owned(), 1));
+        assert_eq!(paths[2], ("2.jpg".to_owned(), 0));
+        assert_eq!(paths.len(), 4);
+        let mut matches = vec![];
+        let options = ListOptions::default().sorted(SortBy::Name, true);
+        images::for_each_query_match("seen", &options, &conn, |_, path| matches.push(path))
+            .unwrap();
+        assert_eq!(matches, vec!["3.jpg", "1.jpg", "0.jpg"]);
+        let mut orphans = vec![];
+        images::for_each_orphan(&ListOptions::default(), &conn, |id, _| orphans.push(id)).unwrap();
+        assert_eq!(orphans.len(), 1);
+    }
+    #[test]
+    fn cursor_round_trip() {
+        for cursor in ["i4:1700000000", "t12:some:path.jpg"] {
+            assert_eq!(cursor.parse::<Cursor>().unwrap().to_string(), cursor);
+        }
+        assert!("x1:2".parse::<Cursor>().is_err());
+    }
+}
+
+#[cfg(test)]
+mod history_tests {
+    use super::{groups, history, images, init_db, namespaces, subtags, tags, utils, TagId};
+    use history::Operation;
+
+    fn operations(entries: &[history::HistoryEntry]) -> Vec<Operation> {
+        entries.iter().map(|entry| entry.operation).collect()
+    }
+
+    #[test]
+    fn image_history() {
+        let conn = init_db();
+        let im = images::add_image("test.jpg", &conn).unwrap();
+        tags::add_tag_to_img("nsfw", im, true, &conn).unwrap();
+        let t_id = TagId(utils::get_id("tags", "name='nsfw'", &conn).unwrap());
+        tags::remove_tag_from_img(t_id, im, &conn).unwrap();
+        images::update_path(im, "moved.jpg", &conn).unwrap();
+        let entries = history::get_history_of_image(im, &conn).unwrap();
+        assert_eq!(
+            operations(&entries),
+            vec![
+                Operation::AddImage,
+                Operation::TagImage,
+                Operation::UntagImage,
+                Operation::UpdatePath
+            ]
+        );
+        assert_eq!(entries[1].tag_name, Some("nsfw".to_owned()));
+        assert_eq!(entries[1].img_path, Some("test.jpg".to_owned()));
+        assert_eq!(entries[3].img_path, Some("moved.jpg".to_owned()));
+        assert_eq!(entries[3].previous, Some("test.jpg".to_owned()));
+    }
+    #[test]
+    fn tag_history() {
+        let conn = init_db();
+        let parent = tags::add_tag("animal", &conn).unwrap();
+        let child = tags::add_tag("cat", &conn).unwrap();
+        let ns = namespaces::add_namespace("species", &conn).unwrap();
+        subtags::parent_tag(parent, child, &conn).unwrap();
+        namespaces::add_namespace_to_tag(ns, child, &conn).unwrap();
+        tags::rename_tag(child, "kitten", &conn).unwrap();
+        tags::remove_tag(child, &conn).unwrap();
+        let entries = history::get_history_of_tag(child, &conn).unwrap();
+        assert_eq!(
+            operations(&entries),
+            vec![
+                Operation::AddTag,
+                Operation::AddParent,
+                Operation::AddNamespaceToTag,
+                Operation::RenameTag,
+                Operation::RemoveTag
+            ]
+        );
+        assert_eq!(entries[3].previous, Some("cat".to_owned()));
+        assert_eq!(entries[4].tag_name, Some("kitten".to_owned()));
+        // the parent also sees the connection
+        assert_eq!(
+            operations(&history::get_history_of_tag(parent, &conn).unwrap()),
+            vec![Operation::AddTag, Operation::AddParent]
+        );
+    }
+    #[test]
+    fn failed_changes_are_not_recorded() {
+        let conn = init_db();
+        let _ = tags::add_tag("test", &conn);
+        let _ = tags::add_tag("test", &conn);
+        tags::remove_tag(TagId(42), &conn).unwrap();
+        let entries = history::get_history_between(0, i64::MAX, &conn).unwrap();
+        assert_eq!(operations(&entries), vec![Operation::AddTag]);
+    }
+    #[test]
+    fn history_between() {
+        let conn = init_db();
+        let _ = images::add_image("test.jpg", &conn);
+        conn.execute("UPDATE history SET timestamp=100", ())
+            .unwrap();
+        let _ = images::add_image("test2.jpg", &conn);
+        assert_eq!(
+            history::get_history_between(0, 101, &conn).unwrap().len(),
+            1
+        );
+        assert_eq!(
+            history::get_history_between(101, i64::MAX, &conn)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+    #[test]
+    fn actor() {
+        let conn = init_db();
+        let _ = tags::add_tag("before", &conn);
+        history::set_actor(Some("importer"), &conn).unwrap();
+        let _ = tags::add_tag("during", &conn);
+        history::set_actor(None, &conn).unwrap();
+        let _ = tags::add_tag("after", &conn);
+        let actors: Vec<Option<String>> = history::get_history_between(0, i64::MAX, &conn)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.actor)
+            .collect();
+        assert_eq!(actors, vec![None, Some("importer".to_owned()), None]);
+    }
+    #[test]
+    fn timestamps() {
+        assert_eq!(utils::parse_date("1970-01-01"), Some(0));
+        assert_eq!(utils::parse_date("2026-01-01"), Some(1767225600));
+        assert_eq!(utils::parse_date("2024-02-29 12:30:15"), Some(1709209815));
+        assert_eq!(utils::parse_date("2024-13-01"), None);
+        assert_eq!(utils::format_timestamp(1709209815), "2024-02-29 12:30:15");
+    }
+    #[test]
+    fn rows_without_history_entry() {
+        let conn = init_db();
+        conn.execute_batch("DROP TABLE history").unwrap();
+        assert_eq!(tags::add_tag("cat", &conn), None);
+        assert_eq!(tags::resolve("cat", &conn), None);
+        assert_eq!(images::add_image("cat.jpg", &conn), None);
+        assert_eq!(images::get_image_by_path("cat.jpg", &conn), None);
+        assert_eq!(namespaces::add_namespace("species", &conn), None);
+        assert_eq!(namespaces::get_namespace_by_name("species", &conn), None);
+        assert_eq!(groups::add_group("cats", &conn), None);
+        assert_eq!(groups::add_smart_group("cats", "cat", false, &conn), None);
+        assert_eq!(groups::get_group_by_name("cats", &conn), None);
+
+        // inside a transaction only the tag gets undone
+        conn.execute_batch("BEGIN").unwrap();
+        conn.execute_batch("INSERT INTO tags(name) VALUES ('dog')")
+            .unwrap();
+        assert_eq!(tags::add_tag("cat", &conn), None);
+        conn.execute_batch("COMMIT").unwrap();
+        assert_eq!(tags::resolve("cat", &conn), None);
+        assert!(tags::resolve("dog", &conn).is_some());
+    }
+}
+
+#[cfg(test)]
+mod value_tests {
+    use super::{history, images, init_db, tags, utils, values, Comparison, TagId, TagValue};
+
+    #[test]
+    fn set_and_get() {
+        let conn = init_db();
+        let im = images::add_image("test.jpg", &conn).unwrap();
+        tags::add_tag_to_img("rating", im, true, &conn).unwrap();
+        tags::add_tag_to_img("title", im, true, &conn).unwrap();
+        let rating = TagId(utils::get_id("tags", "name='rating'", &conn).unwrap());
+        let title = TagId(utils::get_id("tags", "name='title'", &conn).unwrap());
+        assert_eq!(values::get_value(rating, im, &conn).unwrap(), None);
+        values::set_value(rating, im, Some(&TagValue::Int(3)), &conn).unwrap();
+        values::set_value(rating, im, Some(&TagValue::Int(4)), &conn).unwrap();
+        values::set_value(title, im, Some(&"sunset".parse().unwrap()), &conn).unwrap();
+        assert_eq!(
+            values::get_value(rating, im, &conn).unwrap(),
+            Some(TagValue::Int(4))
+        );
+        let all = values::get_values_of_img(im, &conn).unwrap();
+        assert_eq!(
+            all[1],
+            (
+                title,
+                "title".to_owned(),
+                TagValue::Text("sunset".to_owned())
+            )
+        );
+        let entry = history::get_history_of_tag(rating, &conn)
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(entry.operation, history::Operation::SetValue);
+        assert_eq!(entry.previous.as_deref(), Some("3"));
+        values::set_value(rating, im, None, &conn).unwrap();
+        assert_eq!(values::get_value(rating, im, &conn).unwrap(), None);
+        // the image needs the tag first
+        let other = images::add_image("other.jpg", &conn).unwrap();
+        assert!(values::set_value(rating, other, Some(&TagValue::Int(1)), &conn).is_err());
+    }
+    #[test]
+    fn compare_in_queries() {
+        let conn = init_db();
+        for (i, (rating, year, taken)) in [
+            ("5", "2014", "2014-06-01"),
+            ("4.5", "2016", "2016-02-10"),
+            ("2", "2018", "2018-12-31"),
+            ("good", "2020", "2020-01-01"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let im = images::add_image(&format!("{}.jpg", i), &conn).unwrap();
+            for (tag, value) in [("rating", rating), ("year", year), ("taken", taken)] {
+                tags::add_tag_to_img(tag, im, true, &conn).unwrap();
+                let tag = utils::get_id("tags", &format!("name='{}'", tag), &conn).unwrap();
+                values::set_value(TagId(tag), im, Some(&value.parse().unwrap()), &conn).unwrap();
+            }
+        }
+        let paths = |query: &str| -> Vec<String> {
+            images::query_sql(query, &conn)
+                .into_iter()
+                .map(|image| image.1)
+                .collect()
+        };
+        assert_eq!(paths("rating>=4"), vec!["0.jpg", "1.jpg"]);
+        assert_eq!(paths("year=2015..2018"), vec!["1.jpg", "2.jpg"]);
+        assert_eq!(paths("taken<2018-01-01 rating!=5"), vec!["1.jpg"]);
+        assert_eq!(paths("rating=good"), vec!["3.jpg"]);
+        assert_eq!(paths("rating year>2017").len(), 2);
+        assert_eq!(TagValue::Date(1767225600).to_string(), "2026-01-01");
+    }
+    #[test]
+    fn parse_conditions() {
+        assert_eq!(values::parse_condition("rating"), None);
+        assert_eq!(
+            values::parse_condition("rating<=2.5"),
+            Some(("rating", Comparison::Le(TagValue::Float(2.5))))
+        );
+        assert_eq!(
+            values::parse_condition("year=2015..2018"),
+            Some((
+                "year",
+                Comparison::Between(TagValue::Int(2015), TagValue::Int(2018))
+            ))
+        );
+        assert_eq!(
+            values::parse_condition("taken>1970-01-02"),
+            Some(("taken", Comparison::Gt(TagValue::Date(86400))))
+        );
+    }
+}
+
+#[cfg(test)]
+mod description_tests {
+    use super::{init_db, namespaces, tags, utils};
+
+    #[test]
+    fn set_and_search() {
+        let conn = init_db();
+        let dutch = tags::add_tag("dutch_angle", &conn).unwrap();
+        let wide = tags::add_tag("wide_shot", &conn).unwrap();
+        tags::set_description(dutch, Some("Camera tilted on its roll axis"), &conn).unwrap();
+        tags::set_description(wide, Some("The **camera** shows the whole scene"), &conn).unwrap();
+        assert_eq!(
+            tags::get_description(dutch, &conn).as_deref(),
+            Some("Camera tilted on its roll axis")
+        );
+        assert_eq!(tags::search_descriptions("camera", &conn).unwrap().len(), 2);
+        assert_eq!(
+            tags::search_descriptions("tilt*", &conn).unwrap(),
+            vec![(dutch, "dutch_angle".to_owned())]
+        );
+        // the index follows changes and removals
+        tags::set_description(dutch, None, &conn).unwrap();
+        assert!(tags::search_descriptions("tilted", &conn)
+            .unwrap()
+            .is_empty());
+        utils::remove_id(wide.0, "tags", &conn).unwrap();
+        assert!(tags::search_descriptions("camera", &conn)
+            .unwrap()
+            .is_empty());
+        let composition = namespaces::add_namespace("composition", &conn).unwrap();
+        namespaces::set_description(composition, Some("How a shot is framed"), &conn).unwrap();
+        assert_eq!(
+            namespaces::search_descriptions("framed", &conn).unwrap(),
+            vec![(composition, "composition".to_owned())]
+        );
+        let loaded = namespaces::load_namespaces(&[composition], &conn).unwrap();
+        assert_eq!(
+            loaded[0].description.as_deref(),
+            Some("How a shot is framed")
+        );
+    }
+}
+
+#[cfg(test)]
+mod property_tests {
+    use super::{history, images, init_db, properties};
+
+    #[test]
+    fn set_get_remove() {
+        let conn = init_db();
+        let im = images::add_image("test.jpg", &conn).unwrap();
+        properties::set_property(im, "license", "CC0", &conn).unwrap();
+        properties::set_property(im, "license", "CC-BY", &conn).unwrap();
+        properties::set_property(im, "caption", "A red fox", &conn).unwrap();
+        assert_eq!(
+            properties::get_property(im, "license", &conn).unwrap(),
+            Some("CC-BY".to_owned())
+        );
+        assert_eq!(
+            properties::get_properties(im, &conn).unwrap(),
+            vec![
+                ("caption".to_owned(), "A red fox".to_owned()),
+                ("license".to_owned(), "CC-BY".to_owned())
+            ]
+        );
+        properties::remove_property(im, "license", &conn).unwrap();
+        assert_eq!(
+            properties::get_property(im, "license", &conn).unwrap(),
+            None
+        );
+        let operations: Vec<history::Operation> = history::get_history_of_image(im, &conn)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.operation)
+            .collect();
+        assert_eq!(operations.len(), 5);
+        assert_eq!(operations[4], history::Operation::RemoveProperty);
+    }
+    #[test]
+    fn search() {
+        let conn = init_db();
+        let fox = images::add_image("fox.jpg", &conn).unwrap();
+        let cat = images::add_image("cat.jpg", &conn).unwrap();
+        properties::set_property(fox, "caption", "A red Fox", &conn).unwrap();
+        properties::set_property(cat, "caption", "A cat", &conn).unwrap();
+        properties::set_property(cat, "photographer", "fox", &conn).unwrap();
+        let found = properties::search_property("caption", "fox", &conn).unwrap();
+        assert_eq!(found, vec![(fox, "fox.jpg".to_owned())]);
+        assert_eq!(
+            properties::get_images_with_property("caption", "A cat", &conn).unwrap()[0].0,
+            cat
+        );
+        assert!(
+            properties::get_images_with_property("caption", "a cat", &conn)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
+
+#[cfg(test)]
+mod search_tests {
+    use super::{history, images, init_db, searches, tags, ListOptions, SortBy};
+
+    #[test]
+    fn save_and_run() {
+        let conn = init_db();
+        for path in ["a.jpg", "b.jpg", "c.jpg"] {
+            let im = images::add_image(path, &conn).unwrap();
+            tags::add_tag_to_img("cat", im, true, &conn).unwrap();
+        }
+        let cats = searches::add_search("cats", "cat", SortBy::Name, true, &conn).unwrap();
+        assert_eq!(
+            searches::add_search("cats", "dog", SortBy::Id, false, &conn),
+            None
+        );
+        searches::add_search("all", "", SortBy::Id, false, &conn).unwrap();
+        let names: Vec<String> = searches::get_searches(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|search| search.name)
+            .collect();
+        assert_eq!(names, vec!["all", "cats"]);
+        let paths: Vec<String> = searches::run_search(cats, &conn)
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .map(|image| image.1)
+            .collect();
+        assert_eq!(paths, vec!["c.jpg", "b.jpg", "a.jpg"]);
+        // the saved order wins over the one of the options
+        let page = searches::run_search_page(cats, &ListOptions::limit(1), &conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(page.items[0].1, "c.jpg");
+        assert!(page.next.is_some());
+        searches::rename_search(cats, "felines", &conn).unwrap();
+        let search = searches::get_search_by_name("felines", &conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (search.id, search.sort, search.descending),
+            (cats, SortBy::Name, true)
+        );
+        assert!(search.created.is_some());
+        searches::update_search(cats, "cat -dog", SortBy::Id, false, &conn).unwrap();
+        searches::remove_search(cats, &conn).unwrap();
+        assert_eq!(searches::run_search(cats, &conn).unwrap(), None);
+
+        let entries = history::get_history_of_search(cats, &conn).unwrap();
+        let changes: Vec<(history::Operation, Option<String>)> = entries
+            .into_iter()
+            .map(|entry| (entry.operation, entry.previous))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (history::Operation::AddSearch, None),
+                (history::Operation::RenameSearch, Some("cats".to_owned())),
+                (history::Operation::UpdateSearch, Some("cat".to_owned())),
+                (history::Operation::RemoveSearch, None),
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod merge_tests {
+    use super::{history, images, init_db, namespaces, subtags, tags, values, TagId, TagValue};
+
+    #[test]
+    fn merge_into_target() {
+        let mut conn = init_db();
+        let both = images::add_image("both.jpg", &conn).unwrap();
+        let kitten = images::add_image("kitten.jpg", &conn).unwrap();
+        tags::add_tag_to_img("cat", both, true, &conn).unwrap();
+        tags::add_tag_to_img("kitten", both, true, &conn).unwrap();
+        tags::add_tag_to_img("kitten", kitten, true, &conn).unwrap();
+        let cat = tags::resolve("cat", &conn).unwrap();
+        let source = tags::resolve("kitten", &conn).unwrap();
+        let animal = tags::add_tag("animal", &conn).unwrap();
+        subtags::parent_tag(animal, source, &conn).unwrap();
+        let species = namespaces::add_namespace("species", &conn).unwrap();
+        namespaces::add_namespace_to_tag(species, source, &conn).unwrap();
+        values::set_value(source, kitten, Some(&TagValue::Int(2)), &conn).unwrap();
+
+        tags::merge_tags(&[source], cat, true, &mut conn).unwrap();
+        assert_eq!(tags::get_name(source, &conn), None);
+        assert_eq!(images::get_images_with_tag(cat, &conn).unwrap().len(), 2);
+        assert_eq!(
+            images::get_tags_of_img(both, &conn),
+            vec![(cat, "cat".to_owned())]
+        );
+        assert_eq!(subtags::get_parents(cat, &conn).unwrap(), vec![animal]);
+        assert_eq!(namespaces::get_namespace_of_tag(cat, &conn), Some(species));
+        assert_eq!(
+            values::get_value(cat, kitten, &conn).unwrap(),
+            Some(TagValue::Int(2))
+        );
+        let entries = history::get_history_of_tag(cat, &conn).unwrap();
+        let changes: Vec<(history::Operation, Option<&str>)> = entries
+            .iter()
+            .rev()
+            .take(2)
+            .map(|entry| (entry.operation, entry.previous.as_deref()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (history::Operation::AddAlias, Some("kitten")),
+                (history::Operation::MergeTag, Some("kitten")),
+            ]
+        );
+
+        // the old name keeps working through the alias
+        assert_eq!(tags::resolve("kitten", &conn), Some(cat));
+        assert_eq!(tags::get_aliases(cat, &conn).unwrap(), vec!["kitten"]);
+        assert_eq!(images::query_sql("kitten", &conn).len(), 2);
+        let new = images::add_image("new.jpg", &conn).unwrap();
+        tags::add_tag_to_img("kitten", new, false, &conn).unwrap();
+        assert_eq!(images::get_tags_of_img(new, &conn)[0].0, cat);
+        tags::remove_alias("kitten", &conn).unwrap();
+        assert_eq!(tags::resolve("kitten", &conn), None);
+        let entry = history::get_history_of_tag(cat, &conn)
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(entry.operation, history::Operation::RemoveAlias);
+        assert_eq!(entry.previous.as_deref(), Some("kitten"));
+    }
+
+    #[test]
+    fn failed_merge_changes_nothing() {
+        let mut conn = init_db();
+        let img = images::add_image("a.jpg", &conn).unwrap();
+        tags::add_tag_to_img("a", img, true, &conn).unwrap();
+        let a = tags::resolve("a", &conn).unwrap();
+        let b = tags::add_tag("b", &conn).unwrap();
+        assert!(tags::merge_tags(&[a, TagId(99)], b, false, &mut conn).is_err());
+        assert_eq!(
+            images::get_tags_of_img(img, &conn),
+            vec![(a, "a".to_owned())]
+        );
+        assert!(tags::merge_tags(&[b], a, false, &mut conn).is_ok());
+        assert_eq!(tags::resolve("b", &conn), None);
+    }
+
+    #[test]
+    fn bulk_rename() {
+        let mut conn = init_db();
+        let img = images::add_image("a.jpg", &conn).unwrap();
+        for tag in ["cat_young", "dog_young", "kitten", "cat", "bird_young"] {
+            tags::add_tag_to_img(tag, img, true, &conn).unwrap();
+        }
+        let id = |name: &str, conn: &rusqlite::Connection| tags::resolve(name, conn).unwrap();
+        let (cat_young, dog_young, cat) = (
+            id("cat_young", &conn),
+            id("dog_young", &conn),
+            id("cat", &conn),
+        );
+        let birds = namespaces::add_namespace("birds", &conn).unwrap();
+        namespaces::add_namespace_to_tag(birds, id("bird_young", &conn), &conn).unwrap();
+
+        let renames = tags::preview_rename("^(.*)_young$", "$1", None, &conn).unwrap();
+        assert_eq!(renames.len(), 3);
+        assert_eq!(renames[0].collision, Some(cat));
+        assert_eq!(
+            (renames[1].tag_id, renames[1].new.as_str()),
+            (dog_young, "dog")
+        );
+        assert_eq!(renames[1].collision, None);
+        let in_namespace = tags::preview_rename("_young", "", Some(birds), &conn).unwrap();
+        assert_eq!(in_namespace.len(), 1);
+        assert!(tags::preview_rename("(", "", None, &conn).is_err());
+
+        // collisions fail the whole rename unless they get merged
+        assert!(tags::bulk_rename("_young$", "", None, false, &mut conn).is_err());
+        assert_eq!(tags::get_name(dog_young, &conn).unwrap(), "dog_young");
+        tags::bulk_rename("_young$", "", None, true, &mut conn).unwrap();
+        assert_eq!(tags::get_name(cat_young, &conn), None);
+        assert_eq!(tags::get_name(dog_young, &conn).unwrap(), "dog");
+        let names: Vec<String> = images::get_tags_of_img(img, &conn)
+            .into_iter()
+            .map(|tag| tag.1)
+            .collect();
+        assert_eq!(names, vec!["dog", "kitten", "cat", "bird"]);
+
+        // a tag can take the name another tag is renamed away from
+        let x = tags::add_tag("x", &conn).unwrap();
+        let xx = tags::add_tag("xx", &conn).unwrap();
+        tags::bulk_rename("^(x+)$", "${1}x", None, false, &mut conn).unwrap();
+        assert_eq!(tags::get_name(x, &conn).unwrap(), "xx");
+        assert_eq!(tags::get_name(xx, &conn).unwrap(), "xxx");
+    }
+
+    #[test]
+    fn merge_refuses_loops() {
+        let mut conn = init_db();
+        let source = tags::add_tag("source", &conn).unwrap();
+        let middle = tags::add_tag("middle", &conn).unwrap();
+        let target = tags::add_tag("target", &conn).unwrap();
+        subtags::parent_tag(source, middle, &conn).unwrap();
+        subtags::parent_tag(middle, target, &conn).unwrap();
+
+        // target -> middle -> target after merging
+        assert!(tags::merge_tags(&[source], target, false, &mut conn).is_err());
+        assert_eq!(tags::get_name(source, &conn).unwrap(), "source");
+        assert_eq!(subtags::get_parents(middle, &conn).unwrap(), vec![source]);
+        assert_eq!(
+            subtags::get_parents(target, &conn).unwrap(),
+            vec![middle, source]
+        );
+
+        // a direct connection just goes away
+        subtags::remove_connection(middle, target, &conn).unwrap();
+        subtags::parent_tag(source, target, &conn).unwrap();
+        tags::merge_tags(&[source], target, false, &mut conn).unwrap();
+        assert_eq!(tags::get_name(source, &conn), None);
+        assert_eq!(
+            subtags::get_parents(target, &conn).unwrap(),
+            Vec::<TagId>::new()
+        );
+        assert_eq!(subtags::get_parents(middle, &conn).unwrap(), vec![target]);
+    }
+}
+
+#[cfg(test)]
+mod suggestion_tests {
+    use super::{images, init_db, suggestions, tags};
+
+    #[test]
+    fn ranked_by_co_occurrence() {
+        let conn = init_db();
+        let mut img = None;
+        for (path, names) in [
+            ("1.jpg", vec!["cat", "cute", "indoor"]),
+            ("2.jpg", vec!["cat", "cute"]),
+            ("3.jpg", vec!["cat", "outdoor"]),
+            ("4.jpg", vec!["dog", "outdoor"]),
+            ("5.jpg", vec!["cat"]),
+        ] {
+            img = images::add_image(path, &conn);
+            for name in names {
+                tags::add_tag_to_img(name, img.unwrap(), true, &conn).unwrap();
+            }
+        }
+        // the last image only has cat
+        let img = img.unwrap();
+        let suggested = suggestions::suggest_tags(img, 10, &conn).unwrap();
+        let names: Vec<&str> = suggested.iter().map(|tag| tag.name.as_str()).collect();
+        assert_eq!(names, vec!["cute", "indoor", "outdoor"]);
+        assert_eq!(suggested[0].confidence, 0.5);
+        assert_eq!(suggested[0].support, 2);
+        // outdoor is as likely next to cat as indoor, but it isn't a cat thing
+        assert!(suggested[1].pmi > suggested[2].pmi);
+        assert_eq!(suggestions::suggest_tags(img, 1, &conn).unwrap().len(), 1);
+
+        let untagged = images::add_image("6.jpg", &conn).unwrap();
+        assert!(suggestions::suggest_tags(untagged, 10, &conn)
+            .unwrap()
+            .is_empty());
+    }
+}
+
+#[cfg(test)]
+mod rule_tests {
+    use super::{history, images, init_db, rules, tags, RuleId, RuleKind, TagId, Violation};
+    use history::Operation;
+    use rusqlite::Connection;
+
+    fn tag_names(img: super::ImageId, conn: &Connection) -> Vec<String> {
+        let mut names: Vec<String> = images::get_tags_of_img(img, conn)
+            .into_iter()
+            .map(|tag| tag.1)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn implications_and_exclusions() {
+        let mut conn = init_db();
+        let [beach, sunset, golden_hour, indoor, outdoor, warm]: [TagId; 6] = [
+            "beach",
+            "sunset",
+            "golden_hour",
+            "indoor",
+            "outdoor",
+            "warm",
+        ]
+        .map(|name| tags::add_tag(name, &conn).unwrap());
+        let golden =
+            rules::add_rule(RuleKind::Implies, &[beach, sunset], golden_hour, &mut conn).unwrap();
+        rules::add_rule(RuleKind::Implies, &[golden_hour], warm, &mut conn).unwrap();
+        let inside = rules::add_rule(RuleKind::Excludes, &[indoor], outdoor, &mut conn).unwrap();
+        assert_eq!(
+            rules::add_rule(RuleKind::Implies, &[], warm, &mut conn),
+            None
+        );
+        assert_eq!(
+            rules::add_rule(RuleKind::Implies, &[warm], warm, &mut conn),
+            None
+        );
+        assert_eq!(
+            rules::get_rules(&conn).unwrap()[0].conditions,
+            vec![beach, sunset]
+        );
+
+        let img = images::add_image("a.jpg", &conn).unwrap();
+        tags::add_tag_to_img("beach", img, false, &conn).unwrap();
+        assert_eq!(tag_names(img, &conn), vec!["beach"]);
+        // both conditions are needed, implied tags imply further tags
+        tags::add_tag_to_img("sunset", img, false, &conn).unwrap();
+        assert_eq!(
+            tag_names(img, &conn),
+            vec!["beach", "golden_hour", "sunset", "warm"]
+        );
+
+        tags::add_tag_to_img("indoor", img, false, &conn).unwrap();
+        assert_eq!(tags::add_tag_to_img("outdoor", img, false, &conn), None);
+        assert!(tags::batch_tag(&[img], &["outdoor"], &[], false, &mut conn).is_err());
+        // removing the excluding tag in the same batch is fine
+        let summary = tags::batch_tag(&[img], &["outdoor"], &["indoor"], false, &mut conn).unwrap();
+        assert_eq!(summary[0].added, vec![outdoor]);
+
+        // rules added later get checked and applied on demand
+        let other = images::add_image("b.jpg", &conn).unwrap();
+        tags::add_tag_to_img("golden_hour", other, false, &conn).unwrap();
+        let bright = tags::add_tag("bright", &conn).unwrap();
+        let sunny = rules::add_rule(RuleKind::Implies, &[warm], bright, &mut conn).unwrap();
+        rules::add_rule(RuleKind::Excludes, &[outdoor], bright, &mut conn).unwrap();
+        assert_eq!(
+            rules::get_violations(&conn).unwrap(),
+            vec![
+                Violation {
+                    rule_id: sunny,
+                    img_id: img
+                },
+                Violation {
+                    rule_id: sunny,
+                    img_id: other
+                },
+            ]
+        );
+        // the outdoor image can't get bright
+        assert_eq!(rules::apply_rules_to_all(&mut conn).unwrap(), 1);
+        assert!(tag_names(other, &conn).contains(&"bright".to_owned()));
+        assert_eq!(
+            rules::get_violations(&conn).unwrap(),
+            vec![Violation {
+                rule_id: sunny,
+                img_id: img
+            }]
+        );
+        rules::remove_rule(inside, &conn).unwrap();
+        tags::add_tag_to_img("indoor", img, false, &conn).unwrap();
+        assert_eq!(rules::get_rules(&conn).unwrap().len(), 4);
+        let changes: Vec<(Operation, Option<TagId>, Option<String>)> =
+            history::get_history_between(0, i64::MAX, &conn)
+                .unwrap()
+                .into_iter()
+                .filter(|entry| {
+                    matches!(entry.operation, Operation::AddRule | Operation::RemoveRule)
+                })
+                .map(|entry| (entry.operation, entry.tag_id, entry.previous))
+                .collect();
+        let change =
+            |operation, target, rule: &str| (operation, Some(target), Some(rule.to_owned()));
+        assert_eq!(
+            changes,
+            vec![
+                change(
+                    Operation::AddRule,
+                    golden_hour,
+                    "implies when beach, sunset"
+                ),
+                change(Operation::AddRule, warm, "implies when golden_hour"),
+                change(Operation::AddRule, outdoor, "excludes when indoor"),
+                change(Operation::AddRule, bright, "implies when warm"),
+                change(Operation::AddRule, bright, "excludes when outdoor"),
+                change(Operation::RemoveRule, outdoor, "excludes when indoor"),
+            ]
+        );
+    }
+
+    #[test]
+    fn deleted_and_merged_tags() {
+        let mut conn = init_db();
+        let [beach, sunset, golden_hour, dusk, evening, warm] =
+            ["beach", "sunset", "golden_hour", "dusk", "evening", "warm"]
+                .map(|name| tags::add_tag(name, &conn).unwrap());
+        rules::add_rule(RuleKind::Implies, &[beach, sunset], golden_hour, &mut conn).unwrap();
+        let rule = rules::add_rule(RuleKind::Implies, &[dusk, sunset], evening, &mut conn).unwrap();
+        let warmth = rules::add_rule(RuleKind::Implies, &[beach], warm, &mut conn).unwrap();
+        rules::add_rule(RuleKind::Implies, &[golden_hour], sunset, &mut conn).unwrap();
+        // the rule doesn't get broader
+        tags::remove_tag(golden_hour, &conn).unwrap();
+        let remaining: Vec<RuleId> = rules::get_rules(&conn)
+            .unwrap()
+            .iter()
+            .map(|rule| rule.id)
+            .collect();
+        assert_eq!(remaining, vec![rule, warmth]);
+        let img = images::add_image("a.jpg", &conn).unwrap();
+        tags::add_tag_to_img("sunset", img, false, &conn).unwrap();
+        assert_eq!(rules::apply_rules(img, &conn).unwrap(), vec![]);
+
+        // conditions and targets move to the merge target, duplicates go
+        tags::merge_tags(&[dusk], sunset, false, &mut conn).unwrap();
+        tags::merge_tags(&[warm], evening, false, &mut conn).unwrap();
+        let rules = rules::get_rules(&conn).unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].conditions, vec![sunset]);
+        assert_eq!(rules[1].target, evening);
+        assert_eq!(rules::apply_rules(img, &conn).unwrap(), vec![evening]);
+    }
+}
+
+#[cfg(test)]
+mod exclusive_tests {
+    use super::{history, images, init_db, namespaces, subtags, tags, Exclusivity, NamespaceId};
+
+    #[test]
+    fn replace_or_refuse() {
+        let mut conn = init_db();
+        let rating = namespaces::add_namespace("rating", &conn).unwrap();
+        let orientation = namespaces::add_namespace("orientation", &conn).unwrap();
+        for (namespace, names) in [
+            (rating, ["safe", "questionable"]),
+            (orientation, ["portrait", "landscape"]),
+        ] {
+            for name in names {
+                let tag = tags::add_tag(name, &conn).unwrap();
+                namespaces::add_namespace_to_tag(namespace, tag, &conn).unwrap();
+            }
+        }
+        let img = images::add_image("a.jpg", &conn).unwrap();
+        // not exclusive yet
+        tags::add_tag_to_img("safe", img, false, &conn).unwrap();
+        tags::add_tag_to_img("questionable", img, false, &conn).unwrap();
+        namespaces::set_exclusive(rating, Some(Exclusivity::Replace), &conn).unwrap();
+        namespaces::set_exclusive(orientation, Some(Exclusivity::Refuse), &conn).unwrap();
+        assert_eq!(
+            namespaces::get_exclusive_violations(&conn).unwrap(),
+            vec![(rating, img)]
+        );
+        let loaded = namespaces::load_namespaces(&[rating], &conn).unwrap();
+        assert_eq!(loaded[0].exclusive, Some(Exclusivity::Replace));
+        namespaces::set_exclusive(rating, Some(Exclusivity::Refuse), &conn).unwrap();
+        namespaces::set_exclusive(rating, Some(Exclusivity::Replace), &conn).unwrap();
+        let changes: Vec<(Option<NamespaceId>, Option<String>)> =
+            history::get_history_between(0, i64::MAX, &conn)
+                .unwrap()
+                .into_iter()
+                .filter(|entry| entry.operation == history::Operation::SetExclusive)
+                .map(|entry| (entry.namespace_id, entry.previous))
+                .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (Some(rating), None),
+                (Some(orientation), None),
+                (Some(rating), Some("replace".to_owned())),
+                (Some(rating), Some("refuse".to_owned())),
+            ]
+        );
+
+        tags::add_tag_to_img("safe", img, false, &conn).unwrap();
+        let names = |conn: &rusqlite::Connection| -> Vec<String> {
+            images::get_tags_of_img(img, conn)
+                .into_iter()
+                .map(|tag| tag.1)
+                .collect::<std::collections::BTreeSet<_>>()
+                .into_iter()
+                .collect()
+        };
+        assert_eq!(names(&conn), vec!["safe"]);
+        assert!(namespaces::get_exclusive_violations(&conn)
+            .unwrap()
+            .is_empty());
+
+        tags::add_tag_to_img("portrait", img, false, &conn).unwrap();
+        assert_eq!(tags::add_tag_to_img("landscape", img, false, &conn), None);
+        assert!(tags::batch_tag(&[img], &["landscape"], &[], false, &mut conn).is_err());
+        let summary = tags::batch_tag(&[img], &["questionable"], &[], false, &mut conn).unwrap();
+        assert_eq!(summary[0].removed.len(), 1);
+        assert_eq!(names(&conn), vec!["portrait", "questionable"]);
+
+        namespaces::set_exclusive(orientation, None, &conn).unwrap();
+        tags::add_tag_to_img("landscape", img, false, &conn).unwrap();
+        assert_eq!(names(&conn).len(), 3);
+    }
+
+    #[test]
+    fn among_added_tags() {
+        let mut conn = init_db();
+        let rating = namespaces::add_namespace("rating", &conn).unwrap();
+        let orientation = namespaces::add_namespace("orientation", &conn).unwrap();
+        let mut ids = Vec::new();
+        for (namespace, name) in [
+            (rating, "safe"),
+            (rating, "explicit"),
+            (orientation, "portrait"),
+            (orientation, "landscape"),
+        ] {
+            let tag = tags::add_tag(name, &conn).unwrap();
+            namespaces::add_namespace_to_tag(namespace, tag, &conn).unwrap();
+            ids.push(tag);
+        }
+        namespaces::set_exclusive(rating, Some(Exclusivity::Replace), &conn).unwrap();
+        namespaces::set_exclusive(orientation, Some(Exclusivity::Refuse), &conn).unwrap();
+        let img = images::add_image("a.jpg", &conn).unwrap();
+
+        assert!(
+            tags::batch_tag(&[img], &["portrait", "landscape"], &[], false, &mut conn).is_err()
+        );
+        let summary =
+            tags::batch_tag(&[img], &["safe", "explicit"], &[], false, &mut conn).unwrap();
+        assert_eq!(summary[0].added, vec![ids[1]]);
+        // a tag and its parent in the same namespace
+        subtags::parent_tag(ids[2], ids[3], &conn).unwrap();
+        assert_eq!(tags::add_tag_to_img("landscape", img, false, &conn), None);
+        subtags::parent_tag(ids[0], ids[1], &conn).unwrap();
+        tags::add_tag_to_img("explicit", img, false, &conn).unwrap();
+        assert_eq!(images::get_tags_of_img(img, &conn).len(), 1);
+        assert!(namespaces::get_exclusive_violations(&conn)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn failed_replace_changes_nothing() {
+        let conn = init_db();
+        let rating = namespaces::add_namespace("rating", &conn).unwrap();
+        namespaces::set_exclusive(rating, Some(Exclusivity::Replace), &conn).unwrap();
+        let safe = tags::add_tag("safe", &conn).unwrap();
+        namespaces::add_namespace_to_tag(rating, safe, &conn).unwrap();
+        let img = images::add_image("a.jpg", &conn).unwrap();
+        tags::add_tag_to_img("safe", img, false, &conn).unwrap();
+        let explicit = tags::add_tag("explicit", &conn).unwrap();
+        namespaces::add_namespace_to_tag(rating, explicit, &conn).unwrap();
+
+        // the replaced tag is already gone when recording the new one fails
+        conn.execute_batch(
+            "CREATE TEMP TRIGGER fail BEFORE INSERT ON history WHEN NEW.operation='tag_image'
+            BEGIN SELECT RAISE(ABORT, 'failed'); END",
+        )
+        .unwrap();
+        assert_eq!(tags::add_tag_to_img("explicit", img, false, &conn), None);
+        assert_eq!(tags::add_tag_to_img("new", img, true, &conn), None);
+        assert_eq!(
+            images::get_tags_of_img(img, &conn),
+            vec![(safe, "safe".to_owned())]
+        );
+        assert_eq!(tags::resolve("new", &conn), None);
+    }
+
+    #[test]
+    fn requested_tag_wins_over_parent() {
+        let mut conn = init_db();
+        let species = namespaces::add_namespace("species", &conn).unwrap();
+        namespaces::set_exclusive(species, Some(Exclusivity::Replace), &conn).unwrap();
+        let [cat, kitten, dog] = ["cat", "kitten", "dog"].map(|name| {
+            let tag = tags::add_tag(name, &conn).unwrap();
+            namespaces::add_namespace_to_tag(species, tag, &conn).unwrap();
+            tag
+        });
+        subtags::parent_tag(cat, kitten, &conn).unwrap();
+
+        let img = images::add_image("a.jpg", &conn).unwrap();
+        tags::add_tag_to_img("kitten", img, false, &conn).unwrap();
+        assert_eq!(
+            images::get_tags_of_img(img, &conn),
+            vec![(kitten, "kitten".to_owned())]
+        );
+        let other = images::add_image("b.jpg", &conn).unwrap();
+        let summary = tags::batch_tag(&[other], &["kitten"], &[], false, &mut conn).unwrap();
+        assert_eq!(summary[0].added, vec![kitten]);
+        // of two requested tags the last one stays
+        let summary = tags::batch_tag(&[other], &["kitten", "dog"], &[], false, &mut conn).unwrap();
+        assert_eq!(summary[0].added, vec![dog]);
+    }
+}
+
+#[cfg(test)]
+mod tree_tests {
+    use super::{images, init_db, subtags, tags, TagId, TagNode};
+
+    #[test]
+    fn tree() {
+        let conn = init_db();
+        let animal = tags::add_tag("animal", &conn).unwrap();
+        let pet = tags::add_tag("pet", &conn).unwrap();
+        let dog = tags::add_tag("dog", &conn).unwrap();
+        let cat = tags::add_tag("cat", &conn).unwrap();
+        let car = tags::add_tag("car", &conn).unwrap();
+        subtags::parent_tag(animal, dog, &conn).unwrap();
+        subtags::parent_tag(animal, cat, &conn).unwrap();
+        subtags::parent_tag(pet, cat, &conn).unwrap();
+        subtags::parent_tag(cat, pet, &conn); // would be a loop
+        subtags::parent_tag(animal, pet, &conn).unwrap();
+        let img = images::add_image("a.jpg", &conn).unwrap();
+        tags::add_tag_to_img("cat", img, false, &conn).unwrap();
+
+        let leaf = |id: TagId, name: &str, image_count: i64| TagNode {
+            id,
+            name: name.to_string(),
+            namespace: None,
+            image_count,
+            children: Vec::new(),
+        };
+        let pet_node = TagNode {
+            children: vec![leaf(cat, "cat", 1)],
+            ..leaf(pet, "pet", 1)
+        };
+        assert_eq!(subtags::get_tree(pet, &conn).unwrap(), pet_node);
+        let forest = subtags::get_forest(&conn).unwrap();
+        assert_eq!(
+            forest,
+            vec![
+                TagNode {
+                    children: vec![leaf(cat, "cat", 1), leaf(dog, "dog", 0), pet_node],
+                    ..leaf(animal, "animal", 1)
+                },
+                leaf(car, "car", 0),
+            ]
+        );
+        assert_eq!(subtags::get_roots(&conn).unwrap(), vec![animal, car]);
+        assert_eq!(subtags::get_leaves(&conn).unwrap(), vec![dog, cat, car]);
+        assert_eq!(
+            subtags::get_depths(&conn).unwrap(),
+            vec![(animal, 0), (pet, 1), (dog, 1), (cat, 2), (car, 0)]
+        );
+        assert!(subtags::get_tree(TagId(42), &conn).is_err());
+    }
+
+    #[test]
+    fn depths_with_loops() {
+        let conn = init_db();
+        let [root, a, b, c, x, y, z] =
+            ["root", "a", "b", "c", "x", "y", "z"].map(|name| tags::add_tag(name, &conn).unwrap());
+        // parent_tag refuses loops, older databases can still have them
+        for (parent, child) in [(root, a), (a, b), (b, c), (c, a), (x, y), (y, z), (z, x)] {
+            conn.execute(
+                "INSERT INTO subtag_map(parent_id, child_id) VALUES (?1, ?2)",
+                [parent, child],
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            subtags::get_depths(&conn).unwrap(),
+            vec![(root, 0), (a, 1), (b, 2), (c, 3), (x, 2), (y, 2), (z, 2)]
+        );
+    }
+
+    #[test]
+    fn tree_with_loop() {
+        let conn = init_db();
+        let [a, b, c] = ["a", "b", "c"].map(|name| tags::add_tag(name, &conn).unwrap());
+        subtags::parent_tag(a, b, &conn).unwrap();
+        subtags::parent_tag(b, c, &conn).unwrap();
+        assert_eq!(subtags::parent_tag(c, a, &conn), None);
+        assert_eq!(subtags::parent_tag(a, a, &conn), None);
+        assert_eq!(subtags::get_parents(a, &conn).unwrap(), Vec::<TagId>::new());
+
+        conn.execute(
+            "INSERT INTO subtag_map(parent_id, child_id) VALUES (?1, ?2)",
+            [c, a],
+        )
+        .unwrap();
+        let leaf = |id: TagId, name: &str| TagNode {
+            id,
+            name: name.to_string(),
+            namespace: None,
+            image_count: 0,
+            children: Vec::new(),
+        };
+        assert_eq!(
+            subtags::get_tree(a, &conn).unwrap(),
+            TagNode {
+                children: vec![TagNode {
+                    children: vec![leaf(c, "c")],
+                    ..leaf(b, "b")
+                }],
+                ..leaf(a, "a")
+            }
+        );
+    }
+}
+
+#[cfg(test)]
+mod graph_tests {
+    use super::{graph, images, init_db, namespaces, subtags, tags, GraphFormat, GraphOptions};
+
+    #[test]
+    fn export_hierarchy() {
+        let conn = init_db();
+        let animal = tags::add_tag("animal", &conn).unwrap();
+        let cat = tags::add_tag("cat", &conn).unwrap();
+        let kitten = tags::add_tag("kitten \"small\"", &conn).unwrap();
+        tags::add_tag("car", &conn).unwrap();
+        subtags::parent_tag(animal, cat, &conn).unwrap();
+        subtags::parent_tag(cat, kitten, &conn).unwrap();
+        let species = namespaces::add_namespace("species", &conn).unwrap();
+        namespaces::add_namespace_to_tag(species, cat, &conn).unwrap();
+        let img = images::add_image("a.jpg", &conn).unwrap();
+        tags::add_tag_to_img("cat", img, false, &conn).unwrap();
+
+        let dot =
+            graph::export_hierarchy(GraphFormat::Dot, &GraphOptions::default(), &conn).unwrap();
+        assert!(dot.starts_with("digraph tags {"));
+        assert!(dot.contains("t1 -> t2;"));
+        assert!(dot.contains("t2 -> t3;"));
+        assert!(dot.contains("t4 [label=\"car\"];"));
+        assert!(dot.contains(r#"label="kitten \"small\"""#));
+        assert!(!dot.contains("fillcolor=\"#"));
+
+        let options = GraphOptions {
+            root: Some(cat),
+            color_namespaces: true,
+            size_by_usage: true,
+        };
+        let mermaid = graph::export_hierarchy(GraphFormat::Mermaid, &options, &conn).unwrap();
+        assert_eq!(
+            mermaid,
+            "flowchart LR
+    t2[\"cat\"]
+    t3[\"kitten #quot;small#quot;\"]
+    t2 --> t3
+    subgraph namespaces
+        n1[\"species\"]
+    end
+    style n1 fill:#ffffb3
+    style t2 fill:#ffffb3,font-size:42px
+    style t3 font-size:14px
+"
+        );
+    }
+
+    #[test]
+    fn export_loop_from_root() {
+        let conn = init_db();
+        let [a, b, c] = ["a", "b", "c"].map(|name| tags::add_tag(name, &conn).unwrap());
+        subtags::parent_tag(a, b, &conn).unwrap();
+        subtags::parent_tag(b, c, &conn).unwrap();
+        conn.execute(
+            "INSERT INTO subtag_map(parent_id, child_id) VALUES (?1, ?2)",
+            [c, a],
+        )
+        .unwrap();
+
+        let options = GraphOptions {
+            root: Some(b),
+            ..Default::default()
+        };
+        let dot = graph::export_hierarchy(GraphFormat::Dot, &options, &conn).unwrap();
+        assert!(dot.contains("t1 -> t2;"));
+        assert!(dot.contains("t2 -> t3;"));
+        assert!(dot.contains("t3 -> t1;"));
+    }
+}
+
+#[cfg(test)]
+mod integrity_tests {
+    use super::{
+        images, init_db, integrity, namespaces, rules, subtags, tags, utils, Exclusivity, ImageId,
+        Problem, RuleKind, TagId, Violation,
+    };
+    use rusqlite::Connection;
+
+    fn tag_names(img: ImageId, conn: &Connection) -> Vec<String> {
+        let mut names: Vec<String> = images::get_tags_of_img(img, conn)
+            .into_iter()
+            .map(|tag| tag.1)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn foreign_keys_cascade() {
+        let conn = init_db();
+        let img = images::add_image("a.jpg", &conn).unwrap();
+        tags::add_tag_to_img("cat", img, true, &conn).unwrap();
+        conn.execute("DELETE FROM images", ()).unwrap();
+        assert!(utils::get_id("tag_map", "tag_id=1", &conn).is_none());
+        assert!(integrity::check(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn check_and_repair() {
+        let mut conn = init_db();
+        let animal = tags::add_tag("animal", &conn).unwrap();
+        let cat = tags::add_tag("cat", &conn).unwrap();
+        let kitten = tags::add_tag("kitten", &conn).unwrap();
+        let outdoor = tags::add_tag("outdoor", &conn).unwrap();
+        let img = images::add_image("a.jpg", &conn).unwrap();
+        tags::add_tag_to_img("cat", img, false, &conn).unwrap();
+        tags::add_tag_to_img("kitten", img, false, &conn).unwrap();
+        // parents, rules and exclusive namespaces added after tagging
+        subtags::parent_tag(animal, cat, &conn).unwrap();
+        subtags::parent_tag(cat, kitten, &conn).unwrap();
+        let rating = namespaces::add_namespace("rating", &conn).unwrap();
+        for name in ["safe", "questionable"] {
+            let tag = tags::add_tag(name, &conn).unwrap();
+            namespaces::add_namespace_to_tag(rating, tag, &conn).unwrap();
+            tags::add_tag_to_img(name, img, false, &conn).unwrap();
+        }
+        namespaces::set_exclusive(rating, Some(Exclusivity::Replace), &conn).unwrap();
+        let rule = rules::add_rule(RuleKind::Implies, &[kitten], outdoor, &mut conn).unwrap();
+        // what older versions could leave behind
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        conn.execute(
+            "INSERT INTO subtag_map(parent_id, child_id) VALUES (?1, ?2)",
+            [kitten, animal],
+        )
+        .unwrap();
+        conn.execute("INSERT INTO tag_map(img_id, tag_id) VALUES (?1, 42)", [img])
+            .unwrap();
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+
+        let problems = integrity::check(&conn).unwrap();
+        assert!(problems.iter().any(|problem| matches!(
+            problem,
+            Problem::Dangling { table, parent, .. } if table == "tag_map" && parent == "tags"
+        )));
+        for (parent_id, child_id) in [(animal, cat), (cat, kitten), (kitten, animal)] {
+            assert!(problems.contains(&Problem::Cycle {
+                parent_id,
+                child_id
+            }));
+        }
+        assert!(problems.contains(&Problem::MissingParent {
+            img_id: img,
+            tag_id: cat,
+            parent_id: animal,
+        }));
+        assert!(problems.contains(&Problem::Rule(Violation {
+            rule_id: rule,
+            img_id: img
+        })));
+        assert!(problems.contains(&Problem::Exclusive {
+            namespace_id: rating,
+            img_id: img,
+        }));
+
+        assert_eq!(integrity::repair(&mut conn).unwrap(), vec![]);
+        assert_eq!(
+            tag_names(img, &conn),
+            vec!["animal", "cat", "kitten", "outdoor", "questionable"]
+        );
+        // the newest connection of the loop is gone
+        assert_eq!(
+            subtags::get_parents(animal, &conn).unwrap(),
+            Vec::<TagId>::new()
+        );
+        assert_eq!(
+            subtags::get_parents(kitten, &conn).unwrap(),
+            vec![cat, animal]
+        );
+    }
+}
+
+#[cfg(test)]
+mod sync_tests {
+    use super::{
+        images, init_db, sync, tags, Conflict, ConflictKind, Side, SyncChange, SyncReport,
+    };
+    use rusqlite::Connection;
+
+    fn tag_names(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn.prepare("SELECT name FROM tags ORDER BY name").unwrap();
+        let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+        rows.collect::<rusqlite::Result<Vec<String>>>().unwrap()
+    }
+
+    #[test]
+    fn two_way() {
+        let mut local = init_db();
+        let mut remote = init_db();
+        // the same file at different paths on both computers
+        let dir = std::env::temp_dir();
+        let mut paths = Vec::new();
+        for side in ["local", "remote"] {
+            let path = dir.join(format!(
+                "tag_database_sync_{}_{}.jpg",
+                side,
+                std::process::id()
+            ));
+            std::fs::write(&path, "same content").unwrap();
+            paths.push(path.display().to_string());
+        }
+        let mut imgs = Vec::new();
+        for (conn, path) in [(&local, &paths[0]), (&remote, &paths[1])] {
+            for name in ["cat", "dog", "old", "gone", "stale"] {
+                tags::add_tag(name, conn).unwrap();
             }
+            let img = images::add_image("a.jpg", conn).unwrap();
+            tags::add_tag_to_img("cat", img, false, conn).unwrap();
+            imgs.push((img, images::add_image(path, conn).unwrap()));
+        }
+        let (local_img, _) = imgs[0];
+        let (remote_img, remote_file) = imgs[1];
+        tags::add_tag("bird", &local).unwrap();
+        tags::rename_tag(tags::resolve("old", &local).unwrap(), "new", &local).unwrap();
+        tags::remove_tag_from_img(tags::resolve("cat", &local).unwrap(), local_img, &local)
+            .unwrap();
+        tags::add_tag_to_img("stale", local_img, false, &local).unwrap();
+        tags::remove_tag(tags::resolve("gone", &remote).unwrap(), &remote).unwrap();
+        tags::remove_tag(tags::resolve("stale", &remote).unwrap(), &remote).unwrap();
+        tags::add_tag_to_img("dog", remote_img, false, &remote).unwrap();
+        tags::add_tag_to_img("dog", remote_file, false, &remote).unwrap();
+        // the other computer hashes its own files, they don't exist here
+        assert_eq!(sync::hash_images(&remote).unwrap(), 1);
+        std::fs::remove_file(&paths[1]).unwrap();
+        // removed before stale got used on the other computer
+        remote
+            .execute(
+                "UPDATE history SET timestamp=timestamp-100
+                WHERE operation='remove_tag' AND tag_name='stale'",
+                (),
+            )
+            .unwrap();
+
+        let preview = sync::sync(&mut local, &mut remote, true).unwrap();
+        assert!(tags::resolve("bird", &remote).is_none());
+        let report = sync::sync(&mut local, &mut remote, false).unwrap();
+        assert_eq!(preview, report);
+        let SyncReport {
+            local: local_changes,
+            remote: remote_changes,
+            conflicts,
+        } = report;
+        for change in [
+            SyncChange::RemoveTag("gone".to_string()),
+            SyncChange::TagImage {
+                path: "a.jpg".to_string(),
+                tag: "dog".to_string(),
+            },
+            SyncChange::TagImage {
+                path: paths[0].clone(),
+                tag: "dog".to_string(),
+            },
+        ] {
+            assert!(local_changes.contains(&change), "{}", change);
         }
+        for change in [
+            SyncChange::AddTag("bird".to_string()),
+            SyncChange::AddTag("stale".to_string()),
+            SyncChange::RenameTag {
+                from: "old".to_string(),
+                to: "new".to_string(),
+            },
+            SyncChange::UntagImage {
+                path: "a.jpg".to_string(),
+                tag: "cat".to_string(),
+            },
+        ] {
+            assert!(remote_changes.contains(&change), "{}", change);
+        }
+        assert!(!remote_changes
+            .iter()
+            .any(|change| matches!(change, SyncChange::AddImage(_))));
+        assert_eq!(
+            conflicts,
+            vec![Conflict {
+                side: Side::Local,
+                skipped: SyncChange::RemoveTag("stale".to_string()),
+                kind: ConflictKind::ChangedAfterDelete,
+            }]
+        );
+        assert_eq!(tag_names(&local), tag_names(&remote));
+        assert_eq!(
+            tag_names(&local),
+            vec!["bird", "cat", "dog", "new", "stale"]
+        );
+        // nothing left to do
+        let again = sync::sync(&mut local, &mut remote, false).unwrap();
+        assert!(again.local.is_empty() && again.remote.is_empty());
+        let _ = std::fs::remove_file(&paths[0]);
+    }
+}
+
+#[cfg(test)]
+mod timestamp_tests {
+    use super::{images, init_db, tags, utils, ImageId, ListOptions, SortBy};
+    use rusqlite::Connection;
+
+    /// Moves the creation of the image back to the given day
+    fn backdate(img: ImageId, date: &str, conn: &Connection) {
+        let time = utils::parse_date(date).unwrap();
+        conn.execute(
+            "UPDATE images SET created=?1, updated=?1 WHERE id=?2",
+            (time, img),
+        )
+        .unwrap();
+        conn.execute("UPDATE tag_map SET created=?1 WHERE img_id=?2", (time, img))
+            .unwrap();
+    }
+
+    #[test]
+    fn maintained_by_triggers() {
+        let conn = init_db();
+        let im = images::add_image("old.jpg", &conn).unwrap();
+        backdate(im, "2020-01-01", &conn);
+        images::update_path(im, "new.jpg", &conn).unwrap();
+        let image = images::load_images(&[im], &conn).unwrap().pop().unwrap();
+        assert_eq!(image.created, utils::parse_date("2020-01-01"));
+        assert!(image.updated > image.created);
+        tags::add_tag_to_img("a", im, true, &conn).unwrap();
+        let tagged: Option<i64> = conn
+            .query_row("SELECT created FROM tag_map", (), |row| row.get(0))
+            .unwrap();
+        assert!(tagged.is_some());
+    }
+    #[test]
+    fn date_predicates_and_sorting() {
+        let conn = init_db();
+        for (path, date) in [
+            ("a.jpg", "2025-06-01"),
+            ("b.jpg", "2026-01-01"),
+            ("c.jpg", "2026-03-15"),
+        ] {
+            let im = images::add_image(path, &conn).unwrap();
+            tags::add_tag_to_img("all", im, true, &conn).unwrap();
+            backdate(im, date, &conn);
+        }
+        let paths = |query: &str| -> Vec<String> {
+            images::query_sql(query, &conn)
+                .into_iter()
+                .map(|image| image.1)
+                .collect()
+        };
+        assert_eq!(paths("added:>=2026-01-01"), vec!["b.jpg", "c.jpg"]);
+        assert_eq!(paths("all added:2026-01-01"), vec!["b.jpg"]);
+        assert_eq!(paths("tagged:=2025-01-01..2025-12-31"), vec!["a.jpg"]);
+        assert_eq!(paths("updated:<2026-01-01"), vec!["a.jpg"]);
+        let options = ListOptions::default().sorted(SortBy::Tagged, true);
+        let page = images::query_sql_page("all", &options, &conn).unwrap();
+        assert_eq!(page.items[0].1, "c.jpg");
+    }
+    #[test]
+    fn date_only_bounds_cover_the_day() {
+        let conn = init_db();
+        let im = images::add_image("noon.jpg", &conn).unwrap();
+        tags::add_tag_to_img("all", im, true, &conn).unwrap();
+        let noon = utils::parse_date("2026-03-15").unwrap() + 43200;
+        conn.execute("UPDATE images SET created=?1 WHERE id=?2", (noon, im))
+            .unwrap();
+        let matches = |query: &str| !images::query_sql(query, &conn).is_empty();
+        assert!(matches("added:2026-03-15"));
+        assert!(matches("added:=2026-03-15..2026-03-15"));
+        assert!(matches("added:=2026-03-01..2026-03-15"));
+        assert!(matches("added:<=2026-03-15"));
+        assert!(!matches("added:<2026-03-15"));
+        assert!(!matches("added:>2026-03-15"));
+        assert!(matches("added:>2026-03-14"));
+        assert!(matches("added:>=2026-03-15"));
+    }
+}
+
+#[cfg(test)]
+mod wrapper_tests {
+    use crate::{
+        methods::history::Operation,
+        types::{ListOptions, TagId},
+        wrapper::Database,
+    };
+
+    /// Path of a fresh database file in the temp directory
+    fn temp_path(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("tag_database_{}_{}.db", name, std::process::id()));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+        path.display().to_string()
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let db = Database::open(&temp_path("threads"));
+        let handles: Vec<_> = (0..4)
+            .map(|thread| {
+                let db = db.clone();
+                std::thread::spawn(move || {
+                    for i in 0..25 {
+                        let img = db.create_image(&format!("{}_{}.jpg", thread, i)).unwrap();
+                        db.add_tag_to_img(&format!("thread{}", thread), img, true)
+                            .unwrap();
+                        assert_eq!(db.get_tags_of_image(img).len(), 1);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(db.get_tag_orphans().len(), 0);
+        assert_eq!(db.get_tags_with("thread").len(), 4);
+    }
+    #[test]
+    fn backup_and_restore() {
+        let db = Database::open(&temp_path("live"));
+        let img = db.create_image("a.jpg").unwrap();
+        db.add_tag_to_img("cat", img, true).unwrap();
+        let backup = temp_path("backup");
+        let mut steps = 0;
+        db.backup(&backup, |progress| {
+            steps += 1;
+            assert!(progress.remaining <= progress.pagecount);
+        })
+        .unwrap();
+        assert!(steps > 0);
+
+        let dir =
+            std::env::temp_dir().join(format!("tag_database_snapshots_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let snapshots: Vec<_> = (0..3)
+            .map(|_| {
+                std::thread::sleep(std::time::Duration::from_millis(2));
+                db.backup_rotating(dir.to_str().unwrap(), 2, |_| {})
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        assert!(!snapshots[0].exists());
+        assert!(snapshots[2].exists());
+
+        db.add_tag_to_img("dog", img, true).unwrap();
+        let junk = temp_path("junk");
+        std::fs::write(&junk, "not a database").unwrap();
+        assert_eq!(db.restore(&junk, |_| {}), None);
+        assert_eq!(db.get_tags_of_image(img).len(), 2);
+        db.restore(&backup, |_| {}).unwrap();
+        let tags: Vec<String> = db
+            .get_tags_of_image(img)
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+        assert_eq!(tags, vec!["cat"]);
+        // the restored database is still usable
+        db.add_tag_to_img("dog", img, true).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+    #[test]
+    fn in_memory_reads_see_writes() {
+        let db = Database::default();
+        let img = db.create_image("test.jpg").unwrap();
+        assert_eq!(db.get_image_path(img), Some("test.jpg".to_owned()));
+        let other = db.clone();
+        std::thread::spawn(move || other.create_image("test2.jpg").unwrap())
+            .join()
+            .unwrap();
+        assert_eq!(db.get_image_orphans().len(), 2);
+    }
+    #[test]
+    fn returns_structs() {
+        let db = Database::default();
+        let img = db.create_image("test.jpg").unwrap();
+        db.add_tag_to_img("animal", img, true).unwrap();
+        let animal = db.get_tags_with("animal")[0].id;
+        db.create_tags(vec!["cat"]);
+        let cat = db.get_tags_with("cat")[0].id;
+        db.add_tag_parent(animal, cat).unwrap();
+        let ns = db.create_namespace("species").unwrap();
+        db.add_namespace_to_tag(ns, cat).unwrap();
+        db.add_tag_to_img("cat", img, false).unwrap();
+
+        let tag = db.get_tag(cat).unwrap();
+        assert_eq!(tag.name, "cat");
+        assert_eq!(tag.namespace_id, Some(ns));
+        assert_eq!(tag.namespace, Some("species".to_owned()));
+        assert_eq!(tag.parents, vec![animal]);
+        assert_eq!(tag.image_count, 1);
+        assert!(tag.created.is_some());
+
+        let image = db.get_image(img).unwrap();
+        assert_eq!(image.path, "test.jpg");
+        assert_eq!(image.tag_count, 2);
+        assert_eq!(db.get_namespace_of_tag(cat).unwrap().tag_count, 1);
+        assert_eq!(db.get_tag_parents(cat).unwrap()[0].name, "animal");
+        assert!(db.get_tag(TagId(cat.0 + 10)).is_none());
+    }
+    #[test]
+    fn pages_of_structs() {
+        let db = Database::default();
+        for i in 0..5 {
+            let img = db.create_image(&format!("{}.jpg", i)).unwrap();
+            db.add_tag_to_img("all", img, true).unwrap();
+        }
+        let tag = db.get_tags_with("all")[0].id;
+        let first = db
+            .get_images_with_tag_page(tag, &ListOptions::limit(2))
+            .unwrap();
+        assert_eq!(first.items.len(), 2);
+        assert_eq!(first.items[0].tag_count, 1);
+        let second = db
+            .get_images_with_tag_page(tag, &ListOptions::limit(2).after(first.next))
+            .unwrap();
+        assert_eq!(second.items[0].path, "2.jpg");
+    }
+    #[test]
+    fn groups() {
+        let db = Database::default();
+        let first = db.create_image("1.jpg").unwrap();
+        let second = db.create_image("2.jpg").unwrap();
+        let group = db.create_group("holiday").unwrap();
+        assert!(db.create_group("holiday").is_none());
+        db.add_image_to_group(group, first).unwrap();
+        db.add_image_to_group(group, second).unwrap();
+        db.add_image_to_group(group, second).unwrap();
+        assert_eq!(db.get_group(group).unwrap().image_count, 2);
+        assert_eq!(db.get_groups_of_image(first)[0].name, "holiday");
+
+        db.rename_group(group, "trip").unwrap();
+        db.remove_image_from_group(group, first).unwrap();
+        let imgs = db.get_images_of_group(group).unwrap();
+        assert_eq!(imgs.len(), 1);
+        assert_eq!(imgs[0].path, "2.jpg");
+
+        db.delete_group(group).unwrap();
+        assert!(db.get_groups().is_empty());
+        assert!(db.get_groups_of_image(second).is_empty());
+        assert_eq!(db.get_group_history(group).unwrap().len(), 6);
+    }
+
+    #[test]
+    fn smart_groups() {
+        let db = Database::default();
+        let cat = db.create_image("cat.jpg").unwrap();
+        let dog = db.create_image("dog.jpg").unwrap();
+        db.add_tag_to_img("cat", cat, true).unwrap();
+        db.add_tag_to_img("dog", dog, true).unwrap();
+        let live = db.create_smart_group("cats", "cat", false).unwrap();
+        let frozen = db.create_smart_group("frozen", "cat", true).unwrap();
+        // a group can't contain itself
+        let endless = db
+            .create_smart_group("endless", "in:endless", false)
+            .unwrap();
+        assert_eq!(db.get_group(live).unwrap().image_count, 1);
+        assert_eq!(db.get_group(frozen).unwrap().image_count, 1);
+        assert_eq!(db.get_group(endless).unwrap().image_count, 0);
+
+        db.add_tag_to_img("cat", dog, true).unwrap();
+        assert_eq!(db.get_images_of_group(live).unwrap().len(), 2);
+        assert_eq!(db.get_images_of_group(frozen).unwrap().len(), 1);
+        let names: Vec<String> = db
+            .get_groups_of_image(dog)
+            .into_iter()
+            .map(|group| group.name)
+            .collect();
+        assert_eq!(names, vec!["cats"]);
+        db.refresh_groups().unwrap();
+        assert_eq!(db.get_group(frozen).unwrap().image_count, 2);
+
+        let in_group = db
+            .query_images_page("in:cats dog", &ListOptions::default())
+            .unwrap()
+            .items;
+        assert_eq!(in_group.len(), 1);
+        assert_eq!(in_group[0].path, "dog.jpg");
+        assert!(db
+            .query_images_page("in:missing", &ListOptions::default())
+            .unwrap()
+            .items
+            .is_empty());
+
+        // without a query the group keeps its last members
+        db.set_group_query(frozen, None, false).unwrap();
+        db.remove_tag_from_img(db.get_tags_of_image(dog)[0].id, dog)
+            .unwrap();
+        assert_eq!(db.get_group(frozen).unwrap().image_count, 2);
+        let last = db.get_group_history(frozen).unwrap().pop().unwrap();
+        assert_eq!(last.operation, Operation::SetGroupQuery);
+        assert_eq!(last.previous.as_deref(), Some("cat"));
+    }
+}
+
+#[cfg(all(test, feature = "async"))]
+mod async_tests {
+    use crate::{async_wrapper::AsyncDatabase, wrapper::Database};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn mirrors_sync_methods() {
+        let db = AsyncDatabase::default();
+        let img = db.create_image("test.jpg".to_owned()).await.unwrap();
+        db.add_tag_to_img("test".to_owned(), img, true)
+            .await
+            .unwrap();
+        let tags = db.get_tags_of_image(img).await;
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, "test");
+        assert_eq!(db.sync().get_image_path(img), Some("test.jpg".to_owned()));
+    }
+    #[tokio::test]
+    async fn dropping_interrupts_query() {
+        let db = AsyncDatabase::from(Database::default());
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let endless = db.run(move |db| {
+            let res = db.read().unwrap().query_row(
+                "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x+1 FROM c)
+                SELECT count(*) FROM c",
+                (),
+                |row| row.get::<usize, i64>(0),
+            );
+            sender.send(res).unwrap();
+        });
+        assert!(tokio::time::timeout(Duration::from_millis(100), endless)
+            .await
+            .is_err());
+        let res = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(
+            res.unwrap_err().sqlite_error_code(),
+            Some(rusqlite::ErrorCode::OperationInterrupted)
+        );
+        // the connection is usable again afterwards
+        assert!(db.create_image("test.jpg".to_owned()).await.is_some());
+    }
+}
+
+/// Tests every TagStore implementation has to pass
+#[cfg(test)]
+mod conformance {
+    use crate::{store::TagStore, types::TagId};
+
+    fn sorted(mut ids: Vec<TagId>) -> Vec<TagId> {
+        ids.sort();
+        ids
+    }
+
+    pub fn tags(mut store: impl TagStore) {
+        let t1 = store.add_tag("cat").unwrap();
+        let t2 = store.add_tag("Catalog").unwrap();
+        assert_eq!((t1, t2), (TagId(1), TagId(2)));
+        assert_eq!(store.add_tag("cat"), None);
+        assert_eq!(store.get_tag_name(t1), Some("cat".to_owned()));
+        assert_eq!(store.get_tag_id("Catalog"), Some(t2));
+        assert_eq!(
+            store.get_tags_with("CAT"),
+            vec![(t1, "cat".to_owned()), (t2, "Catalog".to_owned())]
+        );
+        assert!(store.get_tags_with("").is_empty());
+        assert_eq!(store.rename_tag(t1, "Catalog"), None);
+        store.rename_tag(t1, "kitten").unwrap();
+        assert_eq!(store.get_tag_id("kitten"), Some(t1));
+        store.remove_tag(t2).unwrap();
+        store.remove_tag(t2).unwrap();
+        assert_eq!(store.get_tag_name(t2), None);
+        assert_eq!(store.get_tag_orphans(), vec![(t1, "kitten".to_owned())]);
+        // ids get reused like SQLite's rowid
+        assert_eq!(store.add_tag("new"), Some(TagId(2)));
+    }
+    pub fn images(mut store: impl TagStore) {
+        let im1 = store.add_image("a.jpg").unwrap();
+        let im2 = store.add_image("b.jpg").unwrap();
+        assert_eq!(store.add_image("a.jpg"), None);
+        assert_eq!(store.get_image_id("b.jpg"), Some(im2));
+        assert_eq!(store.update_image_path(im1, "b.jpg"), None);
+        store.update_image_path(im1, "c.jpg").unwrap();
+        assert_eq!(store.get_image_path(im1), Some("c.jpg".to_owned()));
+        assert_eq!(store.add_tag_to_img("cat", im1, false), None);
+        store.add_tag_to_img("cat", im1, true).unwrap();
+        store.add_tag_to_img("cat", im1, true).unwrap();
+        let cat = store.get_tag_id("cat").unwrap();
+        assert_eq!(store.get_tags_of_img(im1), vec![(cat, "cat".to_owned())]);
+        assert_eq!(
+            store.get_images_with_tag(cat),
+            vec![(im1, "c.jpg".to_owned())]
+        );
+        assert_eq!(store.get_image_orphans(), vec![(im2, "b.jpg".to_owned())]);
+        store.add_tag_to_img("cat", im2, false).unwrap();
+        store.remove_tag_from_img(cat, im1).unwrap();
+        assert!(store.get_tags_of_img(im1).is_empty());
+        store.remove_image(im2).unwrap();
+        assert_eq!(store.get_image_path(im2), None);
+        assert!(store.get_images_with_tag(cat).is_empty());
+        assert_eq!(store.get_tag_orphans(), vec![(cat, "cat".to_owned())]);
+    }
+    pub fn namespaces(mut store: impl TagStore) {
+        let ns1 = store.add_namespace("species").unwrap();
+        let ns2 = store.add_namespace("color").unwrap();
+        assert_eq!(store.add_namespace("color"), None);
+        let cat = store.add_tag("cat").unwrap();
+        let dog = store.add_tag("dog").unwrap();
+        store.add_namespace_to_tag(ns1, cat).unwrap();
+        store.add_namespace_to_tag(ns1, dog).unwrap();
+        assert_eq!(store.add_namespace_to_tag(ns2, cat), None);
+        assert_eq!(store.get_namespace_of_tag(cat), Some(ns1));
+        assert_eq!(
+            store.get_tags_with_namespace(ns1),
+            vec![(cat, "cat".to_owned()), (dog, "dog".to_owned())]
+        );
+        assert_eq!(
+            store.get_namespace_orphans(),
+            vec![(ns2, "color".to_owned())]
+        );
+        assert_eq!(store.rename_namespace(ns1, "color"), None);
+        store.rename_namespace(ns1, "animal").unwrap();
+        assert_eq!(store.get_namespace_name(ns1), Some("animal".to_owned()));
+        store.remove_namespace_from_tag(cat).unwrap();
+        assert_eq!(store.get_namespace_of_tag(cat), None);
+        store.remove_tag(dog).unwrap();
+        assert!(store.get_tags_with_namespace(ns1).is_empty());
+        store.add_namespace_to_tag(ns2, cat).unwrap();
+        store.remove_namespace(ns2).unwrap();
+        assert_eq!(store.get_namespace_name(ns2), None);
+        assert_eq!(store.get_namespace_of_tag(cat), None);
+    }
+    pub fn subtags(mut store: impl TagStore) {
+        let animal = store.add_tag("animal").unwrap();
+        let cat = store.add_tag("cat").unwrap();
+        let kitten = store.add_tag("kitten").unwrap();
+        store.add_parent(animal, cat).unwrap();
+        store.add_parent(cat, kitten).unwrap();
+        assert_eq!(store.add_parent(cat, animal), None);
+        assert_eq!(sorted(store.get_parents(kitten)), vec![animal, cat]);
+        assert_eq!(sorted(store.get_children(animal)), vec![cat, kitten]);
+        let img = store.add_image("a.jpg").unwrap();
+        store.add_tag_to_img("kitten", img, false).unwrap();
+        assert_eq!(store.get_tags_of_img(img).len(), 3);
+        store.remove_connection(kitten, cat).unwrap();
+        store.remove_connection(kitten, cat).unwrap();
+        assert!(store.get_parents(kitten).is_empty());
+        store.remove_tag(cat).unwrap();
+        assert!(store.get_children(animal).is_empty());
+    }
+
+    macro_rules! conformance_tests {
+        ($name:ident, $store:expr) => {
+            mod $name {
+                #[test]
+                fn tags() {
+                    super::tags($store);
+                }
+                #[test]
+                fn images() {
+                    super::images($store);
+                }
+                #[test]
+                fn namespaces() {
+                    super::namespaces($store);
+                }
+                #[test]
+                fn subtags() {
+                    super::subtags($store);
+                }
+            }
+        };
     }
+    conformance_tests!(sqlite, crate::tests::init_db());
+    conformance_tests!(memory, crate::store::memory::MemoryStore::default());
 }
@@ -0,0 +1,731 @@
+//! Structs returned by the [`crate::wrapper::Database`] API.
+//!
+//! With the `serde` feature they implement `Serialize` and `Deserialize`.
+use crate::methods::utils::{format_timestamp, parse_date};
+use rusqlite::{
+    types::{FromSql, FromSqlResult, ToSqlOutput, ValueRef},
+    ToSql,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, fmt, num::ParseIntError, str::FromStr};
+
+/// Generates a newtype around the row id of a table, so ids of different tables can't be mixed
+/// up. They can be used as SQL parameters and read from rows like a plain i64
+macro_rules! id_type {
+    ($($(#[$doc:meta])* $name:ident;)*) => {
+        $(
+            $(#[$doc])*
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+            #[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+            pub struct $name(pub i64);
+            impl From<i64> for $name {
+                fn from(id: i64) -> Self {
+                    $name(id)
+                }
+            }
+            impl From<$name> for i64 {
+                fn from(id: $name) -> Self {
+                    id.0
+                }
+            }
+            impl fmt::Display for $name {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    write!(f, "{}", self.0)
+                }
+            }
+            impl FromStr for $name {
+                type Err = ParseIntError;
+                fn from_str(s: &str) -> Result<Self, Self::Err> {
+                    s.parse().map($name)
+                }
+            }
+            impl ToSql for $name {
+                fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+                    self.0.to_sql()
+                }
+            }
+            impl FromSql for $name {
+                fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+                    i64::column_result(value).map($name)
+                }
+            }
+        )*
+    };
+}
+
+id_type! {
+    /// Id of a row in the tags table
+    TagId;
+    /// Id of a row in the images table
+    ImageId;
+    /// Id of a row in the namespaces table
+    NamespaceId;
+    /// Id of a row in the groups table
+    GroupId;
+    /// Id of a row in the saved_searches table
+    SearchId;
+    /// Id of a row in the rules table
+    RuleId;
+}
+
+/// A tag with its namespace, direct parents and usage count
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Tag {
+    pub id: TagId,
+    pub name: String,
+    pub namespace_id: Option<NamespaceId>,
+    pub namespace: Option<String>,
+    /// Ids of the direct parents
+    pub parents: Vec<TagId>,
+    /// Number of images with this tag
+    pub image_count: i64,
+    /// Unix timestamp of the creation, None for old tags which aren't in the history
+    pub created: Option<i64>,
+    /// Unix timestamp of the last rename, or the creation
+    pub updated: Option<i64>,
+    /// Notes explaining the tag, markdown
+    pub description: Option<String>,
+}
+
+/// A tag in the hierarchy with all of its children. Tags with several parents appear below
+/// each of them
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TagNode {
+    pub id: TagId,
+    pub name: String,
+    pub namespace: Option<String>,
+    /// Number of images with this tag
+    pub image_count: i64,
+    /// Direct children, sorted by name
+    pub children: Vec<TagNode>,
+}
+
+/// An image (or any file) with the number of its tags
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Image {
+    pub id: ImageId,
+    pub path: String,
+    pub tag_count: i64,
+    /// Unix timestamp of the creation, None for old images which aren't in the history
+    pub created: Option<i64>,
+    /// Unix timestamp of the last path change, or the creation
+    pub updated: Option<i64>,
+}
+
+/// A namespace with the number of tags in it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Namespace {
+    pub id: NamespaceId,
+    pub name: String,
+    pub tag_count: i64,
+    /// Unix timestamp of the creation if it is in the history
+    pub created: Option<i64>,
+    /// Notes explaining the namespace, markdown
+    pub description: Option<String>,
+    /// Set if images can only have one tag of the namespace
+    pub exclusive: Option<Exclusivity>,
+}
+/// What happens when an image gets a second tag of an exclusive namespace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Exclusivity {
+    /// The tag the image had gets removed
+    Replace,
+    /// The new tag doesn't get added
+    Refuse,
+}
+impl Exclusivity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Exclusivity::Replace => "replace",
+            Exclusivity::Refuse => "refuse",
+        }
+    }
+}
+impl fmt::Display for Exclusivity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+impl FromStr for Exclusivity {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "replace" => Exclusivity::Replace,
+            "refuse" => Exclusivity::Refuse,
+            _ => return Err(format!("Unknown exclusivity {}", s)),
+        })
+    }
+}
+
+/// A named collection of images, either picked by hand or the results of a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Group {
+    pub id: GroupId,
+    pub name: String,
+    pub image_count: i64,
+    /// Unix timestamp of the creation if it is in the history
+    pub created: Option<i64>,
+    /// Image query deciding the members of a smart group, None for static groups
+    pub query: Option<String>,
+    /// If the members of the smart group are only updated when refreshing it
+    pub materialized: bool,
+}
+
+/// An image query stored under a name, with the order its results get listed in
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SavedSearch {
+    pub id: SearchId,
+    pub name: String,
+    /// Query in the syntax of [`crate::methods::images::query_sql`]
+    pub query: String,
+    pub sort: SortBy,
+    pub descending: bool,
+    /// Unix timestamp of the creation
+    pub created: Option<i64>,
+}
+
+/// A rule about the tags of images, the conditions are tags which all need to be on an image
+/// for the rule to apply
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rule {
+    pub id: RuleId,
+    pub kind: RuleKind,
+    pub conditions: Vec<TagId>,
+    pub target: TagId,
+}
+/// What a rule does with its target tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RuleKind {
+    /// The target gets added to images having all conditions
+    Implies,
+    /// Images having all conditions can't have the target
+    Excludes,
+}
+impl RuleKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleKind::Implies => "implies",
+            RuleKind::Excludes => "excludes",
+        }
+    }
+}
+impl fmt::Display for RuleKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+impl FromStr for RuleKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "implies" => RuleKind::Implies,
+            "excludes" => RuleKind::Excludes,
+            _ => return Err(format!("Unknown rule kind {}", s)),
+        })
+    }
+}
+/// An image breaking a rule, it has all conditions and misses the implied tag or has the
+/// excluded one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Violation {
+    pub rule_id: RuleId,
+    pub img_id: ImageId,
+}
+
+/// Something wrong in the database, found by the integrity check
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Problem {
+    /// Message of SQLite's `PRAGMA integrity_check`
+    Corruption(String),
+    /// A row of the table refers to a row of the parent table which doesn't exist
+    Dangling {
+        table: String,
+        row_id: i64,
+        parent: String,
+    },
+    /// A parent-child connection which is part of a loop
+    Cycle { parent_id: TagId, child_id: TagId },
+    /// An image has a tag but not its parent
+    MissingParent {
+        img_id: ImageId,
+        tag_id: TagId,
+        parent_id: TagId,
+    },
+    /// An image breaks a rule
+    Rule(Violation),
+    /// An image has several tags of an exclusive namespace
+    Exclusive {
+        namespace_id: NamespaceId,
+        img_id: ImageId,
+    },
+}
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Corruption(message) => write!(f, "corruption: {}", message),
+            Problem::Dangling {
+                table,
+                row_id,
+                parent,
+            } => write!(
+                f,
+                "row {} of {} refers to a missing {} row",
+                row_id, table, parent
+            ),
+            Problem::Cycle {
+                parent_id,
+                child_id,
+            } => write!(
+                f,
+                "connection {} -> {} is part of a loop",
+                parent_id, child_id
+            ),
+            Problem::MissingParent {
+                img_id,
+                tag_id,
+                parent_id,
+            } => write!(
+                f,
+                "image {} has tag {} but not its parent {}",
+                img_id, tag_id, parent_id
+            ),
+            Problem::Rule(violation) => write!(
+                f,
+                "image {} breaks rule {}",
+                violation.img_id, violation.rule_id
+            ),
+            Problem::Exclusive {
+                namespace_id,
+                img_id,
+            } => write!(
+                f,
+                "image {} has several tags of exclusive namespace {}",
+                img_id, namespace_id
+            ),
+        }
+    }
+}
+
+/// One of two databases getting synchronized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Side {
+    Local,
+    Remote,
+}
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::Local => write!(f, "local"),
+            Side::Remote => write!(f, "remote"),
+        }
+    }
+}
+
+/// A change synchronizing made to one database, tags are identified by name and images by path
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SyncChange {
+    AddTag(String),
+    RemoveTag(String),
+    RenameTag { from: String, to: String },
+    MergeTag { from: String, to: String },
+    AddImage(String),
+    RemoveImage(String),
+    MoveImage { from: String, to: String },
+    TagImage { path: String, tag: String },
+    UntagImage { path: String, tag: String },
+    AddParent { parent: String, child: String },
+    RemoveParent { parent: String, child: String },
+    SetNamespace { tag: String, namespace: String },
+    RemoveNamespace { tag: String },
+}
+impl fmt::Display for SyncChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncChange::AddTag(name) => write!(f, "add tag '{}'", name),
+            SyncChange::RemoveTag(name) => write!(f, "remove tag '{}'", name),
+            SyncChange::RenameTag { from, to } => write!(f, "rename tag '{}' to '{}'", from, to),
+            SyncChange::MergeTag { from, to } => write!(f, "merge tag '{}' into '{}'", from, to),
+            SyncChange::AddImage(path) => write!(f, "add image '{}'", path),
+            SyncChange::RemoveImage(path) => write!(f, "remove image '{}'", path),
+            SyncChange::MoveImage { from, to } => write!(f, "move image '{}' to '{}'", from, to),
+            SyncChange::TagImage { path, tag } => write!(f, "tag image '{}' with '{}'", path, tag),
+            SyncChange::UntagImage { path, tag } => {
+                write!(f, "remove tag '{}' from image '{}'", tag, path)
+            }
+            SyncChange::AddParent { parent, child } => {
+                write!(f, "add parent '{}' to tag '{}'", parent, child)
+            }
+            SyncChange::RemoveParent { parent, child } => {
+                write!(f, "remove parent '{}' from tag '{}'", parent, child)
+            }
+            SyncChange::SetNamespace { tag, namespace } => {
+                write!(f, "set namespace of tag '{}' to '{}'", tag, namespace)
+            }
+            SyncChange::RemoveNamespace { tag } => write!(f, "remove namespace of tag '{}'", tag),
+        }
+    }
+}
+
+/// Why synchronizing skipped a change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ConflictKind {
+    /// The other database deleted it, but this one changed it afterwards, so it is kept
+    ChangedAfterDelete,
+    /// Both databases renamed the same tag, to different names
+    RenamedDifferently,
+    /// A rule, an exclusive namespace or a loop between tags refused it
+    Refused,
+}
+impl fmt::Display for ConflictKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConflictKind::ChangedAfterDelete => write!(f, "changed after it was deleted"),
+            ConflictKind::RenamedDifferently => write!(f, "renamed differently on both sides"),
+            ConflictKind::Refused => write!(f, "refused by a rule, namespace or loop"),
+        }
+    }
+}
+
+/// A change synchronizing didn't make to a database
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Conflict {
+    /// Database the change was skipped on
+    pub side: Side,
+    pub skipped: SyncChange,
+    pub kind: ConflictKind,
+}
+
+/// What synchronizing changed in each direction
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SyncReport {
+    /// Changes made to the local database
+    pub local: Vec<SyncChange>,
+    /// Changes made to the remote database
+    pub remote: Vec<SyncChange>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// A tag which often appears together with the tags of an image
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Suggestion {
+    pub tag_id: TagId,
+    pub name: String,
+    /// Highest share of the images with one of the tags of the image which also have this tag,
+    /// from 0 to 1
+    pub confidence: f64,
+    /// Highest pointwise mutual information with one of the tags of the image, positive if they
+    /// appear together more often than by chance
+    pub pmi: f64,
+    /// Number of times this tag appears together with the tags of the image
+    pub support: i64,
+}
+
+/// Text format of an exported tag hierarchy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum GraphFormat {
+    /// Graphviz, render with e.g. `dot -Tsvg`
+    #[default]
+    Dot,
+    /// Mermaid flowchart, rendered by e.g. GitHub and GitLab markdown
+    Mermaid,
+}
+impl GraphFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GraphFormat::Dot => "dot",
+            GraphFormat::Mermaid => "mermaid",
+        }
+    }
+}
+impl fmt::Display for GraphFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+impl FromStr for GraphFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "dot" | "graphviz" => GraphFormat::Dot,
+            "mermaid" => GraphFormat::Mermaid,
+            _ => return Err(format!("Unknown graph format {}", s)),
+        })
+    }
+}
+
+/// What an exported tag hierarchy contains. The default is every tag without styling
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GraphOptions {
+    /// Only export this tag and its children
+    pub root: Option<TagId>,
+    /// Fill tags with a color per namespace and add a legend of the namespaces
+    pub color_namespaces: bool,
+    /// Draw tags used on more images larger
+    pub size_by_usage: bool,
+}
+
+/// Value stored with a tag on an image, e.g. the 4 of `rating` or the date of `taken`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TagValue {
+    Int(i64),
+    Float(f64),
+    /// Unix timestamp (seconds, UTC)
+    Date(i64),
+    Text(String),
+}
+impl TagValue {
+    /// Name stored in the value_type column
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            TagValue::Int(_) => "int",
+            TagValue::Float(_) => "float",
+            TagValue::Date(_) => "date",
+            TagValue::Text(_) => "text",
+        }
+    }
+    /// Kinds this value can be compared with, ints and floats compare with each other
+    pub(crate) fn comparable_kinds(&self) -> &'static str {
+        match self {
+            TagValue::Int(_) | TagValue::Float(_) => "'int', 'float'",
+            TagValue::Date(_) => "'date'",
+            TagValue::Text(_) => "'text'",
+        }
+    }
+    /// Reads a value back from the value and value_type columns
+    pub(crate) fn from_columns(kind: &str, value: ValueRef) -> FromSqlResult<Self> {
+        Ok(match kind {
+            "int" => TagValue::Int(value.as_i64()?),
+            "float" => TagValue::Float(value.as_f64()?),
+            "date" => TagValue::Date(value.as_i64()?),
+            _ => TagValue::Text(value.as_str()?.to_owned()),
+        })
+    }
+}
+impl ToSql for TagValue {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match self {
+            TagValue::Int(value) | TagValue::Date(value) => value.to_sql(),
+            TagValue::Float(value) => value.to_sql(),
+            TagValue::Text(value) => value.to_sql(),
+        }
+    }
+}
+impl fmt::Display for TagValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TagValue::Int(value) => write!(f, "{}", value),
+            TagValue::Float(value) => write!(f, "{}", value),
+            TagValue::Date(value) => {
+                let date = format_timestamp(*value);
+                write!(f, "{}", date.strip_suffix(" 00:00:00").unwrap_or(&date))
+            }
+            TagValue::Text(value) => write!(f, "{}", value),
+        }
+    }
+}
+impl FromStr for TagValue {
+    type Err = Infallible;
+    /// Integers, floats and `YYYY-MM-DD [HH:MM:SS]` dates get their type, anything else is text
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(if let Ok(value) = s.parse() {
+            TagValue::Int(value)
+        } else if let Ok(value) = s.parse() {
+            TagValue::Float(value)
+        } else if let Some(value) = parse_date(s) {
+            TagValue::Date(value)
+        } else {
+            TagValue::Text(s.to_owned())
+        })
+    }
+}
+
+/// Condition on the value of a tag
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Comparison {
+    Eq(TagValue),
+    Ne(TagValue),
+    Lt(TagValue),
+    Le(TagValue),
+    Gt(TagValue),
+    Ge(TagValue),
+    /// Inclusive on both ends
+    Between(TagValue, TagValue),
+}
+
+/// What listings get sorted by, ties are broken by id
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SortBy {
+    #[default]
+    Id,
+    /// Name of tags, namespaces and groups, path of images
+    Name,
+    /// Images of a tag, tags of an image or namespace, images of a group
+    Usage,
+    /// Creation time, rows created before the history existed come first
+    Added,
+    /// Time of the last rename or path change, for namespaces and groups of any change
+    Updated,
+    /// Time a tag was last added to the image, for tags the last time they were added to an
+    /// image, for namespaces and groups to any of their tags or images
+    Tagged,
+}
+impl SortBy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortBy::Id => "id",
+            SortBy::Name => "name",
+            SortBy::Usage => "usage",
+            SortBy::Added => "added",
+            SortBy::Updated => "updated",
+            SortBy::Tagged => "tagged",
+        }
+    }
+}
+impl fmt::Display for SortBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+impl FromStr for SortBy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "id" => SortBy::Id,
+            "name" | "path" => SortBy::Name,
+            "usage" => SortBy::Usage,
+            "added" => SortBy::Added,
+            "updated" => SortBy::Updated,
+            "tagged" => SortBy::Tagged,
+            _ => return Err(format!("Unknown sort order {}", s)),
+        })
+    }
+}
+
+/// Position after the last row of a page, continues the listing with the same sort order
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Cursor {
+    pub(crate) key: SortKey,
+    pub(crate) id: i64,
+}
+/// Value a row got sorted by
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) enum SortKey {
+    Int(i64),
+    Text(String),
+}
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.key {
+            SortKey::Int(key) => write!(f, "i{}:{}", self.id, key),
+            SortKey::Text(key) => write!(f, "t{}:{}", self.id, key),
+        }
+    }
+}
+impl FromStr for Cursor {
+    type Err = String;
+    /// Parses cursors written by Display, so they can be handed to users and back
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid cursor {}", s);
+        let (id, key) = s
+            .get(1..)
+            .and_then(|rest| rest.split_once(':'))
+            .ok_or_else(invalid)?;
+        let id = id.parse().map_err(|_| invalid())?;
+        let key = match &s[..1] {
+            "i" => SortKey::Int(key.parse().map_err(|_| invalid())?),
+            "t" => SortKey::Text(key.to_owned()),
+            _ => return Err(invalid()),
+        };
+        Ok(Cursor { key, id })
+    }
+}
+
+/// Sort order, limit and position of a listing. The default lists everything by id
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListOptions {
+    pub sort: SortBy,
+    pub descending: bool,
+    /// Maximum number of rows in the page, None or 0 for no limit
+    pub limit: Option<usize>,
+    /// Rows to skip, after the cursor if there is one
+    pub offset: usize,
+    /// Continue after the last row of a previous page
+    pub after: Option<Cursor>,
+}
+impl ListOptions {
+    /// The first page of the given size, sorted by id
+    pub fn limit(limit: usize) -> Self {
+        ListOptions {
+            limit: Some(limit),
+            ..Default::default()
+        }
+    }
+    /// Same options, sorted by the given order
+    pub fn sorted(self, sort: SortBy, descending: bool) -> Self {
+        ListOptions {
+            sort,
+            descending,
+            ..self
+        }
+    }
+    /// Same options, continuing after the cursor
+    pub fn after(self, cursor: Option<Cursor>) -> Self {
+        ListOptions {
+            after: cursor,
+            ..self
+        }
+    }
+}
+
+/// Part of a listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Continues the listing, None if this is the last page
+    pub next: Option<Cursor>,
+}
+impl<T> Page<T> {
+    /// Converts the items, keeping the cursor
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next: self.next,
+        }
+    }
+}
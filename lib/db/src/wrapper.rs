@@ -1,8 +1,12 @@
-use crate::methods;
+use crate::{
+    methods,
+    pool::{self, ReadManager, Reader},
+    types::Page,
+};
 use rusqlite::Connection;
+use std::sync::{Arc, Mutex, MutexGuard};
 
 #[allow(private_interfaces)]
-
 macro_rules! result_to_option {
     ($res:expr) => {
         match $res {
@@ -15,175 +19,1028 @@ macro_rules! result_to_option {
     };
 }
 
+/// Handle to the tag database which can be cloned and shared between threads.
+///
+/// Reads run concurrently on a pool of read only connections, writes are serialized through a
+/// single writer connection. File databases use WAL mode so readers don't block the writer.
+#[derive(Clone)]
 pub struct Database {
-    db: Connection,
+    writer: Arc<Mutex<Connection>>,
+    /// None for in memory databases, these only exist on the writer connection
+    readers: Option<r2d2::Pool<ReadManager>>,
 }
 
 impl Default for Database {
     fn default() -> Self {
         let db = Connection::open_in_memory().unwrap();
+        methods::init::init_tables(&db).unwrap();
         Database {
-            db: Connection::open_in_memory().unwrap(),
+            writer: Arc::new(Mutex::new(db)),
+            readers: None,
         }
     }
 }
 
 impl Database {
-    /// Create a database with a connection to the given path
+    /// Create a database with a connection to the given path, missing tables get created
     pub fn open(path: &str) -> Self {
-        Database {
-            db: Connection::open(path).unwrap(),
-        }
+        let db = Connection::open(path).unwrap();
+        methods::init::init_tables(&db).unwrap();
+        Database::with_writer(path, db)
     }
     /// Create a database with a connection to the given path, also recreate the database fully
     pub fn create_db(path: &str) -> Self {
+        Database::with_writer(path, methods::init::recreate_db(path.into()))
+    }
+    fn with_writer(path: &str, db: Connection) -> Self {
+        pool::configure(&db).unwrap();
+        db.pragma_update(None, "journal_mode", "WAL").unwrap();
+        let readers = r2d2::Pool::builder()
+            .max_size(pool::READERS)
+            .min_idle(Some(0))
+            .connection_timeout(pool::BUSY_TIMEOUT)
+            .build(ReadManager::new(path))
+            .unwrap();
         Database {
-            db: methods::init::recreate_db(path.into()),
+            writer: Arc::new(Mutex::new(db)),
+            readers: Some(readers),
         }
     }
+    /// Gets a connection for reading, None if no connection of the pool got free within the
+    /// busy timeout
+    pub(crate) fn read(&self) -> Option<Reader<'_>> {
+        let conn = match self.readers {
+            Some(ref readers) => Reader::Pooled(result_to_option!(readers.get())?),
+            None => Reader::Writer(pool::lock(&self.writer)),
+        };
+        pool::register(&conn);
+        Some(conn)
+    }
+    /// Gets the connection for writing, blocks until other writes are done
+    pub(crate) fn write(&self) -> MutexGuard<'_, Connection> {
+        let conn = pool::lock(&self.writer);
+        pool::register(&conn);
+        conn
+    }
+}
+
+/// Gets the ids of (id, name) rows
+fn ids<I: Copy>(rows: &[(I, String)]) -> Vec<I> {
+    rows.iter().map(|row| row.0).collect()
+}
+/// Loads the full rows of a page of (id, name) rows
+fn load_page<I: Copy, T>(
+    page: rusqlite::Result<Page<(I, String)>>,
+    load: impl FnOnce(&[I]) -> rusqlite::Result<Vec<T>>,
+) -> rusqlite::Result<Page<T>> {
+    let page = page?;
+    Ok(Page {
+        items: load(&ids(&page.items))?,
+        next: page.next,
+    })
 }
 
 /// Tag methods for the database
 mod tags {
-    use super::methods::{namespaces, tags};
+    use super::{
+        ids, load_page,
+        methods::{
+            namespaces,
+            tags::{self, Rename},
+        },
+    };
+    use crate::types::{ListOptions, Namespace, NamespaceId, Page, Tag, TagId};
     impl super::Database {
         /// Create tags with the names from the given Vec<&str>
-        pub fn create_tags(&mut self, tags: Vec<&str>) {
-            tags::add_tags(tags, &mut self.db);
+        pub fn create_tags(&self, tags: Vec<&str>) {
+            tags::add_tags(tags, &mut self.write());
         }
         /// Delete all tags via id given in the Vec<i64>
-        pub fn delete_tags(&self, tags: Vec<i64>) -> Option<()> {
-            result_to_option!(tags::remove_tags(tags, &self.db))
+        pub fn delete_tags(&self, tags: Vec<TagId>) -> Option<()> {
+            result_to_option!(tags::remove_tags(tags, &self.write()))
         }
         /// Get the name of the tag with the given id
-        pub fn get_tag_name(&self, tag_id: i64) -> Option<String> {
-            tags::get_name(tag_id, &self.db)
+        pub fn get_tag_name(&self, tag_id: TagId) -> Option<String> {
+            tags::get_name(tag_id, &*self.read()?)
+        }
+        /// Get the tag with the given id
+        pub fn get_tag(&self, tag_id: TagId) -> Option<Tag> {
+            result_to_option!(tags::load_tags(&[tag_id], &*self.read()?))?.pop()
         }
         /// Rename the tag via id
-        pub fn rename_tag(&self, tag_id: i64, new_name: &str) -> Option<()> {
-            result_to_option!(tags::rename_tag(tag_id, new_name, &self.db))
+        pub fn rename_tag(&self, tag_id: TagId, new_name: &str) -> Option<()> {
+            result_to_option!(tags::rename_tag(tag_id, new_name, &self.write()))
+        }
+        /// Merges the source tags into the target, with keep_aliases their names resolve to it
+        pub fn merge_tags(
+            &self,
+            sources: &[TagId],
+            target: TagId,
+            keep_aliases: bool,
+        ) -> Option<()> {
+            result_to_option!(tags::merge_tags(
+                sources,
+                target,
+                keep_aliases,
+                &mut self.write()
+            ))
+        }
+        /// Gets the renames replacing the regex in tag names, optionally only in a namespace
+        pub fn preview_tag_rename(
+            &self,
+            pattern: &str,
+            replacement: &str,
+            namespace: Option<NamespaceId>,
+        ) -> Option<Vec<Rename>> {
+            result_to_option!(tags::preview_rename(
+                pattern,
+                replacement,
+                namespace,
+                &*self.read()?
+            ))
+        }
+        /// Replaces the regex in tag names at once, colliding tags get merged if merge is true
+        /// else nothing changes
+        pub fn rename_tags(
+            &self,
+            pattern: &str,
+            replacement: &str,
+            namespace: Option<NamespaceId>,
+            merge: bool,
+        ) -> Option<Vec<Rename>> {
+            result_to_option!(tags::bulk_rename(
+                pattern,
+                replacement,
+                namespace,
+                merge,
+                &mut self.write()
+            ))
+        }
+        /// Gets the tag with the given name or alias
+        pub fn resolve_tag(&self, name: &str) -> Option<TagId> {
+            tags::resolve(name, &*self.read()?)
+        }
+        /// Lets the name resolve to the tag
+        pub fn add_tag_alias(&self, tag_id: TagId, name: &str) -> Option<()> {
+            result_to_option!(tags::add_alias(tag_id, name, &self.write()))
+        }
+        /// Removes an alias, the tag stays
+        pub fn remove_tag_alias(&self, name: &str) -> Option<()> {
+            result_to_option!(tags::remove_alias(name, &self.write()))
+        }
+        /// Gets the aliases of a tag
+        pub fn get_tag_aliases(&self, tag_id: TagId) -> Option<Vec<String>> {
+            result_to_option!(tags::get_aliases(tag_id, &*self.read()?))
         }
         /// Get all tag ids and names which don't connect to any images
-        pub fn get_tag_orphans(&self) -> Vec<(i64, String)> {
-            tags::get_orphans(&self.db)
+        pub fn get_tag_orphans(&self) -> Vec<Tag> {
+            let Some(conn) = self.read() else {
+                return Vec::new();
+            };
+            result_to_option!(tags::load_tags(&ids(&tags::get_orphans(&conn)), &conn))
+                .unwrap_or_default()
+        }
+        /// Get a page of the tags which don't connect to any images
+        pub fn get_tag_orphans_page(&self, options: &ListOptions) -> Option<Page<Tag>> {
+            let conn = self.read()?;
+            result_to_option!(load_page(tags::get_orphans_page(options, &conn), |ids| {
+                tags::load_tags(ids, &conn)
+            }))
         }
         /// Get all tags which match the given search term
-        pub fn get_tags_with(&self, search_term: &str) -> Vec<(i64, String)> {
-            tags::get_tags_with(search_term, &self.db)
+        pub fn get_tags_with(&self, search_term: &str) -> Vec<Tag> {
+            let Some(conn) = self.read() else {
+                return Vec::new();
+            };
+            let matching = tags::get_tags_with(search_term, &conn);
+            result_to_option!(tags::load_tags(&ids(&matching), &conn)).unwrap_or_default()
+        }
+        /// Get a page of the tags which match the given search term
+        pub fn get_tags_with_page(
+            &self,
+            search_term: &str,
+            options: &ListOptions,
+        ) -> Option<Page<Tag>> {
+            let conn = self.read()?;
+            result_to_option!(load_page(
+                tags::get_tags_with_page(search_term, options, &conn),
+                |ids| tags::load_tags(ids, &conn)
+            ))
+        }
+        /// Sets the description of a tag, None removes it
+        pub fn set_tag_description(&self, tag_id: TagId, description: Option<&str>) -> Option<()> {
+            result_to_option!(tags::set_description(tag_id, description, &self.write()))
+        }
+        /// Gets all tags whose description matches the full text query (SQLite FTS5 syntax)
+        pub fn search_tag_descriptions(&self, query: &str) -> Option<Vec<Tag>> {
+            let conn = self.read()?;
+            let rows = result_to_option!(tags::search_descriptions(query, &conn))?;
+            result_to_option!(tags::load_tags(&ids(&rows), &conn))
+        }
+        /// Gets a page of the tags whose description matches the full text query
+        pub fn search_tag_descriptions_page(
+            &self,
+            query: &str,
+            options: &ListOptions,
+        ) -> Option<Page<Tag>> {
+            let conn = self.read()?;
+            result_to_option!(load_page(
+                tags::search_descriptions_page(query, options, &conn),
+                |ids| tags::load_tags(ids, &conn)
+            ))
+        }
+        /// Passes every tag to f while reading them, for going through the whole library.
+        /// In memory databases only have one connection, which stays locked until this returns,
+        /// so f must not call methods of the database there or it deadlocks
+        pub fn for_each_tag(&self, f: impl FnMut(Tag)) -> Option<()> {
+            result_to_option!(tags::for_each_tag(&*self.read()?, f))
         }
         /// Get the namespace of the tag, return None if it fails
-        pub fn get_namespace_of_tag(&self, tag_id: i64) -> Option<i64> {
-            namespaces::get_namespace_of_tag(tag_id, &self.db)
+        pub fn get_namespace_of_tag(&self, tag_id: TagId) -> Option<Namespace> {
+            let conn = self.read()?;
+            let namespace_id = namespaces::get_namespace_of_tag(tag_id, &conn)?;
+            result_to_option!(namespaces::load_namespaces(&[namespace_id], &conn))?.pop()
         }
     }
 }
 
 /// Image methods of the database
 mod images {
-    use super::methods::{images, tags};
+    use super::{
+        ids, load_page,
+        methods::{
+            images, suggestions,
+            tags::{self, BatchSummary},
+        },
+    };
+    use crate::types::{Image, ImageId, ListOptions, Page, Suggestion, Tag, TagId};
     impl super::Database {
         /// Add a tag to the image, create decides if the tag should get created if it doesn't
         /// exist
-        pub fn add_tag_to_img(&self, tag: &str, img_id: i64, create: bool) -> Option<()> {
-            tags::add_tag_to_img(tag, img_id, create, &self.db)
+        pub fn add_tag_to_img(&self, tag: &str, img_id: ImageId, create: bool) -> Option<()> {
+            tags::add_tag_to_img(tag, img_id, create, &self.write())
+        }
+        /// Suggests tags for the image which often appear together with its tags, best first
+        pub fn suggest_tags(&self, img_id: ImageId, limit: usize) -> Option<Vec<Suggestion>> {
+            result_to_option!(suggestions::suggest_tags(img_id, limit, &*self.read()?))
+        }
+        /// Removes and adds the tags on all images in one transaction, nothing changes if any
+        /// image or (when create is false) any tag to add doesn't exist
+        pub fn batch_tag(
+            &self,
+            imgs: &[ImageId],
+            add: &[&str],
+            remove: &[&str],
+            create: bool,
+        ) -> Option<Vec<BatchSummary>> {
+            result_to_option!(tags::batch_tag(
+                imgs,
+                add,
+                remove,
+                create,
+                &mut self.write()
+            ))
         }
         /// Removes the tag matching the tag_id from the image matching the img_id
-        pub fn remove_tag_from_img(&self, tag_id: i64, img_id: i64) -> Option<()> {
-            result_to_option!(tags::remove_tag_from_img(tag_id, img_id, &self.db))
+        pub fn remove_tag_from_img(&self, tag_id: TagId, img_id: ImageId) -> Option<()> {
+            result_to_option!(tags::remove_tag_from_img(tag_id, img_id, &self.write()))
         }
         /// Creates an image entry with the given path and returns its id
-        pub fn create_image(&self, path: &str) -> Option<i64> {
-            images::add_image(path, &self.db)
+        pub fn create_image(&self, path: &str) -> Option<ImageId> {
+            images::add_image(path, &self.write())
         }
         /// Deletes the image with the path given
         pub fn delete_image(&self, path: &str) -> Option<()> {
-            result_to_option!(images::remove_image_path(path, &self.db))
+            result_to_option!(images::remove_image_path(path, &self.write()))
         }
         /// Gets all images with a given tag
-        pub fn get_images_with_tag(&self, tag_id: i64) -> Option<Vec<(i64, String)>> {
-            result_to_option!(images::get_images_with_tag(tag_id, &self.db))
+        pub fn get_images_with_tag(&self, tag_id: TagId) -> Option<Vec<Image>> {
+            let conn = self.read()?;
+            let matching = result_to_option!(images::get_images_with_tag(tag_id, &conn))?;
+            result_to_option!(images::load_images(&ids(&matching), &conn))
+        }
+        /// Gets a page of the images with a given tag
+        pub fn get_images_with_tag_page(
+            &self,
+            tag_id: TagId,
+            options: &ListOptions,
+        ) -> Option<Page<Image>> {
+            let conn = self.read()?;
+            result_to_option!(load_page(
+                images::get_images_with_tag_page(tag_id, options, &conn),
+                |ids| images::load_images(ids, &conn)
+            ))
         }
         /// Gets all tags of a given image
-        pub fn get_tags_of_image(&self, img_id: i64) -> Vec<(i64, String)> {
-            images::get_tags_of_img(img_id, &self.db)
+        pub fn get_tags_of_image(&self, img_id: ImageId) -> Vec<Tag> {
+            let Some(conn) = self.read() else {
+                return Vec::new();
+            };
+            let tags = images::get_tags_of_img(img_id, &conn);
+            result_to_option!(tags::load_tags(&ids(&tags), &conn)).unwrap_or_default()
+        }
+        /// Gets a page of the tags of a given image
+        pub fn get_tags_of_image_page(
+            &self,
+            img_id: ImageId,
+            options: &ListOptions,
+        ) -> Option<Page<Tag>> {
+            let conn = self.read()?;
+            result_to_option!(load_page(
+                images::get_tags_of_img_page(img_id, options, &conn),
+                |ids| tags::load_tags(ids, &conn)
+            ))
         }
         /// Gets the path of an image via id
-        pub fn get_image_path(&self, img_id: i64) -> Option<String> {
-            images::get_path(img_id, &self.db)
+        pub fn get_image_path(&self, img_id: ImageId) -> Option<String> {
+            images::get_path(img_id, &*self.read()?)
+        }
+        /// Gets the image with the given id
+        pub fn get_image(&self, img_id: ImageId) -> Option<Image> {
+            result_to_option!(images::load_images(&[img_id], &*self.read()?))?.pop()
         }
         /// Replaces the path of an image via id
-        pub fn replace_image_path(&self, img_id: i64, path: &str) -> Option<()> {
-            result_to_option!(images::update_path(img_id, path, &self.db))
+        pub fn replace_image_path(&self, img_id: ImageId, path: &str) -> Option<()> {
+            result_to_option!(images::update_path(img_id, path, &self.write()))
         }
         /// Gets all images without tags associated to them
-        pub fn get_image_orphans(&self) -> Vec<(i64, String)> {
-            images::get_orphans(&self.db)
+        pub fn get_image_orphans(&self) -> Vec<Image> {
+            let Some(conn) = self.read() else {
+                return Vec::new();
+            };
+            result_to_option!(images::load_images(
+                &ids(&images::get_orphans(&conn)),
+                &conn
+            ))
+            .unwrap_or_default()
+        }
+        /// Gets a page of the images without tags associated to them
+        pub fn get_image_orphans_page(&self, options: &ListOptions) -> Option<Page<Image>> {
+            let conn = self.read()?;
+            result_to_option!(load_page(images::get_orphans_page(options, &conn), |ids| {
+                images::load_images(ids, &conn)
+            }))
+        }
+        /// Gets a page of the images which have all known tags of the space separated query
+        pub fn query_images_page(&self, query: &str, options: &ListOptions) -> Option<Page<Image>> {
+            let conn = self.read()?;
+            result_to_option!(load_page(
+                images::query_sql_page(query, options, &conn),
+                |ids| images::load_images(ids, &conn)
+            ))
+        }
+        /// Passes every image to f while reading them, for going through the whole library.
+        /// In memory databases only have one connection, which stays locked until this returns,
+        /// so f must not call methods of the database there or it deadlocks
+        pub fn for_each_image(&self, f: impl FnMut(Image)) -> Option<()> {
+            result_to_option!(images::for_each_image(&*self.read()?, f))
         }
         /// Gets all images matching a complex query
-        pub fn complex_query(&self, query: &str) -> Vec<(i64, String)> {
+        pub fn complex_query(&self, query: &str) -> Vec<Image> {
             todo!()
         }
     }
 }
 
+/// Property methods of the database
+mod properties {
+    use super::{
+        ids, load_page,
+        methods::{images, properties},
+    };
+    use crate::types::{Image, ImageId, ListOptions, Page};
+    impl super::Database {
+        /// Sets a property of the image, replacing the old value of the key
+        pub fn set_property(&self, img_id: ImageId, key: &str, value: &str) -> Option<()> {
+            result_to_option!(properties::set_property(img_id, key, value, &self.write()))
+        }
+        /// Gets the value of a property of the image
+        pub fn get_property(&self, img_id: ImageId, key: &str) -> Option<String> {
+            result_to_option!(properties::get_property(img_id, key, &*self.read()?))?
+        }
+        /// Removes a property from the image
+        pub fn remove_property(&self, img_id: ImageId, key: &str) -> Option<()> {
+            result_to_option!(properties::remove_property(img_id, key, &self.write()))
+        }
+        /// Gets all (key, value) properties of the image sorted by key
+        pub fn get_properties(&self, img_id: ImageId) -> Option<Vec<(String, String)>> {
+            result_to_option!(properties::get_properties(img_id, &*self.read()?))
+        }
+        /// Gets all images where the property has exactly the value
+        pub fn get_images_with_property(&self, key: &str, value: &str) -> Option<Vec<Image>> {
+            let conn = self.read()?;
+            let rows = result_to_option!(properties::get_images_with_property(key, value, &conn))?;
+            result_to_option!(images::load_images(&ids(&rows), &conn))
+        }
+        /// Gets a page of the images where the property has exactly the value
+        pub fn get_images_with_property_page(
+            &self,
+            key: &str,
+            value: &str,
+            options: &ListOptions,
+        ) -> Option<Page<Image>> {
+            let conn = self.read()?;
+            result_to_option!(load_page(
+                properties::get_images_with_property_page(key, value, options, &conn),
+                |ids| images::load_images(ids, &conn)
+            ))
+        }
+        /// Gets all images where the value of the property contains the search term
+        pub fn search_property(&self, key: &str, search_term: &str) -> Option<Vec<Image>> {
+            let conn = self.read()?;
+            let rows = result_to_option!(properties::search_property(key, search_term, &conn))?;
+            result_to_option!(images::load_images(&ids(&rows), &conn))
+        }
+        /// Gets a page of the images where the value of the property contains the search term
+        pub fn search_property_page(
+            &self,
+            key: &str,
+            search_term: &str,
+            options: &ListOptions,
+        ) -> Option<Page<Image>> {
+            let conn = self.read()?;
+            result_to_option!(load_page(
+                properties::search_property_page(key, search_term, options, &conn),
+                |ids| images::load_images(ids, &conn)
+            ))
+        }
+    }
+}
+
+/// Saved search methods of the database
+mod searches {
+    use super::{
+        ids,
+        methods::{images, searches},
+    };
+    use crate::types::{Image, ListOptions, Page, SavedSearch, SearchId, SortBy};
+    impl super::Database {
+        /// Saves an image query under a unique name and returns its id
+        pub fn create_search(
+            &self,
+            name: &str,
+            query: &str,
+            sort: SortBy,
+            descending: bool,
+        ) -> Option<SearchId> {
+            searches::add_search(name, query, sort, descending, &self.write())
+        }
+        /// Renames a saved search
+        pub fn rename_search(&self, search_id: SearchId, new_name: &str) -> Option<()> {
+            result_to_option!(searches::rename_search(search_id, new_name, &self.write()))
+        }
+        /// Changes the query and order of a saved search
+        pub fn update_search(
+            &self,
+            search_id: SearchId,
+            query: &str,
+            sort: SortBy,
+            descending: bool,
+        ) -> Option<()> {
+            result_to_option!(searches::update_search(
+                search_id,
+                query,
+                sort,
+                descending,
+                &self.write()
+            ))
+        }
+        /// Deletes a saved search
+        pub fn delete_search(&self, search_id: SearchId) -> Option<()> {
+            result_to_option!(searches::remove_search(search_id, &self.write()))
+        }
+        /// Gets the saved search with the given id
+        pub fn get_search(&self, search_id: SearchId) -> Option<SavedSearch> {
+            result_to_option!(searches::get_search(search_id, &*self.read()?))?
+        }
+        /// Gets the saved search with the given name
+        pub fn get_search_by_name(&self, name: &str) -> Option<SavedSearch> {
+            result_to_option!(searches::get_search_by_name(name, &*self.read()?))?
+        }
+        /// Gets all saved searches sorted by name
+        pub fn get_searches(&self) -> Option<Vec<SavedSearch>> {
+            result_to_option!(searches::get_searches(&*self.read()?))
+        }
+        /// Gets all images matched by a saved search, in the saved order
+        pub fn run_search(&self, search_id: SearchId) -> Option<Vec<Image>> {
+            let conn = self.read()?;
+            let rows = result_to_option!(searches::run_search(search_id, &conn))??;
+            result_to_option!(images::load_images(&ids(&rows), &conn))
+        }
+        /// Gets a page of the images matched by a saved search, the saved order replaces the one
+        /// of options
+        pub fn run_search_page(
+            &self,
+            search_id: SearchId,
+            options: &ListOptions,
+        ) -> Option<Page<Image>> {
+            let conn = self.read()?;
+            let page = result_to_option!(searches::run_search_page(search_id, options, &conn))??;
+            let items = result_to_option!(images::load_images(&ids(&page.items), &conn))?;
+            Some(Page {
+                items,
+                next: page.next,
+            })
+        }
+    }
+}
+
+/// Tag value methods of the database
+mod values {
+    use super::methods::values;
+    use crate::types::{ImageId, TagId, TagValue};
+    impl super::Database {
+        /// Sets the value of a tag on an image, None removes it. The image needs to have the tag
+        pub fn set_tag_value(
+            &self,
+            tag_id: TagId,
+            img_id: ImageId,
+            value: Option<TagValue>,
+        ) -> Option<()> {
+            result_to_option!(values::set_value(
+                tag_id,
+                img_id,
+                value.as_ref(),
+                &self.write()
+            ))
+        }
+        /// Gets the value of a tag on an image
+        pub fn get_tag_value(&self, tag_id: TagId, img_id: ImageId) -> Option<TagValue> {
+            result_to_option!(values::get_value(tag_id, img_id, &*self.read()?))?
+        }
+        /// Gets the tags of an image which have a value, with their names and values
+        pub fn get_tag_values(&self, img_id: ImageId) -> Option<Vec<(TagId, String, TagValue)>> {
+            result_to_option!(values::get_values_of_img(img_id, &*self.read()?))
+        }
+    }
+}
+
+/// Rule methods of the database
+mod rules {
+    use super::methods::rules;
+    use crate::types::{Rule, RuleId, RuleKind, TagId, Violation};
+    impl super::Database {
+        /// Adds a rule whose conditions imply or exclude the target, None if there are no
+        /// conditions or the target is one of them
+        pub fn create_rule(
+            &self,
+            kind: RuleKind,
+            conditions: &[TagId],
+            target: TagId,
+        ) -> Option<RuleId> {
+            rules::add_rule(kind, conditions, target, &mut self.write())
+        }
+        /// Deletes a rule, tags it added stay
+        pub fn delete_rule(&self, rule_id: RuleId) -> Option<()> {
+            result_to_option!(rules::remove_rule(rule_id, &self.write()))
+        }
+        /// Gets all rules
+        pub fn get_rules(&self) -> Option<Vec<Rule>> {
+            result_to_option!(rules::get_rules(&*self.read()?))
+        }
+        /// Adds the tags implied by rules to every image, returns how many got added
+        pub fn apply_rules(&self) -> Option<usize> {
+            result_to_option!(rules::apply_rules_to_all(&mut self.write()))
+        }
+        /// Gets the images breaking a rule
+        pub fn get_rule_violations(&self) -> Option<Vec<Violation>> {
+            result_to_option!(rules::get_violations(&*self.read()?))
+        }
+    }
+}
+
+/// Backup methods of the database
+mod backup {
+    use super::methods::backup;
+    use rusqlite::backup::Progress;
+    use std::path::{Path, PathBuf};
+    impl super::Database {
+        /// Copies the database to a file while it stays usable, progress gets called after
+        /// every step
+        pub fn backup(&self, path: &str, progress: impl FnMut(Progress)) -> Option<()> {
+            result_to_option!(backup::backup(Path::new(path), progress, &*self.read()?))
+        }
+        /// Copies the database to a new snapshot in the directory and keeps only the newest
+        /// keep snapshots, returns the path of the new one
+        pub fn backup_rotating(
+            &self,
+            dir: &str,
+            keep: usize,
+            progress: impl FnMut(Progress),
+        ) -> Option<PathBuf> {
+            result_to_option!(backup::backup_rotating(
+                Path::new(dir),
+                keep,
+                progress,
+                &*self.read()?
+            ))
+        }
+        /// Replaces the content of the database with a backup, nothing changes if the backup
+        /// isn't a tag database of this or an older version
+        pub fn restore(&self, path: &str, progress: impl FnMut(Progress)) -> Option<()> {
+            result_to_option!(backup::restore(
+                Path::new(path),
+                progress,
+                &mut self.write()
+            ))
+        }
+    }
+}
+
+/// Integrity methods of the database
+mod integrity {
+    use super::methods::integrity;
+    use crate::types::Problem;
+    impl super::Database {
+        /// Finds corruption, rows referring to deleted rows, loops between tags, missing parents
+        /// and images breaking rules or exclusive namespaces
+        pub fn check_integrity(&self) -> Option<Vec<Problem>> {
+            result_to_option!(integrity::check(&*self.read()?))
+        }
+        /// Fixes the problems which can be fixed, returns the ones which are left
+        pub fn repair(&self) -> Option<Vec<Problem>> {
+            result_to_option!(integrity::repair(&mut self.write()))
+        }
+    }
+}
+
+/// Sync methods of the database
+mod sync {
+    use super::methods::sync;
+    use crate::types::SyncReport;
+    use std::path::Path;
+    impl super::Database {
+        /// Makes this database and the one at the path hold the same tags, images and
+        /// connections between them, repeating deletions and renames found in the history of
+        /// either. The other database has to exist. With dry_run nothing changes and the
+        /// report shows what would
+        pub fn sync_with(&self, remote_path: &str, dry_run: bool) -> Option<SyncReport> {
+            let mut remote = result_to_option!(sync::open_remote(Path::new(remote_path)))?;
+            result_to_option!(sync::sync(&mut self.write(), &mut remote, dry_run))
+        }
+        /// Stores the hash of the files of images which don't have one yet, for matching them
+        /// with images of another computer when syncing. Returns how many got hashed
+        pub fn hash_images(&self) -> Option<usize> {
+            result_to_option!(sync::hash_images(&self.write()))
+        }
+    }
+}
+
 /// Subtag methods of the database
 mod subtags {
-    use super::methods::subtags;
+    use super::methods::{graph, subtags, tags};
+    use crate::types::{GraphFormat, GraphOptions, Tag, TagId, TagNode};
     impl super::Database {
         /// Gets all children and the children's children of a tag
-        pub fn get_tag_children(&self, parent_id: i64) -> Option<Vec<i64>> {
-            result_to_option!(subtags::get_children(parent_id, &self.db))
+        pub fn get_tag_children(&self, parent_id: TagId) -> Option<Vec<Tag>> {
+            let conn = self.read()?;
+            let children = result_to_option!(subtags::get_children(parent_id, &conn))?;
+            result_to_option!(tags::load_tags(&children, &conn))
         }
         /// Gets all parents and parent's parents of a tag
-        pub fn get_tag_parents(&self, child_id: i64) -> Option<Vec<i64>> {
-            result_to_option!(subtags::get_parents(child_id, &self.db))
+        pub fn get_tag_parents(&self, child_id: TagId) -> Option<Vec<Tag>> {
+            let conn = self.read()?;
+            let parents = result_to_option!(subtags::get_parents(child_id, &conn))?;
+            result_to_option!(tags::load_tags(&parents, &conn))
+        }
+        /// Gets the tag with everything below it as a tree
+        pub fn get_tag_tree(&self, root: TagId) -> Option<TagNode> {
+            result_to_option!(subtags::get_tree(root, &*self.read()?))
+        }
+        /// Gets a tree for every tag without parents, sorted by name
+        pub fn get_tag_forest(&self) -> Option<Vec<TagNode>> {
+            result_to_option!(subtags::get_forest(&*self.read()?))
+        }
+        /// Gets the tags without parents
+        pub fn get_root_tags(&self) -> Option<Vec<Tag>> {
+            let conn = self.read()?;
+            let roots = result_to_option!(subtags::get_roots(&conn))?;
+            result_to_option!(tags::load_tags(&roots, &conn))
+        }
+        /// Gets the tags without children
+        pub fn get_leaf_tags(&self) -> Option<Vec<Tag>> {
+            let conn = self.read()?;
+            let leaves = result_to_option!(subtags::get_leaves(&conn))?;
+            result_to_option!(tags::load_tags(&leaves, &conn))
+        }
+        /// Gets the length of the longest chain of parents above every tag
+        pub fn get_tag_depths(&self) -> Option<Vec<(TagId, usize)>> {
+            result_to_option!(subtags::get_depths(&*self.read()?))
         }
         /// Adds a parent to a given tag, parent and tag need to exist
-        pub fn add_tag_parent(&self, parent_id: i64, tag_id: i64) -> Option<()> {
-            subtags::parent_tag(parent_id, tag_id, &self.db)
+        pub fn add_tag_parent(&self, parent_id: TagId, tag_id: TagId) -> Option<()> {
+            subtags::parent_tag(parent_id, tag_id, &self.write())
         }
         /// Removes the connection (parent-child / child-parent) from two tags
-        pub fn remove_connection(&self, tag1: i64, tag2: i64) -> Option<()> {
-            subtags::remove_connection(tag1, tag2, &self.db)
+        pub fn remove_connection(&self, tag1: TagId, tag2: TagId) -> Option<()> {
+            subtags::remove_connection(tag1, tag2, &self.write())
+        }
+        /// Renders the tag hierarchy as DOT or Mermaid text
+        pub fn export_tag_hierarchy(
+            &self,
+            format: GraphFormat,
+            options: &GraphOptions,
+        ) -> Option<String> {
+            result_to_option!(graph::export_hierarchy(format, options, &*self.read()?))
         }
     }
 }
 
 /// Namespace methods of the database
 mod namespaces {
-    use super::methods::namespaces;
+    use super::{
+        ids, load_page,
+        methods::{namespaces, tags},
+    };
+    use crate::types::{
+        Exclusivity, ImageId, ListOptions, Namespace, NamespaceId, Page, Tag, TagId,
+    };
     impl super::Database {
         /// Creates a namespace and returns its id
-        pub fn create_namespace(&self, name: &str) -> Option<i64> {
-            namespaces::add_namespace(name, &self.db)
+        pub fn create_namespace(&self, name: &str) -> Option<NamespaceId> {
+            namespaces::add_namespace(name, &self.write())
+        }
+        /// Gets the namespace with the given id
+        pub fn get_namespace(&self, namespace_id: NamespaceId) -> Option<Namespace> {
+            result_to_option!(namespaces::load_namespaces(&[namespace_id], &*self.read()?))?.pop()
         }
         /// Deletes a namespace via id
-        pub fn delete_namespace(&self, namespace_id: i64) -> Option<()> {
-            result_to_option!(namespaces::remove_namespace(namespace_id, &self.db))
+        pub fn delete_namespace(&self, namespace_id: NamespaceId) -> Option<()> {
+            result_to_option!(namespaces::remove_namespace(namespace_id, &self.write()))
         }
         /// Renames a namespace via id
-        pub fn rename_namespace(&self, name: &str, namespace_id: i64) -> Option<()> {
-            result_to_option!(namespaces::rename_namespace(namespace_id, name, &self.db))
+        pub fn rename_namespace(&self, namespace_id: NamespaceId, name: &str) -> Option<()> {
+            result_to_option!(namespaces::rename_namespace(
+                namespace_id,
+                name,
+                &self.write()
+            ))
         }
         /// Adds a namespace to a tag, each tag can only have one namespace
-        pub fn add_namespace_to_tag(&self, namespace_id: i64, tag_id: i64) -> Option<()> {
+        pub fn add_namespace_to_tag(&self, namespace_id: NamespaceId, tag_id: TagId) -> Option<()> {
             result_to_option!(namespaces::add_namespace_to_tag(
                 namespace_id,
                 tag_id,
-                &self.db
+                &self.write()
             ))
         }
         /// Removes the namespace from a tag, each tag only has one namespace so no need to specify
-        pub fn remove_namespace_from_tag(&self, tag_id: i64) -> Option<()> {
-            result_to_option!(namespaces::remove_namespace_from_tag(tag_id, &self.db))
+        pub fn remove_namespace_from_tag(&self, tag_id: TagId) -> Option<()> {
+            result_to_option!(namespaces::remove_namespace_from_tag(tag_id, &self.write()))
         }
         /// Gets tags which have the given namespace
-        pub fn get_tags_with_namespace(&self, namespace_id: i64) -> Vec<(i64, String)> {
-            namespaces::get_tags_with_namespace(namespace_id, &self.db)
+        pub fn get_tags_with_namespace(&self, namespace_id: NamespaceId) -> Vec<Tag> {
+            let Some(conn) = self.read() else {
+                return Vec::new();
+            };
+            let tags = namespaces::get_tags_with_namespace(namespace_id, &conn);
+            result_to_option!(tags::load_tags(&ids(&tags), &conn)).unwrap_or_default()
+        }
+        /// Gets a page of the tags which have the given namespace
+        pub fn get_tags_with_namespace_page(
+            &self,
+            namespace_id: NamespaceId,
+            options: &ListOptions,
+        ) -> Option<Page<Tag>> {
+            let conn = self.read()?;
+            result_to_option!(load_page(
+                namespaces::get_tags_with_namespace_page(namespace_id, options, &conn),
+                |ids| tags::load_tags(ids, &conn)
+            ))
         }
         /// Gets namespaces which don't link to any tags
-        pub fn get_namespace_orphans(&self) -> Vec<(i64, String)> {
-            namespaces::get_orphans(&self.db)
+        pub fn get_namespace_orphans(&self) -> Vec<Namespace> {
+            let Some(conn) = self.read() else {
+                return Vec::new();
+            };
+            let orphans = namespaces::get_orphans(&conn);
+            result_to_option!(namespaces::load_namespaces(&ids(&orphans), &conn))
+                .unwrap_or_default()
+        }
+        /// Gets a page of the namespaces which don't link to any tags
+        pub fn get_namespace_orphans_page(&self, options: &ListOptions) -> Option<Page<Namespace>> {
+            let conn = self.read()?;
+            result_to_option!(load_page(
+                namespaces::get_orphans_page(options, &conn),
+                |ids| { namespaces::load_namespaces(ids, &conn) }
+            ))
+        }
+        /// Makes images able to only have one tag of the namespace, None allows any number
+        pub fn set_namespace_exclusive(
+            &self,
+            namespace_id: NamespaceId,
+            exclusive: Option<Exclusivity>,
+        ) -> Option<()> {
+            result_to_option!(namespaces::set_exclusive(
+                namespace_id,
+                exclusive,
+                &self.write()
+            ))
+        }
+        /// Gets the (namespace, image) pairs where an image has more than one tag of an
+        /// exclusive namespace
+        pub fn get_exclusive_violations(&self) -> Option<Vec<(NamespaceId, ImageId)>> {
+            result_to_option!(namespaces::get_exclusive_violations(&*self.read()?))
+        }
+        /// Sets the description of a namespace, None removes it
+        pub fn set_namespace_description(
+            &self,
+            namespace_id: NamespaceId,
+            description: Option<&str>,
+        ) -> Option<()> {
+            result_to_option!(namespaces::set_description(
+                namespace_id,
+                description,
+                &self.write()
+            ))
+        }
+        /// Gets all namespaces whose description matches the full text query (SQLite FTS5 syntax)
+        pub fn search_namespace_descriptions(&self, query: &str) -> Option<Vec<Namespace>> {
+            let conn = self.read()?;
+            let rows = result_to_option!(namespaces::search_descriptions(query, &conn))?;
+            result_to_option!(namespaces::load_namespaces(&ids(&rows), &conn))
+        }
+        /// Gets a page of the namespaces whose description matches the full text query
+        pub fn search_namespace_descriptions_page(
+            &self,
+            query: &str,
+            options: &ListOptions,
+        ) -> Option<Page<Namespace>> {
+            let conn = self.read()?;
+            result_to_option!(load_page(
+                namespaces::search_descriptions_page(query, options, &conn),
+                |ids| namespaces::load_namespaces(ids, &conn)
+            ))
+        }
+        /// Passes every namespace to f while reading them, for going through the whole library.
+        /// In memory databases only have one connection, which stays locked until this returns,
+        /// so f must not call methods of the database there or it deadlocks
+        pub fn for_each_namespace(&self, f: impl FnMut(Namespace)) -> Option<()> {
+            result_to_option!(namespaces::for_each_namespace(&*self.read()?, f))
+        }
+    }
+}
+
+/// History methods of the database
+mod history {
+    use super::methods::history::{self, HistoryEntry};
+    use crate::types::{GroupId, ImageId, SearchId, TagId};
+    impl super::Database {
+        /// Sets who or what is making the following changes, stored with every history entry
+        pub fn set_actor(&self, actor: Option<&str>) -> Option<()> {
+            result_to_option!(history::set_actor(actor, &self.write()))
+        }
+        /// Gets all changes which affected the image
+        pub fn get_image_history(&self, img_id: ImageId) -> Option<Vec<HistoryEntry>> {
+            result_to_option!(history::get_history_of_image(img_id, &*self.read()?))
+        }
+        /// Gets all changes which affected the tag
+        pub fn get_tag_history(&self, tag_id: TagId) -> Option<Vec<HistoryEntry>> {
+            result_to_option!(history::get_history_of_tag(tag_id, &*self.read()?))
+        }
+        /// Gets all changes which affected the group
+        pub fn get_group_history(&self, group_id: GroupId) -> Option<Vec<HistoryEntry>> {
+            result_to_option!(history::get_history_of_group(group_id, &*self.read()?))
+        }
+        /// Gets all changes which affected the saved search
+        pub fn get_search_history(&self, search_id: SearchId) -> Option<Vec<HistoryEntry>> {
+            result_to_option!(history::get_history_of_search(search_id, &*self.read()?))
+        }
+        /// Gets all changes between the two unix timestamps, `to` is exclusive
+        pub fn get_history_between(&self, from: i64, to: i64) -> Option<Vec<HistoryEntry>> {
+            result_to_option!(history::get_history_between(from, to, &*self.read()?))
+        }
+    }
+}
+
+/// Group methods of the database
+mod groups {
+    use super::{
+        ids, load_page,
+        methods::{groups, images},
+    };
+    use crate::types::{Group, GroupId, Image, ImageId, ListOptions, Page};
+    impl super::Database {
+        /// Creates a group and returns its id
+        pub fn create_group(&self, name: &str) -> Option<GroupId> {
+            groups::add_group(name, &self.write())
+        }
+        /// Creates a smart group holding the images matching the query and returns its id.
+        /// Materialized groups get filled right away and only change when refreshed
+        pub fn create_smart_group(
+            &self,
+            name: &str,
+            query: &str,
+            materialized: bool,
+        ) -> Option<GroupId> {
+            let mut conn = self.write();
+            let id = groups::add_smart_group(name, query, materialized, &conn)?;
+            if materialized {
+                result_to_option!(groups::refresh_group(id, &mut conn))?;
+            }
+            Some(id)
+        }
+        /// Changes the query of a group, None turns it into a normal group
+        pub fn set_group_query(
+            &self,
+            group_id: GroupId,
+            query: Option<&str>,
+            materialized: bool,
+        ) -> Option<()> {
+            result_to_option!(groups::set_group_query(
+                group_id,
+                query,
+                materialized,
+                &self.write()
+            ))
+        }
+        /// Stores the current images matching the query of a smart group
+        pub fn refresh_group(&self, group_id: GroupId) -> Option<()> {
+            result_to_option!(groups::refresh_group(group_id, &mut self.write()))
+        }
+        /// Refreshes all materialized smart groups
+        pub fn refresh_groups(&self) -> Option<()> {
+            result_to_option!(groups::refresh_groups(&mut self.write()))
+        }
+        /// Deletes a group via id, the images stay
+        pub fn delete_group(&self, group_id: GroupId) -> Option<()> {
+            result_to_option!(groups::remove_group(group_id, &self.write()))
+        }
+        /// Renames a group via id
+        pub fn rename_group(&self, group_id: GroupId, name: &str) -> Option<()> {
+            result_to_option!(groups::rename_group(group_id, name, &self.write()))
+        }
+        /// Adds an image to a group
+        pub fn add_image_to_group(&self, group_id: GroupId, img_id: ImageId) -> Option<()> {
+            result_to_option!(groups::add_image_to_group(group_id, img_id, &self.write()))
+        }
+        /// Removes an image from a group
+        pub fn remove_image_from_group(&self, group_id: GroupId, img_id: ImageId) -> Option<()> {
+            result_to_option!(groups::remove_image_from_group(
+                group_id,
+                img_id,
+                &self.write()
+            ))
+        }
+        /// Gets the group with the given id
+        pub fn get_group(&self, group_id: GroupId) -> Option<Group> {
+            result_to_option!(groups::load_groups(&[group_id], &*self.read()?))?.pop()
+        }
+        /// Gets all groups
+        pub fn get_groups(&self) -> Vec<Group> {
+            let Some(conn) = self.read() else {
+                return Vec::new();
+            };
+            result_to_option!(
+                groups::get_groups(&conn).and_then(|all| groups::load_groups(&ids(&all), &conn))
+            )
+            .unwrap_or_default()
+        }
+        /// Gets a page of all groups
+        pub fn get_groups_page(&self, options: &ListOptions) -> Option<Page<Group>> {
+            let conn = self.read()?;
+            result_to_option!(load_page(groups::get_groups_page(options, &conn), |ids| {
+                groups::load_groups(ids, &conn)
+            }))
+        }
+        /// Gets all images in a group
+        pub fn get_images_of_group(&self, group_id: GroupId) -> Option<Vec<Image>> {
+            let conn = self.read()?;
+            let imgs = result_to_option!(groups::get_images_of_group(group_id, &conn))?;
+            result_to_option!(images::load_images(&ids(&imgs), &conn))
+        }
+        /// Gets a page of the images in a group
+        pub fn get_images_of_group_page(
+            &self,
+            group_id: GroupId,
+            options: &ListOptions,
+        ) -> Option<Page<Image>> {
+            let conn = self.read()?;
+            result_to_option!(load_page(
+                groups::get_images_of_group_page(group_id, options, &conn),
+                |ids| images::load_images(ids, &conn)
+            ))
+        }
+        /// Gets all groups an image is in
+        pub fn get_groups_of_image(&self, img_id: ImageId) -> Vec<Group> {
+            let Some(conn) = self.read() else {
+                return Vec::new();
+            };
+            result_to_option!(groups::get_groups_of_image(img_id, &conn)
+                .and_then(|groups| groups::load_groups(&ids(&groups), &conn)))
+            .unwrap_or_default()
+        }
+        /// Gets a page of the groups an image is in
+        pub fn get_groups_of_image_page(
+            &self,
+            img_id: ImageId,
+            options: &ListOptions,
+        ) -> Option<Page<Group>> {
+            let conn = self.read()?;
+            result_to_option!(load_page(
+                groups::get_groups_of_image_page(img_id, options, &conn),
+                |ids| groups::load_groups(ids, &conn)
+            ))
         }
     }
 }